@@ -0,0 +1,448 @@
+use colored::Colorize;
+
+use crate::eval::Flow;
+use crate::parse_input::Node;
+use crate::state::{self, Function, Value};
+
+/// What an option does with a value
+#[derive(Clone, Copy, PartialEq)]
+enum Takes {
+    /// A switch; repeating it counts up
+    Nothing,
+    /// `name=`: a required value
+    Value,
+    /// `name=?`: an optional value, only as `--name=value` or `-nvalue`
+    Optional,
+    /// `name=+`: a required value that may repeat, collected into an array
+    Many,
+}
+
+/// One declared option, e.g. `n/name= Who to greet`
+struct OptionSpec {
+    short: Option<char>,
+    long: Option<String>,
+    takes: Takes,
+    help: String,
+}
+
+impl OptionSpec {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (decl, help) = match text.split_once(char::is_whitespace) {
+            Some((decl, help)) => (decl, help.trim().to_string()),
+            None => (text, String::new()),
+        };
+        let (names, takes) = if let Some(names) = decl.strip_suffix("=?") {
+            (names, Takes::Optional)
+        } else if let Some(names) = decl.strip_suffix("=+") {
+            (names, Takes::Many)
+        } else if let Some(names) = decl.strip_suffix('=') {
+            (names, Takes::Value)
+        } else {
+            (decl, Takes::Nothing)
+        };
+        let (short, long) = match names.split_once('/') {
+            Some((short, long)) => (Some(short), Some(long)),
+            None if names.chars().count() == 1 => (Some(names), None),
+            None => (None, Some(names)),
+        };
+        let short = match short {
+            Some(s) if s.chars().count() == 1 && s != "-" => s.chars().next(),
+            Some(_) => return Err(format!("`{}`: short options are one character", decl)),
+            None => None,
+        };
+        let long = long.map(str::to_string);
+        if long.as_deref().is_some_and(|l| {
+            l.is_empty()
+                || !l
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        }) {
+            return Err(format!("`{}`: invalid option name", decl));
+        }
+        Ok(Self {
+            short,
+            long,
+            takes,
+            help,
+        })
+    }
+
+    /// `flag_name` with dashes turned into underscores
+    fn var(&self) -> String {
+        let name = match (&self.long, self.short) {
+            (Some(long), _) => long.replace('-', "_"),
+            (None, Some(short)) => short.to_string(),
+            (None, None) => String::new(),
+        };
+        format!("flag_{}", name)
+    }
+
+    fn display(&self) -> String {
+        let names = match (self.short, &self.long) {
+            (Some(s), Some(l)) => format!("-{}, --{}", s, l),
+            (Some(s), None) => format!("-{}", s),
+            (None, Some(l)) => format!("    --{}", l),
+            (None, None) => String::new(),
+        };
+        match self.takes {
+            Takes::Nothing => names,
+            Takes::Optional => format!("{}[=VALUE]", names),
+            Takes::Value | Takes::Many => format!("{} <VALUE>", names),
+        }
+    }
+}
+
+/// Everything before the `--` of an `argparse` call
+struct Declaration {
+    name: String,
+    description: Option<String>,
+    min: Option<usize>,
+    max: Option<usize>,
+    options: Vec<OptionSpec>,
+}
+
+fn parse_declaration(args: &[String]) -> Result<(Declaration, Vec<String>), String> {
+    let Some(split) = args.iter().position(|a| a == "--") else {
+        return Err("missing `--` before the arguments to parse".into());
+    };
+    let default_name = state::with(|s| {
+        s.frames
+            .last()
+            .map_or_else(|| s.script_name.clone(), |f| f.name.clone())
+    });
+    let mut decl = Declaration {
+        name: default_name,
+        description: None,
+        min: None,
+        max: None,
+        options: Vec::new(),
+    };
+    let mut specs = args[..split].iter();
+    while let Some(spec) = specs.next() {
+        let mut value = |flag: &str| {
+            specs
+                .next()
+                .cloned()
+                .ok_or_else(|| format!("{}: option requires an argument", flag))
+        };
+        let count = |text: String| {
+            text.parse::<usize>()
+                .map_err(|_| format!("{}: invalid number", text))
+        };
+        match spec.as_str() {
+            "--name" => decl.name = value(spec)?,
+            "--desc" => decl.description = Some(value(spec)?),
+            "--min" => decl.min = Some(count(value(spec)?)?),
+            "--max" => decl.max = Some(count(value(spec)?)?),
+            _ => decl.options.push(OptionSpec::parse(spec)?),
+        }
+    }
+    if !decl
+        .options
+        .iter()
+        .any(|o| o.long.as_deref() == Some("help"))
+    {
+        let short = (!decl.options.iter().any(|o| o.short == Some('h'))).then_some('h');
+        decl.options.push(OptionSpec {
+            short,
+            long: Some("help".into()),
+            takes: Takes::Nothing,
+            help: "Show this help".into(),
+        });
+    }
+    Ok((decl, args[split + 1..].to_vec()))
+}
+
+fn print_help(decl: &Declaration) {
+    let operands = match decl.max {
+        Some(0) => "",
+        _ => " [ARGS...]",
+    };
+    println!("{} {} [OPTIONS]{}", "Usage:".bold(), decl.name, operands);
+    if let Some(description) = &decl.description {
+        println!("\n{}", description);
+    }
+    println!("\n{}", "Options:".bold());
+    let width = decl
+        .options
+        .iter()
+        .map(|o| o.display().chars().count())
+        .max()
+        .unwrap_or(0);
+    for option in &decl.options {
+        let display = option.display();
+        let pad = width - display.chars().count();
+        println!("  {}{}   {}", display.cyan(), " ".repeat(pad), option.help);
+    }
+}
+
+/// Parsed values: the count or values of each option, by index into the declaration
+struct Parsed {
+    values: Vec<Vec<String>>,
+    counts: Vec<usize>,
+    operands: Vec<String>,
+}
+
+fn parse_args(decl: &Declaration, args: &[String]) -> Result<Parsed, String> {
+    let mut parsed = Parsed {
+        values: vec![Vec::new(); decl.options.len()],
+        counts: vec![0; decl.options.len()],
+        operands: Vec::new(),
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        if arg == "--" {
+            parsed.operands.extend(args[i..].iter().cloned());
+            break;
+        }
+        if let Some(body) = arg.strip_prefix("--") {
+            let (name, inline) = match body.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (body, None),
+            };
+            let Some(index) = decl
+                .options
+                .iter()
+                .position(|o| o.long.as_deref() == Some(name))
+            else {
+                return Err(format!("unknown option `--{}`", name));
+            };
+            let option = &decl.options[index];
+            let value = match (option.takes, inline) {
+                (Takes::Nothing, Some(_)) => {
+                    return Err(format!("option `--{}` does not take a value", name));
+                }
+                (Takes::Nothing | Takes::Optional, inline) => inline,
+                (_, Some(value)) => Some(value),
+                (_, None) => {
+                    i += 1;
+                    Some(
+                        args.get(i - 1)
+                            .cloned()
+                            .ok_or_else(|| format!("option `--{}` requires a value", name))?,
+                    )
+                }
+            };
+            record(&mut parsed, index, value);
+            continue;
+        }
+        let Some(group) = arg.strip_prefix('-').filter(|g| !g.is_empty()) else {
+            parsed.operands.push(arg.clone());
+            continue;
+        };
+        let chars: Vec<char> = group.chars().collect();
+        let mut j = 0;
+        while j < chars.len() {
+            let c = chars[j];
+            j += 1;
+            let Some(index) = decl.options.iter().position(|o| o.short == Some(c)) else {
+                return Err(format!("unknown option `-{}`", c));
+            };
+            if decl.options[index].takes == Takes::Nothing {
+                record(&mut parsed, index, None);
+                continue;
+            }
+            // The value is the rest of the group, or the next argument
+            let rest: String = chars[j..].iter().collect();
+            let value = if !rest.is_empty() {
+                Some(rest)
+            } else if decl.options[index].takes == Takes::Optional {
+                None
+            } else {
+                i += 1;
+                Some(
+                    args.get(i - 1)
+                        .cloned()
+                        .ok_or_else(|| format!("option `-{}` requires a value", c))?,
+                )
+            };
+            record(&mut parsed, index, value);
+            break;
+        }
+    }
+    Ok(parsed)
+}
+
+fn check_count(decl: &Declaration, count: usize) -> Result<(), String> {
+    if let Some(min) = decl.min.filter(|&min| count < min) {
+        return Err(format!(
+            "expected at least {} argument(s), got {}",
+            min, count
+        ));
+    }
+    if let Some(max) = decl.max.filter(|&max| count > max) {
+        return Err(format!(
+            "expected at most {} argument(s), got {}",
+            max, count
+        ));
+    }
+    Ok(())
+}
+
+fn record(parsed: &mut Parsed, index: usize, value: Option<String>) {
+    parsed.counts[index] += 1;
+    if let Some(value) = value {
+        parsed.values[index].push(value);
+    }
+}
+
+/// Leaves the caller: returns from a function or sourced file, exits a script
+fn leave(code: i32) -> Flow {
+    let (depth, sourced, interactive) =
+        state::with(|s| (s.frames.len(), s.source_depth, s.interactive));
+    if depth > 0 || sourced > 0 {
+        Flow::Return(code)
+    } else if !interactive {
+        Flow::Exit(code)
+    } else {
+        Flow::Next(code)
+    }
+}
+
+/// `argparse [--name N] [--desc TEXT] [--min N] [--max N] spec... -- "$@"`
+///
+/// Each spec is `[s/]long[=|=?|=+] [help text]`. Options land in `flag_long`
+/// variables (local to the calling function), the remaining arguments become
+/// the new positional parameters, and `--help` prints generated usage.
+pub fn builtin_argparse(args: &[String]) -> Flow {
+    let (decl, rest) = match parse_declaration(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", format!("xinux: argparse: {}", e).red());
+            return Flow::Next(2);
+        }
+    };
+    let usage_error = |e: String| {
+        eprintln!("{}", format!("{}: {}", decl.name, e).red());
+        eprintln!("Try `{} --help` for more information.", decl.name);
+        leave(2)
+    };
+    let parsed = match parse_args(&decl, &rest) {
+        Ok(parsed) => parsed,
+        Err(e) => return usage_error(e),
+    };
+    let help = decl
+        .options
+        .iter()
+        .position(|o| o.long.as_deref() == Some("help"));
+    if help.is_some_and(|i| parsed.counts[i] > 0) {
+        print_help(&decl);
+        return leave(0);
+    }
+    if let Err(e) = check_count(&decl, parsed.operands.len()) {
+        return usage_error(e);
+    }
+
+    let in_function = state::call_depth() > 0;
+    for (i, option) in decl.options.iter().enumerate() {
+        let var = option.var();
+        if in_function {
+            state::make_local(&var);
+        } else {
+            state::unset_var(&var);
+        }
+        if parsed.counts[i] == 0 {
+            continue;
+        }
+        let values = &parsed.values[i];
+        match option.takes {
+            Takes::Nothing => state::set_var(&var, &parsed.counts[i].to_string()),
+            Takes::Many => state::set_value(&var, Value::Array(values.clone())),
+            Takes::Value | Takes::Optional => {
+                state::set_var(&var, values.last().map_or("", String::as_str));
+            }
+        }
+    }
+    state::set_positionals(parsed.operands);
+    Flow::Next(0)
+}
+
+/// Flags declared by `argparse` calls in a function body, for tab completion
+pub fn function_flags(function: &Function) -> Vec<String> {
+    let mut specs = Vec::new();
+    collect_specs(&function.body, &mut specs);
+    let mut flags = Vec::new();
+    for spec in specs {
+        let Ok(option) = OptionSpec::parse(&spec) else {
+            continue;
+        };
+        flags.extend(option.long.map(|l| format!("--{}", l)));
+        flags.extend(option.short.map(|s| format!("-{}", s)));
+    }
+    if !flags.is_empty() && !flags.iter().any(|f| f == "--help") {
+        flags.push("--help".into());
+    }
+    flags
+}
+
+/// Walks the syntax tree for `argparse` commands and gathers their specs
+fn collect_specs(node: &Node, specs: &mut Vec<String>) {
+    match node {
+        Node::Simple(cmd) => {
+            if cmd.words.first().is_none_or(|w| w.text != "argparse") {
+                return;
+            }
+            let mut words = cmd.words[1..]
+                .iter()
+                .map(|w| unquote(&w.text))
+                .take_while(|w| w != "--");
+            while let Some(word) = words.next() {
+                if matches!(word.as_str(), "--name" | "--desc" | "--min" | "--max") {
+                    words.next();
+                } else {
+                    specs.push(word);
+                }
+            }
+        }
+        Node::Pipeline { stages: nodes, .. } | Node::List(nodes) => {
+            for node in nodes {
+                collect_specs(node, specs);
+            }
+        }
+        Node::And(lhs, rhs) | Node::Or(lhs, rhs) => {
+            collect_specs(lhs, specs);
+            collect_specs(rhs, specs);
+        }
+        Node::Group(body)
+        | Node::Subshell(body)
+        | Node::Background { body, .. }
+        | Node::Redirected(body, _)
+        | Node::Time { pipeline: body, .. } => {
+            collect_specs(body, specs);
+        }
+        Node::If {
+            branches,
+            otherwise,
+        } => {
+            for (cond, body) in branches {
+                collect_specs(cond, specs);
+                collect_specs(body, specs);
+            }
+            if let Some(otherwise) = otherwise {
+                collect_specs(otherwise, specs);
+            }
+        }
+        Node::While { cond, body, .. } => {
+            collect_specs(cond, specs);
+            collect_specs(body, specs);
+        }
+        Node::For { body, .. } => collect_specs(body, specs),
+        Node::Case { arms, .. } => {
+            for (_, body) in arms {
+                collect_specs(body, specs);
+            }
+        }
+        Node::FunctionDef { .. } | Node::Cond(_) => {}
+    }
+}
+
+/// Strips the quotes from a literal word without expanding anything
+fn unquote(text: &str) -> String {
+    shell_words::split(text)
+        .ok()
+        .and_then(|words| words.into_iter().next())
+        .unwrap_or_else(|| text.to_string())
+}
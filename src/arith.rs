@@ -0,0 +1,393 @@
+use crate::state;
+
+/// Integer arithmetic for `$(( ... ))`, following the C-like rules of other shells.
+/// Variables may be used bare (`x + 1`) and assigned (`i += 2`, `n++`).
+pub fn evaluate(expr: &str) -> Result<i64, String> {
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+    if parser.tokens.is_empty() {
+        return Ok(0);
+    }
+    let value = parser.assignment()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(value),
+        Some(tok) => Err(format!(
+            "syntax error in expression (error token is \"{}\")",
+            tok.text()
+        )),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Num(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+impl Tok {
+    fn text(&self) -> String {
+        match self {
+            Tok::Num(n) => n.to_string(),
+            Tok::Name(name) => name.clone(),
+            Tok::Op(op) => op.to_string(),
+        }
+    }
+}
+
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "|=", "^=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "?", ":", "=", "(", ")", ",",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Tok>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    'outer: while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '#') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Tok::Num(parse_number(&text)?));
+            continue;
+        }
+        if c == '_' || c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && (chars[i] == '_' || chars[i].is_ascii_alphanumeric()) {
+                i += 1;
+            }
+            tokens.push(Tok::Name(chars[start..i].iter().collect()));
+            continue;
+        }
+        for op in OPERATORS {
+            let len = op.len();
+            if i + len <= chars.len() && chars[i..i + len].iter().copied().eq(op.chars()) {
+                tokens.push(Tok::Op(op));
+                i += len;
+                continue 'outer;
+            }
+        }
+        return Err(format!(
+            "syntax error: invalid arithmetic operator (error token is \"{}\")",
+            c
+        ));
+    }
+    Ok(tokens)
+}
+
+/// Decimal, `0x` hex, leading-zero octal and `base#digits`
+fn parse_number(text: &str) -> Result<i64, String> {
+    let invalid = || format!("{}: value too great for base", text);
+    if let Some((base, digits)) = text.split_once('#') {
+        let base: u32 = base.parse().map_err(|_| invalid())?;
+        if !(2..=36).contains(&base) {
+            return Err(format!("{}: invalid arithmetic base", text));
+        }
+        return i64::from_str_radix(digits, base).map_err(|_| invalid());
+    }
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return i64::from_str_radix(hex, 16).map_err(|_| invalid());
+    }
+    if text.len() > 1 && text.starts_with('0') {
+        return i64::from_str_radix(&text[1..], 8).map_err(|_| invalid());
+    }
+    text.parse().map_err(|_| invalid())
+}
+
+/// Value of a variable used in an expression; non-numeric strings are
+/// themselves evaluated, like bash does.
+fn variable(name: &str, depth: usize) -> Result<i64, String> {
+    let value = state::get_var(name).unwrap_or_default();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    if let Ok(n) = value.parse() {
+        return Ok(n);
+    }
+    if depth > 16 {
+        return Err(format!("{}: expression recursion level exceeded", name));
+    }
+    let tokens = tokenize(value)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth,
+    };
+    parser.assignment()
+}
+
+struct Parser {
+    tokens: Vec<Tok>,
+    pos: usize,
+    /// How many variable values deep this expression is nested
+    depth: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Tok::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(format!("syntax error: `{}` expected", op))
+        }
+    }
+
+    fn assignment(&mut self) -> Result<i64, String> {
+        if let (Some(Tok::Name(name)), Some(Tok::Op(op))) = (
+            self.tokens.get(self.pos).cloned(),
+            self.tokens.get(self.pos + 1).cloned(),
+        ) {
+            let compound = match op {
+                "=" => Some(None),
+                "+=" | "-=" | "*=" | "/=" | "%=" | "<<=" | ">>=" | "&=" | "|=" | "^=" => {
+                    Some(Some(&op[..op.len() - 1]))
+                }
+                _ => None,
+            };
+            if let Some(binary) = compound {
+                self.pos += 2;
+                let rhs = self.assignment()?;
+                let value = match binary {
+                    None => rhs,
+                    Some(op) => apply(op, variable(&name, self.depth + 1)?, rhs)?,
+                };
+                state::set_var(&name, &value.to_string());
+                return Ok(value);
+            }
+        }
+        self.ternary()
+    }
+
+    fn ternary(&mut self) -> Result<i64, String> {
+        let cond = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(cond);
+        }
+        let yes = self.assignment()?;
+        self.expect(":")?;
+        let no = self.assignment()?;
+        Ok(if cond != 0 { yes } else { no })
+    }
+
+    /// Precedence climbing over the binary operators, loosest first
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        const LEVELS: [&[&str]; 10] = [
+            &["||"],
+            &["&&"],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", ">", "<=", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.power();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| LEVELS[level].contains(op)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = apply(op, lhs, rhs)?;
+        }
+        Ok(lhs)
+    }
+
+    fn power(&mut self) -> Result<i64, String> {
+        let base = self.unary()?;
+        if self.eat("**") {
+            let exp = self.power()?;
+            return apply("**", base, exp);
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.peek_op() {
+            Some("-") => {
+                self.pos += 1;
+                Ok(self.unary()?.wrapping_neg())
+            }
+            Some("+") => {
+                self.pos += 1;
+                self.unary()
+            }
+            Some("!") => {
+                self.pos += 1;
+                Ok(i64::from(self.unary()? == 0))
+            }
+            Some("~") => {
+                self.pos += 1;
+                Ok(!self.unary()?)
+            }
+            Some(op @ ("++" | "--")) => {
+                self.pos += 1;
+                let Some(Tok::Name(name)) = self.tokens.get(self.pos).cloned() else {
+                    return Err(format!("syntax error: operand expected after `{}`", op));
+                };
+                self.pos += 1;
+                let delta = if op == "++" { 1 } else { -1 };
+                let value = variable(&name, self.depth + 1)? + delta;
+                state::set_var(&name, &value.to_string());
+                Ok(value)
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Tok::Num(n)) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            Some(Tok::Name(name)) => {
+                self.pos += 1;
+                let value = variable(&name, self.depth + 1)?;
+                for (op, delta) in [("++", 1), ("--", -1)] {
+                    if self.eat(op) {
+                        state::set_var(&name, &(value + delta).to_string());
+                    }
+                }
+                Ok(value)
+            }
+            Some(Tok::Op("(")) => {
+                self.pos += 1;
+                let value = self.assignment()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some(tok) => Err(format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                tok.text()
+            )),
+            None => Err("syntax error: operand expected".into()),
+        }
+    }
+}
+
+fn apply(op: &str, lhs: i64, rhs: i64) -> Result<i64, String> {
+    let value = match op {
+        "||" => i64::from(lhs != 0 || rhs != 0),
+        "&&" => i64::from(lhs != 0 && rhs != 0),
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => i64::from(lhs == rhs),
+        "!=" => i64::from(lhs != rhs),
+        "<" => i64::from(lhs < rhs),
+        ">" => i64::from(lhs > rhs),
+        "<=" => i64::from(lhs <= rhs),
+        ">=" => i64::from(lhs >= rhs),
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err("division by 0".into()),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "**" if rhs < 0 => return Err("exponent less than 0".into()),
+        "**" => lhs.wrapping_pow(rhs.min(u32::MAX as i64) as u32),
+        _ => return Err(format!("unknown operator `{}`", op)),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_operators_follow_c_precedence() {
+        assert_eq!(evaluate("2 + 3 * 4"), Ok(14));
+        assert_eq!(evaluate("(2 + 3) * 4"), Ok(20));
+        assert_eq!(evaluate("10 - 4 - 3"), Ok(3));
+        assert_eq!(evaluate("1 << 2 + 1"), Ok(8));
+        assert_eq!(evaluate("1 | 2 ^ 3 & 1"), Ok(3));
+        assert_eq!(evaluate("1 + 1 == 2 && 3 < 2 || 5"), Ok(1));
+        assert_eq!(evaluate("7 % 4 * 2"), Ok(6));
+    }
+
+    #[test]
+    fn power_is_right_associative_and_below_unary_minus() {
+        assert_eq!(evaluate("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(evaluate("-2 ** 2"), Ok(4));
+        assert_eq!(evaluate("2 * 3 ** 2"), Ok(18));
+        assert_eq!(evaluate("2 ** -1"), Err("exponent less than 0".into()));
+    }
+
+    #[test]
+    fn unary_and_ternary_operators() {
+        assert_eq!(evaluate("!0 + ~0"), Ok(0));
+        assert_eq!(evaluate("1 ? 2 : 3"), Ok(2));
+        assert_eq!(evaluate("0 ? 2 : 1 ? 4 : 5"), Ok(4));
+        assert_eq!(evaluate(""), Ok(0));
+    }
+
+    #[test]
+    fn number_bases() {
+        assert_eq!(evaluate("0x1f + 010 + 2#101"), Ok(31 + 8 + 5));
+        assert!(evaluate("09").is_err());
+        assert!(evaluate("1#1").is_err());
+    }
+
+    #[test]
+    fn variables_are_read_and_assigned() {
+        state::set_var("arith_test_x", "5");
+        assert_eq!(evaluate("arith_test_x * 2"), Ok(10));
+        assert_eq!(evaluate("arith_test_x += 3"), Ok(8));
+        assert_eq!(evaluate("arith_test_x++"), Ok(8));
+        assert_eq!(evaluate("++arith_test_x"), Ok(10));
+        assert_eq!(state::get_var("arith_test_x").as_deref(), Some("10"));
+        // A value that is itself an expression is evaluated
+        state::set_var("arith_test_y", "arith_test_x - 4");
+        assert_eq!(evaluate("arith_test_y * 2"), Ok(12));
+        assert_eq!(evaluate("arith_test_unset + 1"), Ok(1));
+    }
+
+    #[test]
+    fn errors_name_the_problem() {
+        assert_eq!(evaluate("1 / 0"), Err("division by 0".into()));
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("(1 + 2").is_err());
+        assert!(
+            evaluate("1 2")
+                .unwrap_err()
+                .contains("error token is \"2\"")
+        );
+        assert!(evaluate("1 @ 2").is_err());
+    }
+}
@@ -0,0 +1,457 @@
+use std::fs;
+use std::time::Duration;
+
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::eval::{self, Flow, apply_redirects};
+use crate::parse_input::{RedirOp, Redirect, Word, parse};
+use crate::records::{self, Field, Format, Record};
+use crate::timing::{self, Options, format_duration};
+
+/// Measured runs per command when `--runs` is not given
+const DEFAULT_RUNS: usize = 10;
+
+/// How far from the median, in modified z-score, a run counts as an outlier
+const OUTLIER_SCORE: f64 = 3.5;
+
+/// A command to benchmark: how it reads, and how to run it
+pub(crate) type Command<'a> = (String, Box<dyn Fn() -> Flow + 'a>);
+
+/// The measured runs of one command
+struct Sample {
+    command: String,
+    /// Wall-clock seconds of each run
+    times: Vec<f64>,
+    user: Vec<f64>,
+    sys: Vec<f64>,
+    exit_codes: Vec<i32>,
+}
+
+struct Stats {
+    mean: f64,
+    stddev: f64,
+    median: f64,
+    min: f64,
+    max: f64,
+    outliers: usize,
+}
+
+/// What `--export-json` writes
+#[derive(Serialize)]
+struct Results<'a> {
+    results: Vec<Export<'a>>,
+}
+
+/// One command in `--export-json`
+#[derive(Serialize)]
+struct Export<'a> {
+    command: &'a str,
+    mean: f64,
+    stddev: f64,
+    median: f64,
+    min: f64,
+    max: f64,
+    user: f64,
+    system: f64,
+    relative: f64,
+    outliers: usize,
+    times: &'a [f64],
+    exit_codes: &'a [i32],
+}
+
+fn error(message: String) {
+    eprintln!("{}", format!("time: {}", message).red());
+}
+
+/// Runs each command `--warmup` times unmeasured and `--runs` times measured, then
+/// reports the statistics and how the commands rank. Their output is thrown away.
+pub(crate) fn benchmark(options: &Options, commands: &[Command]) -> Flow {
+    let prepare = match options.prepare.as_deref().map(parse) {
+        Some(Ok(node)) => Some(node),
+        Some(Err(e)) => {
+            error(format!("syntax error in `--prepare`: {}", e));
+            return Flow::Next(2);
+        }
+        None => None,
+    };
+    let runs = options.runs.unwrap_or(DEFAULT_RUNS);
+    let pretty = options.format == Format::Pretty;
+
+    let mut samples = Vec::new();
+    let mut stats = Vec::new();
+    let mut status = 0;
+    for (i, (command, run)) in commands.iter().enumerate() {
+        if pretty {
+            println!(
+                "{} {}",
+                format!("Benchmark {}:", i + 1).bold().bright_white(),
+                command
+            );
+        }
+        let mut sample = Sample {
+            command: command.clone(),
+            times: Vec::new(),
+            user: Vec::new(),
+            sys: Vec::new(),
+            exit_codes: Vec::new(),
+        };
+        for n in 0..options.warmup + runs {
+            let silenced = silence();
+            if let Some(prepare) = &prepare {
+                eval::run(prepare);
+            }
+            let (flow, usage) = timing::measure(run);
+            drop(silenced);
+            let Flow::Next(code) = flow else {
+                // `exit` and the like end the benchmark
+                return flow;
+            };
+            if code != 0 && status == 0 {
+                status = code;
+            }
+            if n >= options.warmup {
+                sample.times.push(usage.real.as_secs_f64());
+                sample.user.push(usage.user.as_secs_f64());
+                sample.sys.push(usage.sys.as_secs_f64());
+                sample.exit_codes.push(code);
+            }
+        }
+        let stat = summarize(&sample.times);
+        if pretty {
+            print_sample(&sample, &stat);
+        }
+        samples.push(sample);
+        stats.push(stat);
+    }
+
+    let fastest = (0..stats.len())
+        .min_by(|&a, &b| stats[a].mean.total_cmp(&stats[b].mean))
+        .unwrap_or(0);
+    if pretty {
+        print_summary(&samples, &stats, fastest);
+    } else {
+        let records: Vec<Record> = samples
+            .iter()
+            .zip(&stats)
+            .map(|(sample, stat)| record(sample, stat, &stats[fastest]))
+            .collect();
+        records::print_records(&records, options.format);
+    }
+
+    if let Some(path) = &options.export_json {
+        let results: Vec<Export> = samples
+            .iter()
+            .zip(&stats)
+            .map(|(sample, stat)| Export {
+                command: &sample.command,
+                mean: stat.mean,
+                stddev: stat.stddev,
+                median: stat.median,
+                min: stat.min,
+                max: stat.max,
+                user: mean(&sample.user),
+                system: mean(&sample.sys),
+                relative: ratio(stat, &stats[fastest]).0,
+                outliers: stat.outliers,
+                times: &sample.times,
+                exit_codes: &sample.exit_codes,
+            })
+            .collect();
+        match serde_json::to_string_pretty(&Results { results }) {
+            Ok(json) => export(path, json + "\n"),
+            Err(e) => error(format!("cannot encode results: {}", e)),
+        }
+    }
+    if let Some(path) = &options.export_markdown {
+        export(path, markdown(&samples, &stats, fastest));
+    }
+    Flow::Next(status)
+}
+
+/// Points stdout at `/dev/null` until dropped
+fn silence() -> Option<eval::RedirectGuard> {
+    let target = Word {
+        text: "/dev/null".into(),
+        line: 0,
+        col: 0,
+    };
+    apply_redirects(&[Redirect {
+        fd: 1,
+        op: RedirOp::Out,
+        target,
+    }])
+    .ok()
+}
+
+fn export(path: &str, text: String) {
+    if let Err(e) = fs::write(path, text) {
+        error(format!("cannot write '{}': {}", path, e));
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    match n {
+        0 => 0.0,
+        _ if n % 2 == 1 => sorted[n / 2],
+        _ => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+    }
+}
+
+fn summarize(times: &[f64]) -> Stats {
+    let mut sorted = times.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mean = mean(times);
+    let stddev = if times.len() > 1 {
+        let squares: f64 = times.iter().map(|t| (t - mean).powi(2)).sum();
+        (squares / (times.len() - 1) as f64).sqrt()
+    } else {
+        0.0
+    };
+    let middle = median(&sorted);
+    // Outliers by the modified z-score, which the outliers themselves barely move
+    let mut deviations: Vec<f64> = times.iter().map(|t| (t - middle).abs()).collect();
+    deviations.sort_by(f64::total_cmp);
+    let mad = median(&deviations);
+    let outliers = if mad > 0.0 {
+        times
+            .iter()
+            .filter(|t| (0.6745 * (*t - middle) / mad).abs() > OUTLIER_SCORE)
+            .count()
+    } else {
+        0
+    };
+    Stats {
+        mean,
+        stddev,
+        median: middle,
+        min: sorted.first().copied().unwrap_or(0.0),
+        max: sorted.last().copied().unwrap_or(0.0),
+        outliers,
+    }
+}
+
+/// How many times slower `stat` is than `fastest`, and the uncertainty of that
+fn ratio(stat: &Stats, fastest: &Stats) -> (f64, f64) {
+    if fastest.mean == 0.0 || stat.mean == 0.0 {
+        return (1.0, 0.0);
+    }
+    let ratio = stat.mean / fastest.mean;
+    let spread = ratio
+        * ((stat.stddev / stat.mean).powi(2) + (fastest.stddev / fastest.mean).powi(2)).sqrt();
+    (ratio, spread)
+}
+
+fn seconds(secs: f64) -> String {
+    format_duration(Duration::from_secs_f64(secs.max(0.0)))
+}
+
+fn print_sample(sample: &Sample, stat: &Stats) {
+    println!(
+        "  {}{} ± {}   {}",
+        "Time (mean ± σ):   ".bright_white(),
+        seconds(stat.mean).bold().green(),
+        seconds(stat.stddev).green(),
+        format!(
+            "[User: {}, System: {}]",
+            seconds(mean(&sample.user)),
+            seconds(mean(&sample.sys))
+        )
+        .dimmed()
+    );
+    println!(
+        "  {}{} … {}   median {}   {} runs",
+        "Range (min … max): ".bright_white(),
+        seconds(stat.min).cyan(),
+        seconds(stat.max).purple(),
+        seconds(stat.median).bold(),
+        sample.times.len()
+    );
+    if stat.outliers > 0 {
+        println!(
+            "  {}",
+            format!(
+                "{} of {} runs were outliers; a quieter system or more --warmup may help",
+                stat.outliers,
+                sample.times.len()
+            )
+            .yellow()
+        );
+    }
+    let failed = sample.exit_codes.iter().filter(|&&c| c != 0).count();
+    if failed > 0 {
+        println!(
+            "  {}",
+            format!(
+                "{} of {} runs exited with a non-zero status",
+                failed,
+                sample.times.len()
+            )
+            .red()
+        );
+    }
+    println!();
+}
+
+/// The commands from fastest to slowest, each against the fastest
+fn print_summary(samples: &[Sample], stats: &[Stats], fastest: usize) {
+    if samples.len() < 2 {
+        return;
+    }
+    let mut order: Vec<usize> = (0..samples.len()).collect();
+    order.sort_by(|&a, &b| stats[a].mean.total_cmp(&stats[b].mean));
+    println!("{}", "Summary".bold().bright_white());
+    println!("  {} ran", samples[fastest].command.bold().cyan());
+    for &i in order.iter().filter(|&&i| i != fastest) {
+        let (ratio, spread) = ratio(&stats[i], &stats[fastest]);
+        println!(
+            "    {} ± {} times faster than {}",
+            format!("{:.2}", ratio).bold().green(),
+            format!("{:.2}", spread).green(),
+            samples[i].command.magenta()
+        );
+    }
+}
+
+fn record(sample: &Sample, stat: &Stats, fastest: &Stats) -> Record {
+    let round = |secs: f64| (secs * 1e6).round() / 1e6;
+    let (ratio, _) = ratio(stat, fastest);
+    let failed = sample.exit_codes.iter().filter(|&&c| c != 0).count();
+    Record::new(vec![
+        ("command".to_string(), Field::Text(sample.command.clone())),
+        ("runs".to_string(), Field::Number(sample.times.len() as f64)),
+        ("mean".to_string(), Field::Number(round(stat.mean))),
+        ("stddev".to_string(), Field::Number(round(stat.stddev))),
+        ("median".to_string(), Field::Number(round(stat.median))),
+        ("min".to_string(), Field::Number(round(stat.min))),
+        ("max".to_string(), Field::Number(round(stat.max))),
+        ("user".to_string(), Field::Number(round(mean(&sample.user)))),
+        ("sys".to_string(), Field::Number(round(mean(&sample.sys)))),
+        ("outliers".to_string(), Field::Number(stat.outliers as f64)),
+        ("failed".to_string(), Field::Number(failed as f64)),
+        (
+            "relative".to_string(),
+            Field::Number((ratio * 100.0).round() / 100.0),
+        ),
+    ])
+}
+
+/// A table in the fastest command's unit: seconds, milliseconds or microseconds
+fn markdown(samples: &[Sample], stats: &[Stats], fastest: usize) -> String {
+    let (unit, scale) = match stats[fastest].mean {
+        m if m >= 1.0 => ("s", 1.0),
+        m if m >= 1e-3 => ("ms", 1e3),
+        _ => ("µs", 1e6),
+    };
+    let mut text = format!(
+        "| Command | Mean [{unit}] | Min [{unit}] | Max [{unit}] | Relative |\n\
+         |:---|---:|---:|---:|---:|\n"
+    );
+    for (sample, stat) in samples.iter().zip(stats) {
+        let (ratio, spread) = ratio(stat, &stats[fastest]);
+        let relative = if std::ptr::eq(stat, &stats[fastest]) {
+            "1.00".to_string()
+        } else {
+            format!("{:.2} ± {:.2}", ratio, spread)
+        };
+        text.push_str(&format!(
+            "| `{}` | {:.1} ± {:.1} | {:.1} | {:.1} | {} |\n",
+            sample.command.replace('|', "\\|"),
+            stat.mean * scale,
+            stat.stddev * scale,
+            stat.min * scale,
+            stat.max * scale,
+            relative
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn sample(command: &str, times: &[f64]) -> Sample {
+        Sample {
+            command: command.to_string(),
+            times: times.to_vec(),
+            user: Vec::new(),
+            sys: Vec::new(),
+            exit_codes: vec![0; times.len()],
+        }
+    }
+
+    #[test]
+    fn medians_of_odd_and_even_counts() {
+        assert_eq!(median(&[]), 0.0);
+        assert_eq!(median(&[2.0]), 2.0);
+        assert_eq!(median(&[1.0, 2.0, 9.0]), 2.0);
+        assert_eq!(median(&[1.0, 2.0, 4.0, 9.0]), 3.0);
+    }
+
+    #[test]
+    fn summaries_use_the_sample_deviation() {
+        let stats = summarize(&[4.0, 2.0, 6.0, 8.0]);
+        assert_eq!(stats.mean, 5.0);
+        assert!(close(stats.stddev, (20.0f64 / 3.0).sqrt()));
+        assert_eq!((stats.min, stats.median, stats.max), (2.0, 5.0, 8.0));
+        assert_eq!(stats.outliers, 0);
+        assert_eq!(summarize(&[3.0]).stddev, 0.0);
+        assert_eq!(summarize(&[]).mean, 0.0);
+    }
+
+    #[test]
+    fn outliers_are_far_from_the_median() {
+        let times = [1.0, 1.02, 0.98, 1.01, 0.99, 1.0, 5.0];
+        assert_eq!(summarize(&times).outliers, 1);
+        // With most runs equal there is no spread to measure against
+        assert_eq!(summarize(&[1.0, 1.0, 1.0, 9.0]).outliers, 0);
+    }
+
+    #[test]
+    fn ratios_carry_both_uncertainties() {
+        let fast = summarize(&[1.0, 1.0]);
+        let slow = summarize(&[2.0, 4.0]);
+        let (ratio, spread) = super::ratio(&slow, &fast);
+        assert_eq!(ratio, 3.0);
+        assert!(close(spread, 3.0 * 2f64.sqrt() / 3.0));
+        assert_eq!(super::ratio(&fast, &summarize(&[0.0])), (1.0, 0.0));
+    }
+
+    #[test]
+    fn markdown_tables_use_the_fastest_commands_unit() {
+        let samples = [
+            sample("sleep 0.01", &[0.01, 0.012]),
+            sample("a | b", &[0.5, 0.5]),
+        ];
+        let stats: Vec<Stats> = samples.iter().map(|s| summarize(&s.times)).collect();
+        let table = markdown(&samples, &stats, 0);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines[0],
+            "| Command | Mean [ms] | Min [ms] | Max [ms] | Relative |"
+        );
+        assert_eq!(
+            lines[2],
+            "| `sleep 0.01` | 11.0 ± 1.4 | 10.0 | 12.0 | 1.00 |"
+        );
+        assert!(
+            lines[3].starts_with("| `a \\| b` | 500.0 ± 0.0 |"),
+            "{}",
+            lines[3]
+        );
+        assert!(lines[3].ends_with("| 45.45 ± 5.84 |"), "{}", lines[3]);
+    }
+}
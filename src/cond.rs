@@ -0,0 +1,395 @@
+use std::ffi::CString;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+use colored::Colorize;
+use glob::Pattern;
+use regex::Regex;
+
+use crate::arith;
+use crate::eval::{expand_pattern, expand_quoted_literally};
+use crate::expand::expand_string;
+use crate::parse_input::Word;
+use crate::state::{self, Value};
+
+const UNARY: [&str; 22] = [
+    "-a", "-e", "-f", "-d", "-x", "-r", "-w", "-s", "-L", "-h", "-p", "-S", "-b", "-c", "-g", "-u",
+    "-k", "-O", "-t", "-z", "-n", "-v",
+];
+
+const BINARY: [&str; 15] = [
+    "=", "==", "!=", "<", ">", "=~", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// Evaluates `test`-style expressions for both the `test`/`[` builtins and `[[ ]]`.
+/// In `[[ ]]` mode the arguments are raw words, expanded only when they are used,
+/// so quoting decides what is an operator and what is matched literally.
+struct Cond<'a> {
+    args: &'a [String],
+    pos: usize,
+    extended: bool,
+    /// Parsing the unneeded side of `&&`/`||`: nothing is expanded or tested
+    skipping: usize,
+}
+
+impl Cond<'_> {
+    fn remaining(&self) -> usize {
+        self.args.len() - self.pos
+    }
+
+    fn is(&self, offset: usize, op: &str) -> bool {
+        self.args.get(self.pos + offset).is_some_and(|a| a == op)
+    }
+
+    fn is_binary(&self, offset: usize) -> bool {
+        self.args
+            .get(self.pos + offset)
+            .is_some_and(|a| BINARY.contains(&a.as_str()) && (a != "=~" || self.extended))
+    }
+
+    fn value(&self, index: usize) -> String {
+        if self.skipping > 0 {
+            String::new()
+        } else if self.extended {
+            expand_string(&self.args[index])
+        } else {
+            self.args[index].clone()
+        }
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let or = if self.extended { "||" } else { "-o" };
+        let mut result = self.and()?;
+        while self.is(0, or) {
+            self.pos += 1;
+            let rhs = self.short_circuit(result, Self::and)?;
+            result = result || rhs;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let and = if self.extended { "&&" } else { "-a" };
+        let mut result = self.not()?;
+        while self.is(0, and) {
+            self.pos += 1;
+            let rhs = self.short_circuit(!result, Self::not)?;
+            result = result && rhs;
+        }
+        Ok(result)
+    }
+
+    /// Parses the right side of `&&`/`||`, evaluating it only when it can matter
+    fn short_circuit(
+        &mut self,
+        decided: bool,
+        parse: fn(&mut Self) -> Result<bool, String>,
+    ) -> Result<bool, String> {
+        if !decided {
+            return parse(self);
+        }
+        self.skipping += 1;
+        let result = parse(self);
+        self.skipping -= 1;
+        result
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        if self.is(0, "!") && !(self.remaining() >= 3 && self.is_binary(1)) && self.remaining() > 1
+        {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let Some(first) = self.args.get(self.pos) else {
+            return Err("argument expected".into());
+        };
+        if self.remaining() >= 3 && self.is_binary(1) {
+            let index = self.pos;
+            self.pos += 3;
+            return self.binary(index);
+        }
+        if first == "(" && self.remaining() > 1 {
+            self.pos += 1;
+            let result = self.or()?;
+            if !self.is(0, ")") {
+                return Err("`)` expected".into());
+            }
+            self.pos += 1;
+            return Ok(result);
+        }
+        if UNARY.contains(&first.as_str()) && self.remaining() >= 2 {
+            let op = first.clone();
+            let operand = self.value(self.pos + 1);
+            self.pos += 2;
+            if self.skipping > 0 {
+                return Ok(false);
+            }
+            return unary(&op, &operand);
+        }
+        if self.extended && (first.starts_with('-') && first.len() == 2) && self.remaining() >= 2 {
+            return Err(format!("{}: unary operator expected", first));
+        }
+        let value = self.value(self.pos);
+        self.pos += 1;
+        Ok(!value.is_empty())
+    }
+
+    fn binary(&self, index: usize) -> Result<bool, String> {
+        if self.skipping > 0 {
+            return Ok(false);
+        }
+        let op = self.args[index + 1].as_str();
+        let lhs = self.value(index);
+        let rhs_raw = &self.args[index + 2];
+        match op {
+            "=" | "==" | "!=" => {
+                let equal = if self.extended {
+                    let pattern = expand_pattern(rhs_raw);
+                    Pattern::new(&pattern).map_or(lhs == pattern, |p| p.matches(&lhs))
+                } else {
+                    lhs == *rhs_raw
+                };
+                Ok(equal == (op != "!="))
+            }
+            "=~" => regex_match(&lhs, rhs_raw),
+            "<" => Ok(lhs < self.value(index + 2)),
+            ">" => Ok(lhs > self.value(index + 2)),
+            "-nt" | "-ot" | "-ef" => {
+                let (a, b) = (
+                    fs::metadata(&lhs).ok(),
+                    fs::metadata(self.value(index + 2)).ok(),
+                );
+                Ok(match op {
+                    "-nt" => a.is_some_and(|a| b.is_none_or(|b| mtime(&a) > mtime(&b))),
+                    "-ot" => b.is_some_and(|b| a.is_none_or(|a| mtime(&a) < mtime(&b))),
+                    _ => a
+                        .zip(b)
+                        .is_some_and(|(a, b)| a.dev() == b.dev() && a.ino() == b.ino()),
+                })
+            }
+            _ => {
+                let rhs = self.value(index + 2);
+                let (a, b) = (self.integer(&lhs)?, self.integer(&rhs)?);
+                Ok(match op {
+                    "-eq" => a == b,
+                    "-ne" => a != b,
+                    "-lt" => a < b,
+                    "-le" => a <= b,
+                    "-gt" => a > b,
+                    _ => a >= b,
+                })
+            }
+        }
+    }
+
+    /// `[[ ]]` evaluates integer operands arithmetically, `test` wants plain numbers
+    fn integer(&self, text: &str) -> Result<i64, String> {
+        if self.extended {
+            arith::evaluate(text)
+        } else {
+            text.trim()
+                .parse()
+                .map_err(|_| format!("{}: integer expression expected", text))
+        }
+    }
+}
+
+/// Modification time in nanoseconds, for `-nt` and `-ot`
+fn mtime(meta: &Metadata) -> i128 {
+    i128::from(meta.mtime()) * 1_000_000_000 + i128::from(meta.mtime_nsec())
+}
+
+/// Checks access(2) permissions for the current user
+fn access(path: &str, mode: libc::c_int) -> bool {
+    let Ok(path) = CString::new(path) else {
+        return false;
+    };
+    // SAFETY: `path` is a valid NUL-terminated string.
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+fn unary(op: &str, operand: &str) -> Result<bool, String> {
+    let meta = || fs::metadata(operand).ok();
+    let mode = |bit: u32| meta().is_some_and(|m| m.mode() & bit != 0);
+    Ok(match op {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-v" => state::get_value(operand).is_some(),
+        "-e" | "-a" => meta().is_some(),
+        "-f" => meta().is_some_and(|m| m.is_file()),
+        "-d" => meta().is_some_and(|m| m.is_dir()),
+        "-s" => meta().is_some_and(|m| m.len() > 0),
+        "-p" => meta().is_some_and(|m| m.file_type().is_fifo()),
+        "-S" => meta().is_some_and(|m| m.file_type().is_socket()),
+        "-b" => meta().is_some_and(|m| m.file_type().is_block_device()),
+        "-c" => meta().is_some_and(|m| m.file_type().is_char_device()),
+        "-L" | "-h" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        "-r" => access(operand, libc::R_OK),
+        "-w" => access(operand, libc::W_OK),
+        "-x" => access(operand, libc::X_OK),
+        "-u" => mode(0o4000),
+        "-g" => mode(0o2000),
+        "-k" => mode(0o1000),
+        // SAFETY: geteuid has no preconditions.
+        "-O" => meta().is_some_and(|m| m.uid() == unsafe { libc::geteuid() }),
+        "-t" => {
+            let fd: i32 = operand
+                .trim()
+                .parse()
+                .map_err(|_| format!("{}: integer expression expected", operand))?;
+            // SAFETY: isatty only inspects the descriptor.
+            unsafe { libc::isatty(fd) == 1 }
+        }
+        _ => return Err(format!("{}: unary operator expected", op)),
+    })
+}
+
+/// `=~`: quoted parts of the regex match literally; captures land in `MATCHES`
+fn regex_match(subject: &str, raw: &str) -> Result<bool, String> {
+    let pattern = expand_quoted_literally(raw, regex::escape);
+    let re =
+        Regex::new(&pattern).map_err(|_| format!("{}: invalid regular expression", pattern))?;
+    match re.captures(subject) {
+        Some(caps) => {
+            let groups = caps
+                .iter()
+                .map(|m| m.map_or(String::new(), |m| m.as_str().to_string()))
+                .collect();
+            state::set_value("MATCHES", Value::Array(groups));
+            Ok(true)
+        }
+        None => {
+            state::set_value("MATCHES", Value::Array(Vec::new()));
+            Ok(false)
+        }
+    }
+}
+
+fn evaluate(args: &[String], extended: bool) -> Result<bool, String> {
+    if args.is_empty() {
+        return Ok(false);
+    }
+    let mut cond = Cond {
+        args,
+        pos: 0,
+        extended,
+        skipping: 0,
+    };
+    let result = cond.or()?;
+    match args.get(cond.pos) {
+        None => Ok(result),
+        Some(extra) => Err(format!("{}: unexpected argument", extra)),
+    }
+}
+
+fn report(name: &str, message: String) -> i32 {
+    eprintln!("{}", format!("xinux: {}: {}", name, message).red());
+    2
+}
+
+/// `test expr` and `[ expr ]`: 0 when true, 1 when false, 2 on a malformed expression
+pub fn builtin_test(args: &[String], bracket: bool) -> i32 {
+    let name = if bracket { "[" } else { "test" };
+    let args = if bracket {
+        match args.split_last() {
+            Some((last, rest)) if last == "]" => rest,
+            _ => return report(name, "missing `]`".into()),
+        }
+    } else {
+        args
+    };
+    match evaluate(args, false) {
+        Ok(result) => i32::from(!result),
+        Err(e) => report(name, e),
+    }
+}
+
+/// `[[ expr ]]` with `==` glob patterns, `=~` regexes, `&&`, `||` and `< >`
+pub fn run_extended(words: &[Word]) -> i32 {
+    let args: Vec<String> = words.iter().map(|w| w.text.clone()).collect();
+    match evaluate(&args, true) {
+        Ok(result) => i32::from(!result),
+        Err(e) => report("[[", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(args: &[&str]) -> Result<bool, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        evaluate(&args, false)
+    }
+
+    /// `[[ ]]` from raw words, quotes and all
+    fn extended(args: &[&str]) -> Result<bool, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        evaluate(&args, true)
+    }
+
+    fn matches() -> Vec<String> {
+        match state::get_value("MATCHES") {
+            Some(Value::Array(groups)) => groups,
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn regex_captures_land_in_matches() {
+        assert_eq!(
+            extended(&["v1.22", "=~", "^v([0-9]+)\\.([0-9]+)$"]),
+            Ok(true)
+        );
+        assert_eq!(matches(), ["v1.22", "1", "22"]);
+        assert_eq!(extended(&["v1", "=~", "^x"]), Ok(false));
+        assert!(matches().is_empty());
+    }
+
+    #[test]
+    fn quoted_regex_parts_match_literally() {
+        assert_eq!(extended(&["abc", "=~", "a.c"]), Ok(true));
+        assert_eq!(extended(&["abc", "=~", "'a.c'"]), Ok(false));
+        assert_eq!(extended(&["a.c", "=~", "\"a.c\""]), Ok(true));
+        assert_eq!(extended(&["a.cd", "=~", "^'a.c'd$"]), Ok(true));
+        assert!(extended(&["a", "=~", "("]).is_err());
+    }
+
+    #[test]
+    fn regex_is_only_an_operator_in_double_brackets() {
+        assert!(test(&["abc", "=~", "a"]).is_err());
+    }
+
+    #[test]
+    fn double_bracket_equality_globs_unless_quoted() {
+        assert_eq!(extended(&["main.rs", "==", "*.rs"]), Ok(true));
+        assert_eq!(extended(&["main.rs", "==", "'*.rs'"]), Ok(false));
+        assert_eq!(extended(&["*.rs", "==", "'*.rs'"]), Ok(true));
+        assert_eq!(extended(&["a", "!=", "b*"]), Ok(true));
+        // `test` compares strings as they are
+        assert_eq!(test(&["main.rs", "=", "*.rs"]), Ok(false));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(test(&["a", "-o", "", "-a", ""]), Ok(true));
+        assert_eq!(extended(&["''", "||", "a", "&&", "''"]), Ok(false));
+        assert_eq!(test(&["!", "", "-a", "(", "x", "-o", "", ")"]), Ok(true));
+    }
+
+    #[test]
+    fn integers_and_strings() {
+        assert_eq!(test(&["10", "-gt", "9"]), Ok(true));
+        assert_eq!(test(&["10", "<", "9"]), Ok(true));
+        assert!(test(&["1+1", "-eq", "2"]).is_err());
+        assert_eq!(extended(&["1+1", "-eq", "2"]), Ok(true));
+        assert_eq!(test(&["-z", ""]), Ok(true));
+        assert_eq!(test(&["-n", ""]), Ok(false));
+        assert_eq!(test(&[]), Ok(false));
+        assert!(test(&["a", "b"]).is_err());
+    }
+}
@@ -0,0 +1,461 @@
+use std::cell::RefCell;
+
+use colored::Colorize;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+
+use crate::arith;
+use crate::eval::{Flow, run};
+use crate::expand::expand_string;
+use crate::parse_input::parse;
+use crate::state::{self, Value};
+
+/// When to stop next
+#[derive(Clone, Copy)]
+enum Mode {
+    /// Before the very next command
+    Step,
+    /// Before the next line at this call depth or shallower (steps over calls)
+    Next(usize),
+    /// Only at breakpoints
+    Continue,
+}
+
+#[derive(Debug, PartialEq)]
+enum Breakpoint {
+    Line(usize),
+    Function(String),
+}
+
+/// A line typed at the `(xdb)` prompt, with its argument
+#[derive(Debug, PartialEq)]
+enum Command<'a> {
+    Step,
+    Next,
+    Finish,
+    Continue,
+    Quit,
+    Break(&'a str),
+    Delete(&'a str),
+    /// The line to list around, if one was given
+    List(Option<usize>),
+    Print(&'a str),
+    Vars,
+    Args,
+    Backtrace,
+    Eval(&'a str),
+    Help,
+    Nothing,
+    Unknown(&'a str),
+}
+
+/// What the user asked for at the `(xdb)` prompt
+enum Action {
+    Resume(Mode),
+    Quit,
+}
+
+struct Debugger {
+    file: String,
+    lines: Vec<String>,
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    editor: DefaultEditor,
+    last_command: String,
+    /// Line and call depth of the last stop, so a line with several commands stops once
+    last_stop: Option<(usize, usize)>,
+    /// Set on entering a function that has a breakpoint
+    pending_function: Option<String>,
+    /// `source` depth of the script being debugged; sourced files are not listed
+    script_depth: Option<usize>,
+}
+
+thread_local! {
+    static DEBUGGER: RefCell<Option<Debugger>> = const { RefCell::new(None) };
+}
+
+const HELP: &str = "\
+Commands:
+  s, step              run to the next command, entering functions
+  n, next              run to the next line, stepping over function calls
+  finish               run until the current function returns
+  c, continue          run to the next breakpoint
+  b, break LINE|FUNC   set a breakpoint (no argument lists them)
+  d, delete [N]        delete breakpoint N, or all of them
+  l, list [LINE]       show the source around the current line
+  p, print EXPR        print a variable, a $-expansion or an arithmetic expression
+  vars                 list shell variables
+  args                 show the positional parameters
+  bt, where            show the call stack
+  e, eval COMMAND      run a command in the script's shell
+  q, quit              stop the script
+An empty line repeats the last command.";
+
+/// Debugs a script: stops before its first command and then wherever asked
+pub fn start(file: &str, source: &str) {
+    let editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("xinux: --debug: {}", e);
+            return;
+        }
+    };
+    println!(
+        "{} debugging {} — type {} for commands",
+        "xdb".cyan().bold(),
+        file.bold(),
+        "help".bold()
+    );
+    DEBUGGER.with(|d| {
+        *d.borrow_mut() = Some(Debugger {
+            file: file.to_string(),
+            lines: source.lines().map(str::to_string).collect(),
+            breakpoints: Vec::new(),
+            mode: Mode::Step,
+            editor,
+            last_command: String::new(),
+            last_stop: None,
+            pending_function: None,
+            script_depth: None,
+        })
+    });
+}
+
+/// Called when a function is entered, to honor function breakpoints
+pub fn enter_function(name: &str) {
+    DEBUGGER.with(|d| {
+        if let Some(dbg) = d.borrow_mut().as_mut()
+            && dbg
+                .breakpoints
+                .contains(&Breakpoint::Function(name.to_string()))
+        {
+            dbg.pending_function = Some(name.to_string());
+        }
+    });
+}
+
+/// Called before every simple command. While the prompt is open the debugger is
+/// taken out of its slot, so commands run from the prompt are not debugged themselves.
+pub fn before_command(line: usize) -> Option<Flow> {
+    let mut dbg = DEBUGGER.with(|d| d.borrow_mut().take())?;
+    let flow = dbg.check(line);
+    DEBUGGER.with(|d| *d.borrow_mut() = Some(dbg));
+    flow
+}
+
+impl Debugger {
+    fn check(&mut self, line: usize) -> Option<Flow> {
+        let depth = state::call_depth();
+        let source_depth = state::with(|s| s.source_depth);
+        if source_depth != *self.script_depth.get_or_insert(source_depth) {
+            return None;
+        }
+        if self.last_stop.is_some_and(|stop| stop != (line, depth)) {
+            self.last_stop = None;
+        }
+        let same_line = self.last_stop.is_some();
+
+        let reason = if let Some(name) = self.pending_function.take() {
+            Some(format!("breakpoint in `{}`", name))
+        } else if !same_line && self.breakpoints.contains(&Breakpoint::Line(line)) {
+            Some("breakpoint".to_string())
+        } else {
+            match self.mode {
+                Mode::Step => Some("step".to_string()),
+                Mode::Next(max) if depth <= max && !same_line => Some("next".to_string()),
+                _ => None,
+            }
+        }?;
+
+        self.show_location(line, &reason);
+        match self.prompt(line, depth) {
+            Action::Resume(mode) => {
+                self.mode = mode;
+                self.last_stop = Some((line, depth));
+                None
+            }
+            Action::Quit => {
+                self.mode = Mode::Continue;
+                self.breakpoints.clear();
+                Some(Flow::Exit(1))
+            }
+        }
+    }
+
+    fn show_location(&self, line: usize, reason: &str) {
+        let function = state::with(|s| s.frames.last().map(|f| f.name.clone()));
+        let place = match function {
+            Some(name) => format!("{}:{} in {}()", self.file, line, name),
+            None => format!("{}:{}", self.file, line),
+        };
+        println!("{} {} ({})", "→".cyan().bold(), place.bold(), reason);
+        self.list(line, 0, line);
+    }
+
+    /// Prints the lines within `radius` of `center`, marking the `current` one
+    fn list(&self, center: usize, radius: usize, current: usize) {
+        let first = center.saturating_sub(radius).max(1);
+        let last = (center + radius).min(self.lines.len());
+        for number in first..=last {
+            let text = &self.lines[number - 1];
+            let marker = if number == current { "→" } else { " " };
+            let dot = if self.breakpoints.contains(&Breakpoint::Line(number)) {
+                "●".red().to_string()
+            } else {
+                " ".to_string()
+            };
+            let row = format!("{}{} {:>4} │ {}", dot, marker, number, text);
+            if number == current {
+                println!("{}", row.bold());
+            } else {
+                println!("{}", row);
+            }
+        }
+    }
+
+    fn prompt(&mut self, line: usize, depth: usize) -> Action {
+        loop {
+            let input = match self.editor.readline("(xdb) ") {
+                Ok(input) => input,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(_) => return Action::Quit,
+            };
+            let input = if input.trim().is_empty() {
+                self.last_command.clone()
+            } else {
+                let _ = self.editor.add_history_entry(input.as_str());
+                self.last_command = input.clone();
+                input
+            };
+            match parse_command(&input) {
+                Command::Nothing => {}
+                Command::Step => return Action::Resume(Mode::Step),
+                Command::Next => return Action::Resume(Mode::Next(depth)),
+                Command::Finish if depth == 0 => println!("not inside a function"),
+                Command::Finish => return Action::Resume(Mode::Next(depth - 1)),
+                Command::Continue => return Action::Resume(Mode::Continue),
+                Command::Quit => return Action::Quit,
+                Command::Break(arg) => self.set_breakpoint(arg),
+                Command::Delete(arg) => self.delete_breakpoint(arg),
+                Command::List(center) => self.list(center.unwrap_or(line), 5, line),
+                Command::Print(expr) => print_expression(expr),
+                Command::Vars => print_variables(),
+                Command::Args => print_args(),
+                Command::Backtrace => self.backtrace(line),
+                Command::Eval(src) => eval_command(src),
+                Command::Help => println!("{}", HELP),
+                Command::Unknown(command) => {
+                    println!("unknown command `{}`; type `help`", command)
+                }
+            }
+        }
+    }
+
+    fn set_breakpoint(&mut self, arg: &str) {
+        if arg.is_empty() {
+            if self.breakpoints.is_empty() {
+                println!("no breakpoints");
+            }
+            for (i, bp) in self.breakpoints.iter().enumerate() {
+                match bp {
+                    Breakpoint::Line(n) => println!("{}: line {}", i + 1, n),
+                    Breakpoint::Function(name) => println!("{}: function {}", i + 1, name),
+                }
+            }
+            return;
+        }
+        let bp = match parse_breakpoint(arg, self.lines.len()) {
+            Ok(bp) => bp,
+            Err(n) => {
+                println!(
+                    "line {} is outside {} ({} lines)",
+                    n,
+                    self.file,
+                    self.lines.len()
+                );
+                return;
+            }
+        };
+        if !self.breakpoints.contains(&bp) {
+            self.breakpoints.push(bp);
+        }
+        println!("breakpoint {} set", self.breakpoints.len());
+    }
+
+    fn delete_breakpoint(&mut self, arg: &str) {
+        if arg.is_empty() {
+            self.breakpoints.clear();
+            println!("all breakpoints deleted");
+            return;
+        }
+        match arg.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= self.breakpoints.len() => {
+                self.breakpoints.remove(n - 1);
+            }
+            _ => println!("no breakpoint {}", arg),
+        }
+    }
+
+    fn backtrace(&self, line: usize) {
+        let frames: Vec<(String, Vec<String>, usize)> = state::with(|s| {
+            s.frames
+                .iter()
+                .rev()
+                .map(|f| (f.name.clone(), f.args.clone(), f.call_line))
+                .collect()
+        });
+        // Each frame is at the line its callee was called from
+        let mut at = line;
+        for (i, (name, args, call_line)) in frames.iter().enumerate() {
+            println!(
+                "#{} {}({}) at line {}",
+                i,
+                name,
+                shell_words::join(args),
+                at
+            );
+            at = *call_line;
+        }
+        println!("#{} {} at line {}", frames.len(), self.file, at);
+    }
+}
+
+/// Splits a prompt line into its command and argument; commands have short forms
+fn parse_command(input: &str) -> Command<'_> {
+    let input = input.trim();
+    let (command, arg) = match input.split_once(char::is_whitespace) {
+        Some((command, arg)) => (command, arg.trim()),
+        None => (input, ""),
+    };
+    match command {
+        "" => Command::Nothing,
+        "s" | "step" => Command::Step,
+        "n" | "next" => Command::Next,
+        "finish" => Command::Finish,
+        "c" | "continue" => Command::Continue,
+        "q" | "quit" => Command::Quit,
+        "b" | "break" => Command::Break(arg),
+        "d" | "delete" => Command::Delete(arg),
+        "l" | "list" => Command::List(arg.parse().ok()),
+        "p" | "print" => Command::Print(arg),
+        "vars" => Command::Vars,
+        "args" => Command::Args,
+        "bt" | "where" | "backtrace" => Command::Backtrace,
+        "e" | "eval" => Command::Eval(arg),
+        "h" | "help" => Command::Help,
+        _ => Command::Unknown(command),
+    }
+}
+
+/// A line number within the script's `lines`, or else a function name; the
+/// error is a line number past the end
+fn parse_breakpoint(arg: &str, lines: usize) -> Result<Breakpoint, usize> {
+    match arg.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= lines => Ok(Breakpoint::Line(n)),
+        Ok(n) => Err(n),
+        Err(_) => Ok(Breakpoint::Function(arg.to_string())),
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Str(s) => shell_words::quote(s).into_owned(),
+        Value::Array(items) => format!("({})", shell_words::join(items)),
+    }
+}
+
+/// `p NAME` shows a variable, `p "$x-$y"` expands, anything else is arithmetic
+fn print_expression(expr: &str) {
+    if expr.is_empty() {
+        println!("usage: print NAME | $EXPANSION | ARITHMETIC");
+    } else if state::is_valid_name(expr) {
+        match state::get_value(expr) {
+            Some(value) => println!("{} = {}", expr, format_value(&value)),
+            None => println!("{} is unset", expr),
+        }
+    } else if expr.contains('$') || expr.starts_with(['"', '\'']) {
+        println!("{}", expand_string(expr));
+    } else {
+        match arith::evaluate(expr) {
+            Ok(n) => println!("{}", n),
+            Err(e) => println!("{}", e.red()),
+        }
+    }
+}
+
+fn print_variables() {
+    let mut vars: Vec<(String, Value)> = state::with(|s| s.vars.clone().into_iter().collect());
+    vars.sort_by(|a, b| a.0.cmp(&b.0));
+    if vars.is_empty() {
+        println!("no shell variables");
+    }
+    for (name, value) in vars {
+        println!("{} = {}", name, format_value(&value));
+    }
+}
+
+fn print_args() {
+    println!("$0 = {}", state::with(|s| s.script_name.clone()));
+    let args = state::positionals();
+    if args.is_empty() {
+        println!("no positional parameters");
+    }
+    for (i, arg) in args.iter().enumerate() {
+        println!("${} = {}", i + 1, shell_words::quote(arg));
+    }
+}
+
+fn eval_command(src: &str) {
+    match parse(src) {
+        Ok(program) => {
+            let (status, line) = state::with(|s| (s.status, s.lineno));
+            let code = run(&program).code();
+            if code != 0 {
+                println!("{}", format!("exit status {}", code).yellow());
+            }
+            state::with(|s| {
+                s.status = status;
+                s.lineno = line;
+            });
+        }
+        Err(e) => println!("{}", format!("syntax error: {}", e).red()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_have_short_and_long_forms() {
+        assert_eq!(parse_command("s"), Command::Step);
+        assert_eq!(parse_command("  next "), Command::Next);
+        assert_eq!(parse_command("bt"), Command::Backtrace);
+        assert_eq!(parse_command("where"), Command::Backtrace);
+        assert_eq!(parse_command(""), Command::Nothing);
+        assert_eq!(parse_command("jump 3"), Command::Unknown("jump"));
+    }
+
+    #[test]
+    fn the_argument_is_the_rest_of_the_line() {
+        assert_eq!(parse_command("p  $a  $b "), Command::Print("$a  $b"));
+        assert_eq!(
+            parse_command("eval echo hi; x=1"),
+            Command::Eval("echo hi; x=1")
+        );
+        assert_eq!(parse_command("b\tgreet"), Command::Break("greet"));
+        assert_eq!(parse_command("l 12"), Command::List(Some(12)));
+        assert_eq!(parse_command("list here"), Command::List(None));
+    }
+
+    #[test]
+    fn breakpoints_are_lines_in_range_or_function_names() {
+        assert_eq!(parse_breakpoint("3", 10), Ok(Breakpoint::Line(3)));
+        assert_eq!(
+            parse_breakpoint("main", 10),
+            Ok(Breakpoint::Function("main".into()))
+        );
+        assert_eq!(parse_breakpoint("0", 10), Err(0));
+        assert_eq!(parse_breakpoint("11", 10), Err(11));
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use sysinfo::{Disks, Pid, System};
+
+use crate::ls::format_size;
+
+const UNKNOWN: &str = "Unknown";
+
+/// Firmware placeholders that say nothing about the machine
+const PLACEHOLDERS: [&str; 8] = [
+    "to be filled by o.e.m.",
+    "default string",
+    "system product name",
+    "system manufacturer",
+    "not applicable",
+    "not specified",
+    "o.e.m.",
+    "none",
+];
+
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    let text = fs::read_to_string(path).ok()?;
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    (!text.is_empty() && !PLACEHOLDERS.contains(&text.to_lowercase().as_str()))
+        .then(|| text.to_string())
+}
+
+/// Vendor and model from DMI, the board when the product is blank, or the device tree
+pub fn machine() -> String {
+    let dmi = |name: &str| read_trimmed(Path::new("/sys/class/dmi/id").join(name));
+    let product = match (dmi("sys_vendor"), dmi("product_name")) {
+        (Some(vendor), Some(product)) if !product.starts_with(&vendor) => {
+            Some(format!("{} {}", vendor, product))
+        }
+        (_, Some(product)) => Some(product),
+        _ => None,
+    };
+    let product = product.map(|p| match dmi("product_version") {
+        Some(version) if !p.contains(&version) => format!("{} ({})", p, version),
+        _ => p,
+    });
+    let board = || match (dmi("board_vendor"), dmi("board_name")) {
+        (Some(vendor), Some(board)) => Some(format!("{} {}", vendor, board)),
+        (_, board) => board,
+    };
+    product
+        .or_else(board)
+        .or_else(|| read_trimmed("/sys/firmware/devicetree/base/model"))
+        .unwrap_or_else(|| UNKNOWN.into())
+}
+
+/// The preferred mode of every connected display
+pub fn resolution() -> String {
+    let Ok(connectors) = fs::read_dir("/sys/class/drm") else {
+        return UNKNOWN.into();
+    };
+    let mut modes: Vec<(String, String)> = connectors
+        .flatten()
+        .filter(|c| read_trimmed(c.path().join("status")).as_deref() == Some("connected"))
+        .filter_map(|c| {
+            let modes = fs::read_to_string(c.path().join("modes")).ok()?;
+            let first = modes.lines().next()?.trim().to_string();
+            Some((c.file_name().to_string_lossy().into_owned(), first))
+        })
+        .filter(|(_, mode)| !mode.is_empty())
+        .collect();
+    modes.sort();
+    if modes.is_empty() {
+        return UNKNOWN.into();
+    }
+    modes
+        .into_iter()
+        .map(|(_, mode)| mode)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `XDG_CURRENT_DESKTOP` without its `X-` or distribution prefix, else the session name
+pub fn desktop() -> String {
+    let from_env = |name: &str| env::var(name).ok().filter(|v| !v.trim().is_empty());
+    if let Some(current) = from_env("XDG_CURRENT_DESKTOP") {
+        let name = current
+            .rsplit(':')
+            .find(|p| !p.is_empty())
+            .unwrap_or(&current);
+        return name.trim_start_matches("X-").to_string();
+    }
+    ["DESKTOP_SESSION", "XDG_SESSION_DESKTOP", "GDMSESSION"]
+        .into_iter()
+        .find_map(from_env)
+        .map(|session| session.rsplit('/').next().unwrap_or(&session).to_string())
+        .unwrap_or_else(|| UNKNOWN.into())
+}
+
+/// Window managers and compositors by process name
+const WINDOW_MANAGERS: [(&str, &str); 30] = [
+    ("gnome-shell", "Mutter"),
+    ("mutter", "Mutter"),
+    ("cinnamon", "Muffin"),
+    ("muffin", "Muffin"),
+    ("kwin_x11", "KWin"),
+    ("kwin_wayland", "KWin"),
+    ("xfwm4", "Xfwm4"),
+    ("marco", "Marco"),
+    ("metacity", "Metacity"),
+    ("openbox", "Openbox"),
+    ("fluxbox", "Fluxbox"),
+    ("icewm", "IceWM"),
+    ("enlightenment", "Enlightenment"),
+    ("i3", "i3"),
+    ("sway", "Sway"),
+    ("hyprland", "Hyprland"),
+    ("bspwm", "bspwm"),
+    ("awesome", "awesome"),
+    ("dwm", "dwm"),
+    ("xmonad", "xmonad"),
+    ("herbstluftwm", "herbstluftwm"),
+    ("qtile", "Qtile"),
+    ("river", "river"),
+    ("wayfire", "Wayfire"),
+    ("labwc", "labwc"),
+    ("niri", "niri"),
+    ("weston", "Weston"),
+    ("cosmic-comp", "COSMIC"),
+    ("spectrwm", "spectrwm"),
+    ("leftwm", "LeftWM"),
+];
+
+/// The window manager from compositor sockets or running processes, with the session type
+pub fn window_manager(sys: &System) -> String {
+    let socket = [
+        ("HYPRLAND_INSTANCE_SIGNATURE", "Hyprland"),
+        ("SWAYSOCK", "Sway"),
+        ("I3SOCK", "i3"),
+        ("NIRI_SOCKET", "niri"),
+    ]
+    .into_iter()
+    .find(|(var, _)| env::var_os(var).is_some())
+    .map(|(_, name)| name);
+    let running: HashSet<String> = sys
+        .processes()
+        .values()
+        .map(|p| p.name().to_string_lossy().to_lowercase())
+        .collect();
+    let Some(name) = socket.or_else(|| {
+        WINDOW_MANAGERS
+            .iter()
+            .find(|(process, _)| running.contains(*process))
+            .map(|(_, name)| *name)
+    }) else {
+        return UNKNOWN.into();
+    };
+    match session_type() {
+        Some(session) => format!("{} ({})", name, session),
+        None => name.to_string(),
+    }
+}
+
+fn session_type() -> Option<&'static str> {
+    match env::var("XDG_SESSION_TYPE").as_deref() {
+        Ok("wayland") => Some("Wayland"),
+        Ok("x11") => Some("X11"),
+        _ if env::var_os("WAYLAND_DISPLAY").is_some() => Some("Wayland"),
+        _ if env::var_os("DISPLAY").is_some() => Some("X11"),
+        _ => None,
+    }
+}
+
+/// The shell running `info`, which is always this one
+pub fn shell() -> String {
+    format!("xinux {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Processes between a shell and its terminal that are not the terminal
+const NOT_TERMINALS: [&str; 16] = [
+    "xinux", "bash", "zsh", "fish", "sh", "dash", "ksh", "tcsh", "nu", "sudo", "su", "doas",
+    "script", "env", "time", "strace",
+];
+
+/// The first ancestor that is not a shell, else `TERM_PROGRAM` or `TERM`
+pub fn terminal(sys: &System) -> String {
+    let mut pid = sysinfo::get_current_pid().ok();
+    let mut seen = HashSet::new();
+    while let Some(process) = pid.and_then(|p| sys.process(p)) {
+        if !seen.insert(process.pid()) {
+            break;
+        }
+        let name = process.name().to_string_lossy().into_owned();
+        let base = name.trim_start_matches('-');
+        if !NOT_TERMINALS.contains(&base) {
+            return match base {
+                // A console login or remote session has no terminal emulator
+                "login" | "agetty" | "getty" => tty_name().unwrap_or_else(|| base.into()),
+                "sshd" | "sshd-session" => "SSH".into(),
+                "init" | "systemd" | "containerd-shim" | "docker-init" => break,
+                _ => pretty_terminal(base),
+            };
+        }
+        pid = process.parent().filter(|p| *p != Pid::from(0));
+    }
+    env::var("TERM_PROGRAM")
+        .or_else(|_| env::var("TERM"))
+        .unwrap_or_else(|_| UNKNOWN.into())
+}
+
+fn pretty_terminal(name: &str) -> String {
+    match name {
+        "gnome-terminal-" | "gnome-terminal-server" => "GNOME Terminal".into(),
+        "kgx" => "GNOME Console".into(),
+        "konsole" => "Konsole".into(),
+        "kitty" => "kitty".into(),
+        "alacritty" => "Alacritty".into(),
+        "wezterm-gui" => "WezTerm".into(),
+        "xfce4-terminal" => "Xfce Terminal".into(),
+        "code" | "codium" => "VS Code".into(),
+        _ => name.trim_end_matches("-server").to_string(),
+    }
+}
+
+fn tty_name() -> Option<String> {
+    // SAFETY: ttyname returns null or a string in static storage, copied out at once.
+    unsafe {
+        let name = libc::ttyname(0);
+        (!name.is_null()).then(|| {
+            std::ffi::CStr::from_ptr(name)
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+}
+
+/// Display controllers on the PCI bus, named from `pci.ids` when it is installed
+pub fn gpus() -> String {
+    let Ok(devices) = fs::read_dir("/sys/bus/pci/devices") else {
+        return UNKNOWN.into();
+    };
+    let mut ids: Vec<(String, String)> = devices
+        .flatten()
+        .filter(|d| {
+            read_trimmed(d.path().join("class")).is_some_and(|class| class.starts_with("0x03"))
+        })
+        .filter_map(|d| {
+            let id = |name: &str| {
+                read_trimmed(d.path().join(name)).map(|v| v.trim_start_matches("0x").to_lowercase())
+            };
+            Some((id("vendor")?, id("device")?))
+        })
+        .collect();
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() {
+        return UNKNOWN.into();
+    }
+    ids.iter()
+        .map(|(vendor, device)| pci_name(vendor, device))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn pci_name(vendor: &str, device: &str) -> String {
+    let fallback_vendor = match vendor {
+        "10de" => "NVIDIA",
+        "1002" => "AMD",
+        "8086" => "Intel",
+        "1af4" => "Virtio",
+        "15ad" => "VMware",
+        "1234" | "1b36" => "QEMU",
+        "80ee" => "VirtualBox",
+        "1414" => "Microsoft",
+        _ => "",
+    };
+    let (vendor_name, device_name) = pci_ids_lookup(vendor, device);
+    let vendor_name = vendor_name.unwrap_or_else(|| fallback_vendor.to_string());
+    match device_name {
+        Some(device_name) if vendor_name.is_empty() => device_name,
+        Some(device_name) => format!("{} {}", short_vendor(&vendor_name), device_name),
+        None if vendor_name.is_empty() => format!("[{}:{}]", vendor, device),
+        None => format!("{} [{}:{}]", short_vendor(&vendor_name), vendor, device),
+    }
+}
+
+/// `Advanced Micro Devices, Inc. [AMD/ATI]` is shown as `AMD/ATI`
+fn short_vendor(name: &str) -> String {
+    match name.split_once('[') {
+        Some((_, rest)) => rest.trim_end_matches(']').to_string(),
+        None => name.replace(" Corporation", "").replace(", Inc.", ""),
+    }
+}
+
+/// Vendor and device names from the first `pci.ids` found
+fn pci_ids_lookup(vendor: &str, device: &str) -> (Option<String>, Option<String>) {
+    let paths = [
+        "/usr/share/hwdata/pci.ids",
+        "/usr/share/misc/pci.ids",
+        "/usr/share/pci.ids",
+    ];
+    let Some(file) = paths.iter().find_map(|p| fs::File::open(p).ok()) else {
+        return (None, None);
+    };
+    let mut vendor_name = None;
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        if vendor_name.is_none() {
+            if let Some(name) = line.strip_prefix(vendor).and_then(|r| r.strip_prefix("  ")) {
+                vendor_name = Some(name.trim().to_string());
+            }
+            continue;
+        }
+        // The vendor's devices are indented once; the next vendor ends them
+        if !line.starts_with('\t') && !line.starts_with('#') && !line.is_empty() {
+            break;
+        }
+        if let Some(name) = line
+            .strip_prefix('\t')
+            .and_then(|l| l.strip_prefix(device))
+            .and_then(|r| r.strip_prefix("  "))
+        {
+            return (vendor_name, Some(name.trim().to_string()));
+        }
+    }
+    (vendor_name, None)
+}
+
+/// Used and total space of each mounted device, once per device
+pub fn disks() -> Vec<(String, String)> {
+    let disks = Disks::new_with_refreshed_list();
+    let mut list: Vec<_> = disks
+        .list()
+        .iter()
+        .filter(|d| d.total_space() > 0)
+        .filter(|d| {
+            let fs = d.file_system().to_string_lossy();
+            !["tmpfs", "devtmpfs", "squashfs", "ramfs", "proc", "sysfs"].contains(&fs.as_ref())
+        })
+        .collect();
+    // A device mounted several times (bind mounts) is listed at its shortest mount point
+    list.sort_by_key(|d| d.mount_point().as_os_str().len());
+    let mut seen = HashSet::new();
+    list.into_iter()
+        .filter(|d| seen.insert(d.name().to_os_string()))
+        .map(|d| {
+            let used = d.total_space() - d.available_space();
+            let percent = used as f64 * 100.0 / d.total_space() as f64;
+            (
+                d.mount_point().to_string_lossy().into_owned(),
+                format!(
+                    "{} / {} ({:.0}%)",
+                    format_size(used),
+                    format_size(d.total_space()),
+                    percent
+                ),
+            )
+        })
+        .collect()
+}
+
+pub fn swap(sys: &System) -> String {
+    if sys.total_swap() == 0 {
+        return "Disabled".into();
+    }
+    format!(
+        "{} / {}",
+        format_size(sys.used_swap()),
+        format_size(sys.total_swap())
+    )
+}
+
+/// Charge and state of each battery
+pub fn battery() -> String {
+    let Ok(supplies) = fs::read_dir("/sys/class/power_supply") else {
+        return UNKNOWN.into();
+    };
+    let mut batteries: Vec<String> = supplies
+        .flatten()
+        .filter(|s| read_trimmed(s.path().join("type")).as_deref() == Some("Battery"))
+        .filter_map(|s| {
+            let capacity = read_trimmed(s.path().join("capacity"))?;
+            Some(match read_trimmed(s.path().join("status")) {
+                Some(status) => format!("{}% ({})", capacity, status),
+                None => format!("{}%", capacity),
+            })
+        })
+        .collect();
+    batteries.sort();
+    if batteries.is_empty() {
+        return UNKNOWN.into();
+    }
+    batteries.join(", ")
+}
@@ -0,0 +1,1035 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::IntoRawFd;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use colored::Colorize;
+use glob::Pattern;
+
+use crate::execute::{Stage, execute_argv, execute_piped_commands};
+use crate::expand::{expand_string, expand_words};
+use crate::parse_input::{Node, Pipe, RedirOp, Redirect, SimpleCommand, Word, parse};
+use crate::state::{self, Function, Value};
+
+/// How control leaves a command
+pub enum Flow {
+    /// Carry on with the next command, with this exit status
+    Next(i32),
+    /// `return` from the current function or sourced file
+    Return(i32),
+    /// `break N` out of enclosing loops
+    Break(usize),
+    /// `continue N` with an enclosing loop
+    Continue(usize),
+    /// `exit` the shell
+    Exit(i32),
+    /// Unwind everything up to the prompt (e.g. runaway recursion)
+    Abort(i32),
+}
+
+impl Flow {
+    pub fn code(&self) -> i32 {
+        match self {
+            Flow::Next(code) | Flow::Return(code) | Flow::Exit(code) | Flow::Abort(code) => *code,
+            Flow::Break(_) | Flow::Continue(_) => state::status(),
+        }
+    }
+}
+
+fn error(message: String) {
+    eprintln!("{}", format!("xinux: {}", message).red());
+}
+
+/// Parses and runs a command line or script, returning its exit status.
+/// An `exit` is recorded in the shell state for the caller to act on.
+pub fn run_source(src: &str) -> i32 {
+    match parse(src) {
+        Ok(program) => {
+            let flow = run(&program);
+            if let Flow::Exit(code) = flow {
+                state::with(|s| s.exit_requested = Some(code));
+            }
+            let code = flow.code();
+            state::set_status(code);
+            code
+        }
+        Err(e) => {
+            error(format!("syntax error: {}", e));
+            state::set_status(2);
+            2
+        }
+    }
+}
+
+/// Runs a file in the current shell (`source FILE [args]`).
+/// `args` replace the positional parameters while the file runs.
+pub fn source_file(path: &Path, args: Option<Vec<String>>) -> Flow {
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => {
+            error(format!("{}: {}", path.display(), e));
+            return Flow::Next(1);
+        }
+    };
+    let program = match parse(&src) {
+        Ok(program) => program,
+        Err(e) => {
+            error(format!("{}:{}", path.display(), e));
+            return Flow::Next(2);
+        }
+    };
+
+    let saved_args = args.map(|args| {
+        let old = state::positionals();
+        state::set_positionals(args);
+        old
+    });
+    state::with(|s| s.source_depth += 1);
+    let flow = run(&program);
+    state::with(|s| s.source_depth -= 1);
+    if let Some(old) = saved_args {
+        state::set_positionals(old);
+    }
+
+    match flow {
+        Flow::Return(code) => Flow::Next(code),
+        Flow::Break(_) | Flow::Continue(_) => Flow::Next(state::status()),
+        flow => flow,
+    }
+}
+
+pub fn run(node: &Node) -> Flow {
+    let flow = match node {
+        Node::Simple(cmd) => {
+            let flow = run_simple(cmd);
+            if let Flow::Next(code) = flow {
+                set_pipestatus(&[code]);
+            }
+            flow
+        }
+        Node::Pipeline {
+            stages,
+            pipes,
+            negate,
+        } => run_pipeline(stages, pipes, *negate),
+        Node::And(lhs, rhs) => match quietly(|| run(lhs)) {
+            Flow::Next(0) => run(rhs),
+            flow => flow,
+        },
+        Node::Or(lhs, rhs) => match quietly(|| run(lhs)) {
+            Flow::Next(0) => Flow::Next(0),
+            Flow::Next(_) => run(rhs),
+            flow => flow,
+        },
+        Node::List(items) => {
+            let mut flow = Flow::Next(state::status());
+            for item in items {
+                flow = run(item);
+                if !matches!(flow, Flow::Next(_)) {
+                    break;
+                }
+            }
+            flow
+        }
+        Node::Group(body) => run(body),
+        Node::Subshell(body) => Flow::Next(run_forked(|| run(body).code())),
+        Node::Background { body, source } => crate::jobs::run_background(body, source),
+        Node::Redirected(inner, redirects) => match apply_redirects(redirects) {
+            Ok(_guard) => run(inner),
+            Err(e) => {
+                error(e);
+                Flow::Next(1)
+            }
+        },
+        Node::If {
+            branches,
+            otherwise,
+        } => run_if(branches, otherwise.as_deref()),
+        Node::While { cond, body, until } => run_while(cond, body, *until),
+        Node::For { var, items, body } => run_for(var, items.as_deref(), body),
+        Node::Case { word, arms } => run_case(word, arms),
+        Node::Cond(words) => Flow::Next(crate::cond::run_extended(words)),
+        Node::Time {
+            options,
+            pipeline,
+            source,
+        } => crate::timing::run_time(options, pipeline, source),
+        Node::FunctionDef {
+            name, body, source, ..
+        } => {
+            state::define_function(
+                name,
+                Function {
+                    body: Rc::clone(body),
+                    source: source.clone(),
+                },
+            );
+            Flow::Next(0)
+        }
+    };
+    state::set_status(flow.code());
+    flow
+}
+
+/// Runs `f` in a context where failures are expected and not reported
+fn quietly(f: impl FnOnce() -> Flow) -> Flow {
+    state::with(|s| s.quiet_depth += 1);
+    let flow = f();
+    state::with(|s| s.quiet_depth -= 1);
+    flow
+}
+
+fn run_if(branches: &[(Node, Node)], otherwise: Option<&Node>) -> Flow {
+    for (cond, body) in branches {
+        match quietly(|| run(cond)) {
+            Flow::Next(0) => return run(body),
+            Flow::Next(_) => {}
+            flow => return flow,
+        }
+    }
+    match otherwise {
+        Some(body) => run(body),
+        None => Flow::Next(0),
+    }
+}
+
+/// What a loop should do after its body ran
+enum LoopStep {
+    Continue,
+    Stop(Flow),
+}
+
+fn loop_step(flow: Flow, last: &mut i32) -> LoopStep {
+    match flow {
+        Flow::Next(code) => {
+            *last = code;
+            LoopStep::Continue
+        }
+        Flow::Break(1) => LoopStep::Stop(Flow::Next(*last)),
+        Flow::Break(n) => LoopStep::Stop(Flow::Break(n - 1)),
+        Flow::Continue(1) => LoopStep::Continue,
+        Flow::Continue(n) => LoopStep::Stop(Flow::Continue(n - 1)),
+        flow => LoopStep::Stop(flow),
+    }
+}
+
+fn run_loop(mut body: impl FnMut() -> Option<Flow>) -> Flow {
+    state::with(|s| s.loop_depth += 1);
+    let mut last = 0;
+    let flow = loop {
+        let Some(flow) = body() else {
+            break Flow::Next(last);
+        };
+        if let LoopStep::Stop(flow) = loop_step(flow, &mut last) {
+            break flow;
+        }
+    };
+    state::with(|s| s.loop_depth -= 1);
+    flow
+}
+
+fn run_while(cond: &Node, body: &Node, until: bool) -> Flow {
+    run_loop(|| match quietly(|| run(cond)) {
+        Flow::Next(code) if (code == 0) != until => Some(run(body)),
+        Flow::Next(_) => None,
+        flow => Some(flow),
+    })
+}
+
+fn run_for(var: &str, items: Option<&[Word]>, body: &Node) -> Flow {
+    let values = match items {
+        Some(words) => expand_words(words),
+        None => state::positionals(),
+    };
+    let mut values = values.into_iter();
+    run_loop(|| {
+        let value = values.next()?;
+        state::set_var(var, &value);
+        Some(run(body))
+    })
+}
+
+fn run_case(word: &Word, arms: &[(Vec<Word>, Node)]) -> Flow {
+    let subject = expand_string(&word.text);
+    for (patterns, body) in arms {
+        let matched = patterns.iter().any(|pattern| {
+            let pattern = expand_pattern(&pattern.text);
+            Pattern::new(&pattern).is_ok_and(|p| p.matches(&subject))
+        });
+        if matched {
+            return run(body);
+        }
+    }
+    Flow::Next(0)
+}
+
+/// Runs the `DEBUG` trap and the debugger before a simple command.
+/// `Some` means the command must not run.
+fn before_command(line: usize) -> Option<Flow> {
+    if state::with(|s| s.in_trap) {
+        return None;
+    }
+    state::with(|s| s.lineno = line);
+    if let Some(trap) = state::with(|s| s.traps.get("DEBUG").cloned())
+        && let Flow::Exit(code) = run_trap(&trap)
+    {
+        return Some(Flow::Exit(code));
+    }
+    crate::debugger::before_command(line)
+}
+
+/// Runs a trap command without disturbing `$?`
+fn run_trap(src: &str) -> Flow {
+    let status = state::status();
+    state::with(|s| s.in_trap = true);
+    let flow = match parse(src) {
+        Ok(program) => run(&program),
+        Err(e) => {
+            error(format!("trap: syntax error: {}", e));
+            Flow::Next(2)
+        }
+    };
+    state::with(|s| s.in_trap = false);
+    state::set_status(status);
+    flow
+}
+
+/// Runs the `EXIT` trap once, when the shell or a script finishes
+pub fn run_exit_trap() {
+    if let Some(trap) = state::with(|s| s.traps.remove("EXIT")) {
+        run_trap(&trap);
+    }
+}
+
+fn run_simple(cmd: &SimpleCommand) -> Flow {
+    if let Some(flow) = before_command(cmd.line) {
+        return flow;
+    }
+    let argv = expand_words(&cmd.words);
+    let _guard = match apply_redirects(&cmd.redirects) {
+        Ok(guard) => guard,
+        Err(e) => {
+            error(e);
+            return Flow::Next(1);
+        }
+    };
+    let _color = check_colors();
+
+    if argv.is_empty() {
+        for (name, value) in &cmd.assigns {
+            state::set_var(name, &expand_string(&value.text));
+        }
+        return Flow::Next(0);
+    }
+
+    // `NAME=value cmd` only changes the environment of `cmd`; shell variables that
+    // were not exported come back as they were, like `local` values do
+    let saved: Vec<(String, Option<Value>, bool)> = cmd
+        .assigns
+        .iter()
+        .map(|(name, value)| {
+            let value = expand_string(&value.text);
+            let old = state::get_value(name);
+            let exported = env::var_os(name).is_some();
+            state::unset_var(name);
+            state::export(name, &value);
+            (name.clone(), old, exported)
+        })
+        .collect();
+
+    let flow = run_argv(&argv);
+
+    for (name, old, exported) in saved.into_iter().rev() {
+        state::unset_var(&name);
+        match old {
+            Some(value) if exported => state::export(&name, &value.as_scalar()),
+            Some(value) => state::set_value(&name, value),
+            None => {}
+        }
+    }
+    flow
+}
+
+/// Records the exit status of every stage of the last pipeline in `PIPESTATUS`
+fn set_pipestatus(statuses: &[i32]) {
+    let items = statuses.iter().map(i32::to_string).collect();
+    state::set_value("PIPESTATUS", Value::Array(items));
+}
+
+fn run_pipeline(stages: &[Node], pipes: &[Pipe], negate: bool) -> Flow {
+    let flow = if stages.len() == 1 {
+        run(&stages[0])
+    } else {
+        let commands: Vec<Stage> = stages
+            .iter()
+            .map(|stage| match stage {
+                Node::Simple(cmd) => Stage::Command {
+                    argv: expand_words(&cmd.words),
+                    assigns: cmd
+                        .assigns
+                        .iter()
+                        .map(|(name, value)| (name.clone(), expand_string(&value.text)))
+                        .collect(),
+                    redirects: &cmd.redirects,
+                },
+                node => Stage::Compound(node),
+            })
+            .collect();
+        let statuses = execute_piped_commands(&commands, pipes);
+        let code = statuses.last().copied().unwrap_or(0);
+        set_pipestatus(&statuses);
+        Flow::Next(code)
+    };
+    match flow {
+        Flow::Next(code) if negate => Flow::Next(i32::from(code == 0)),
+        flow => flow,
+    }
+}
+
+/// Commands the shell runs itself rather than looking up in `PATH`
+pub const BUILTINS: [&str; 46] = [
+    "return",
+    "exit",
+    "break",
+    "continue",
+    "source",
+    ".",
+    ":",
+    "true",
+    "false",
+    "local",
+    "export",
+    "unset",
+    "shift",
+    "functions",
+    "funcsave",
+    "read",
+    "printf",
+    "getopts",
+    "argparse",
+    "test",
+    "[",
+    "trap",
+    "cd",
+    "ls",
+    "la",
+    "clear",
+    "echo",
+    "info",
+    "top",
+    "touch",
+    "xinsay",
+    "time",
+    "help",
+    "alias",
+    "xinux",
+    "where",
+    "sort-by",
+    "select",
+    "first",
+    "to",
+    "ps",
+    "procs",
+    "kill",
+    "pkill",
+    "jobs",
+    "wait",
+];
+
+/// Runs an expanded command: functions first, then builtins, then programs
+pub fn run_argv(argv: &[String]) -> Flow {
+    if let Some(function) = state::function(&argv[0]) {
+        return call_function(&argv[0], &function, argv[1..].to_vec());
+    }
+    let args = &argv[1..];
+    match argv[0].as_str() {
+        "return" => builtin_return(args),
+        "exit" => builtin_exit(args),
+        "break" | "continue" => builtin_break(&argv[0], args),
+        "source" | "." => builtin_source(args),
+        ":" | "true" => Flow::Next(0),
+        "false" => Flow::Next(1),
+        "local" => Flow::Next(builtin_local(args)),
+        "export" => Flow::Next(builtin_export(args)),
+        "unset" => Flow::Next(builtin_unset(args)),
+        "shift" => Flow::Next(builtin_shift(args)),
+        "functions" => Flow::Next(builtin_functions(args)),
+        "funcsave" => Flow::Next(builtin_funcsave(args)),
+        "read" => Flow::Next(crate::read::builtin_read(args)),
+        "printf" => Flow::Next(crate::printf::builtin_printf(args)),
+        "getopts" => Flow::Next(crate::getopts::builtin_getopts(args)),
+        "trap" => Flow::Next(builtin_trap(args)),
+        "jobs" => Flow::Next(crate::jobs::builtin_jobs(args)),
+        "wait" => Flow::Next(crate::jobs::builtin_wait(args)),
+        "argparse" => crate::argparse::builtin_argparse(args),
+        "test" => Flow::Next(crate::cond::builtin_test(args, false)),
+        "[" => Flow::Next(crate::cond::builtin_test(args, true)),
+        "where" | "sort-by" | "select" | "first" | "to" => {
+            Flow::Next(crate::records::without_records(&argv[0]))
+        }
+        _ => Flow::Next(execute_argv(argv)),
+    }
+}
+
+pub fn call_function(name: &str, function: &Function, args: Vec<String>) -> Flow {
+    let max = state::max_call_depth();
+    if state::call_depth() >= max {
+        error(format!(
+            "{}: maximum function nesting level exceeded ({})",
+            name, max
+        ));
+        return Flow::Abort(1);
+    }
+    state::push_frame(name, args);
+    crate::debugger::enter_function(name);
+    let loops = state::with(|s| std::mem::take(&mut s.loop_depth));
+    let flow = run(&function.body);
+    state::with(|s| s.loop_depth = loops);
+    state::pop_frame();
+    match flow {
+        Flow::Return(code) => Flow::Next(code),
+        Flow::Break(_) | Flow::Continue(_) => Flow::Next(state::status()),
+        flow => flow,
+    }
+}
+
+/// Defines the functions stored in the `[functions]` table of the config.
+/// Values may be a full definition or just the body.
+pub fn load_functions(functions: &HashMap<String, String>) {
+    for (name, source) in functions {
+        match parse(source) {
+            Ok(Node::List(items)) if matches!(items.as_slice(), [Node::FunctionDef { name: n, .. }] if n == name) =>
+            {
+                run(&items[0]);
+            }
+            Ok(body) => state::define_function(
+                name,
+                Function {
+                    body: Rc::new(body),
+                    source: format!("{}() {{ {}; }}", name, source.trim()),
+                },
+            ),
+            Err(e) => error(format!("function `{}` in config: {}", name, e)),
+        }
+    }
+}
+
+fn builtin_return(args: &[String]) -> Flow {
+    if state::call_depth() == 0 && state::with(|s| s.source_depth) == 0 {
+        error("return: can only `return` from a function or sourced file".into());
+        return Flow::Next(1);
+    }
+    match args.first() {
+        None => Flow::Return(state::status()),
+        Some(n) => match n.parse::<i32>() {
+            Ok(code) => Flow::Return(code & 0xff),
+            Err(_) => {
+                error(format!("return: {}: numeric argument required", n));
+                Flow::Return(2)
+            }
+        },
+    }
+}
+
+fn builtin_exit(args: &[String]) -> Flow {
+    match args.first() {
+        None => Flow::Exit(state::status()),
+        Some(n) => match n.parse::<i32>() {
+            Ok(code) => Flow::Exit(code & 0xff),
+            Err(_) => {
+                error(format!("exit: {}: numeric argument required", n));
+                Flow::Exit(2)
+            }
+        },
+    }
+}
+
+fn builtin_break(name: &str, args: &[String]) -> Flow {
+    let n = match args.first().map(|a| a.parse::<usize>()) {
+        None => 1,
+        Some(Ok(n)) if n > 0 => n,
+        _ => {
+            error(format!("{}: {}: loop count out of range", name, args[0]));
+            return Flow::Next(1);
+        }
+    };
+    let loops = state::with(|s| s.loop_depth);
+    if loops == 0 {
+        error(format!(
+            "{}: only meaningful in a `for`, `while`, or `until` loop",
+            name
+        ));
+        return Flow::Next(0);
+    }
+    let n = n.min(loops);
+    if name == "break" {
+        Flow::Break(n)
+    } else {
+        Flow::Continue(n)
+    }
+}
+
+/// `source FILE [args]` / `. FILE [args]`: looks in PATH when FILE has no slash
+fn builtin_source(args: &[String]) -> Flow {
+    let Some(file) = args.first() else {
+        eprintln!("Usage: source file [args...]");
+        return Flow::Next(2);
+    };
+    let mut path = PathBuf::from(file);
+    if !file.contains('/') {
+        let found = env::var("PATH").ok().and_then(|paths| {
+            paths
+                .split(':')
+                .map(|dir| Path::new(dir).join(file))
+                .find(|candidate| candidate.is_file())
+        });
+        if let Some(found) = found {
+            path = found;
+        }
+    }
+    let script_args = (args.len() > 1).then(|| args[1..].to_vec());
+    source_file(&path, script_args)
+}
+
+fn builtin_local(args: &[String]) -> i32 {
+    if state::call_depth() == 0 {
+        error("local: can only be used in a function".into());
+        return 1;
+    }
+    let mut code = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !state::is_valid_name(name) {
+            error(format!("local: `{}`: not a valid identifier", arg));
+            code = 1;
+            continue;
+        }
+        state::make_local(name);
+        if let Some(value) = value {
+            state::set_var(name, value);
+        }
+    }
+    code
+}
+
+fn builtin_export(args: &[String]) -> i32 {
+    if args.is_empty() {
+        let mut vars: Vec<(String, String)> = env::vars().collect();
+        vars.sort();
+        for (name, value) in vars {
+            println!("export {}={}", name, shell_words::quote(&value));
+        }
+        return 0;
+    }
+    let mut code = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, value.to_string()),
+            None => (arg.as_str(), state::get_var(arg).unwrap_or_default()),
+        };
+        if !state::is_valid_name(name) {
+            error(format!("export: `{}`: not a valid identifier", arg));
+            code = 1;
+            continue;
+        }
+        state::with(|s| s.vars.remove(name));
+        state::export(name, &value);
+    }
+    code
+}
+
+fn builtin_unset(args: &[String]) -> i32 {
+    let (functions, names) = match args.first().map(String::as_str) {
+        Some("-f") => (true, &args[1..]),
+        Some("-v") => (false, &args[1..]),
+        _ => (false, args),
+    };
+    for name in names {
+        if functions {
+            state::with(|s| s.functions.remove(name));
+        } else {
+            state::unset_var(name);
+        }
+    }
+    0
+}
+
+fn builtin_shift(args: &[String]) -> i32 {
+    let n = match args.first().map(|a| a.parse::<usize>()) {
+        None => 1,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            error(format!("shift: {}: numeric argument required", args[0]));
+            return 2;
+        }
+    };
+    let mut params = state::positionals();
+    if n > params.len() {
+        return 1;
+    }
+    params.drain(..n);
+    state::set_positionals(params);
+    0
+}
+
+/// `trap [-p]`, `trap CMD CONDITION...` and `trap - CONDITION...`.
+/// The conditions are `DEBUG` (before every simple command) and `EXIT` (`0`).
+fn builtin_trap(args: &[String]) -> i32 {
+    if args.is_empty() || args[0] == "-p" {
+        let mut traps: Vec<(String, String)> =
+            state::with(|s| s.traps.clone().into_iter().collect());
+        traps.sort();
+        for (condition, command) in traps {
+            println!("trap -- {} {}", shell_words::quote(&command), condition);
+        }
+        return 0;
+    }
+    let Some((command, conditions)) = args.split_first().filter(|(_, c)| !c.is_empty()) else {
+        eprintln!("Usage: trap [-p] | trap COMMAND CONDITION... | trap - CONDITION...");
+        return 2;
+    };
+    let mut code = 0;
+    for condition in conditions {
+        let condition = match condition.to_uppercase().as_str() {
+            "DEBUG" => "DEBUG",
+            "EXIT" | "0" => "EXIT",
+            _ => {
+                error(format!(
+                    "trap: {}: only DEBUG and EXIT can be trapped",
+                    condition
+                ));
+                code = 1;
+                continue;
+            }
+        };
+        state::with(|s| {
+            if command == "-" || command.is_empty() {
+                s.traps.remove(condition);
+            } else {
+                s.traps.insert(condition.to_string(), command.clone());
+            }
+        });
+    }
+    code
+}
+
+/// `functions` lists defined functions; `functions NAME...` prints their source
+fn builtin_functions(args: &[String]) -> i32 {
+    if args.is_empty() {
+        for name in state::function_names() {
+            println!("{}", name);
+        }
+        return 0;
+    }
+    let mut code = 0;
+    for name in args {
+        match state::function(name) {
+            Some(function) => println!("{}", function.source),
+            None => {
+                error(format!("functions: {}: not found", name));
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+/// `funcsave NAME...` persists functions into the `[functions]` table of the config
+fn builtin_funcsave(args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("Usage: funcsave name...");
+        return 2;
+    }
+    let mut config = crate::load_config();
+    let mut code = 0;
+    for name in args {
+        match state::function(name) {
+            Some(function) => {
+                config.functions.insert(name.clone(), function.source);
+                println!("Function '{}' saved to config", name);
+            }
+            None => {
+                error(format!("funcsave: {}: not a function", name));
+                code = 1;
+            }
+        }
+    }
+    crate::save_config(&config);
+    code
+}
+
+/// Expands a `case` or `[[ == ]]` pattern, escaping the parts that were quoted
+pub(crate) fn expand_pattern(text: &str) -> String {
+    expand_quoted_literally(text, Pattern::escape)
+}
+
+/// Expands a word, passing its quoted parts through `escape` so they match literally
+pub(crate) fn expand_quoted_literally(text: &str, escape: impl Fn(&str) -> String) -> String {
+    let mut pattern = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['\'', '"', '\\']) {
+        pattern.push_str(&expand_string(&rest[..start]));
+        let quote = rest[start..].chars().next().unwrap_or('"');
+        let end = if quote == '\\' {
+            (start + 2).min(rest.len())
+        } else {
+            rest[start + 1..]
+                .find(quote)
+                .map_or(rest.len(), |i| start + i + 2)
+        };
+        pattern.push_str(&escape(&expand_string(&rest[start..end])));
+        rest = &rest[end..];
+    }
+    pattern.push_str(&expand_string(rest));
+    pattern
+}
+
+/// Decodes a `waitpid` status into a shell exit code
+pub(crate) fn exit_code(status: i32) -> i32 {
+    if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else if libc::WIFSIGNALED(status) {
+        128 + libc::WTERMSIG(status)
+    } else {
+        1
+    }
+}
+
+pub(crate) fn wait_pid(pid: libc::pid_t) -> i32 {
+    wait_status(pid).map_or(1, exit_code)
+}
+
+/// Waits for a child with `wait4`, handing its resource usage to any running `time`
+pub(crate) fn wait_status(pid: libc::pid_t) -> Option<i32> {
+    let mut status = 0;
+    // SAFETY: `rusage` is plain data, for which all zeroes is valid.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: waiting on a child we started ourselves.
+        let rc = unsafe { libc::wait4(pid, &mut status, 0, &mut usage) };
+        if rc == pid {
+            crate::timing::reaped(&usage);
+            return Some(status);
+        }
+        if rc == -1 && io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return None;
+        }
+    }
+}
+
+/// Runs `f` in a forked copy of the shell and waits for it
+fn run_forked(f: impl FnOnce() -> i32) -> i32 {
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    // SAFETY: the child only runs shell code and leaves through `_exit`.
+    match unsafe { libc::fork() } {
+        -1 => {
+            error(format!("fork: {}", io::Error::last_os_error()));
+            1
+        }
+        0 => {
+            let code = f();
+            let _ = io::stdout().flush();
+            let _ = io::stderr().flush();
+            // SAFETY: terminating the forked child without running parent destructors.
+            unsafe { libc::_exit(code) }
+        }
+        pid => wait_pid(pid),
+    }
+}
+
+/// Runs `src` in a subshell and returns its output without trailing newlines
+pub fn capture(src: &str) -> String {
+    let (mut reader, writer) = match io::pipe() {
+        Ok(pipe) => pipe,
+        Err(e) => {
+            error(format!("pipe: {}", e));
+            return String::new();
+        }
+    };
+    let _ = io::stdout().flush();
+    // SAFETY: the child only runs shell code and leaves through `_exit`.
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        drop(reader);
+        let fd = writer.into_raw_fd();
+        // SAFETY: plain descriptor juggling in the child.
+        unsafe {
+            libc::dup2(fd, 1);
+            libc::close(fd);
+        }
+        let code = run_source(src);
+        let _ = io::stdout().flush();
+        // SAFETY: terminating the forked child without running parent destructors.
+        unsafe { libc::_exit(code) }
+    }
+    drop(writer);
+    let mut output = Vec::new();
+    let _ = reader.read_to_end(&mut output);
+    if pid > 0 {
+        state::set_status(wait_pid(pid));
+    }
+    String::from_utf8_lossy(&output)
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+/// Restores redirected file descriptors when dropped
+pub struct RedirectGuard {
+    saved: Vec<(i32, i32)>,
+}
+
+impl RedirectGuard {
+    fn save(&mut self, fd: i32) {
+        if self.saved.iter().any(|(f, _)| *f == fd) {
+            return;
+        }
+        // SAFETY: duplicating a descriptor number; -1 means it was closed.
+        let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+        self.saved.push((fd, copy));
+    }
+
+    fn install(&mut self, fd: i32, file: File) {
+        self.save(fd);
+        let raw = file.into_raw_fd();
+        // SAFETY: `raw` is a descriptor we own.
+        unsafe {
+            libc::dup2(raw, fd);
+            libc::close(raw);
+        }
+    }
+}
+
+impl Drop for RedirectGuard {
+    fn drop(&mut self) {
+        if self.saved.is_empty() {
+            return;
+        }
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        for &(fd, copy) in self.saved.iter().rev() {
+            // SAFETY: restoring descriptors saved in `save`.
+            unsafe {
+                if copy >= 0 {
+                    libc::dup2(copy, fd);
+                    libc::close(copy);
+                } else {
+                    libc::close(fd);
+                }
+            }
+        }
+    }
+}
+
+/// Puts colors back as they were once a command is done
+pub struct ColorGuard {
+    before: bool,
+}
+
+/// Turns colors off while a command writes somewhere other than a terminal, or
+/// while `NO_COLOR` is set, unless `CLICOLOR_FORCE` asks for them anyway. The
+/// variables are read each time, so exporting one takes effect right away.
+pub fn check_colors() -> ColorGuard {
+    let before = colored::control::SHOULD_COLORIZE.should_colorize();
+    let var = |name: &str| env::var_os(name).filter(|v| !v.is_empty());
+    // SAFETY: only asks whether stdout is a terminal.
+    let tty = unsafe { libc::isatty(1) } == 1;
+    let colors = var("CLICOLOR_FORCE").is_some_and(|v| v != "0")
+        || (tty && var("NO_COLOR").is_none() && var("CLICOLOR").is_none_or(|v| v != "0"));
+    colored::control::set_override(colors);
+    ColorGuard { before }
+}
+
+impl Drop for ColorGuard {
+    fn drop(&mut self) {
+        colored::control::set_override(self.before);
+    }
+}
+
+/// Points file descriptors at the redirection targets until the guard is dropped
+pub fn apply_redirects(redirects: &[Redirect]) -> Result<RedirectGuard, String> {
+    let mut guard = RedirectGuard { saved: Vec::new() };
+    if redirects.is_empty() {
+        return Ok(guard);
+    }
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+
+    for redirect in redirects {
+        let target = expand_string(&redirect.target.text);
+        let open = |options: &mut OpenOptions| {
+            options
+                .open(&target)
+                .map_err(|e| format!("{}: {}", target, e))
+        };
+        match redirect.op {
+            RedirOp::In => {
+                let file = open(OpenOptions::new().read(true))?;
+                guard.install(redirect.fd, file);
+            }
+            RedirOp::Out | RedirOp::Append | RedirOp::OutErr | RedirOp::AppendErr => {
+                let append = matches!(redirect.op, RedirOp::Append | RedirOp::AppendErr);
+                let file = open(
+                    OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .append(append)
+                        .truncate(!append),
+                )?;
+                if matches!(redirect.op, RedirOp::OutErr | RedirOp::AppendErr) {
+                    let copy = file.try_clone().map_err(|e| e.to_string())?;
+                    guard.install(2, copy);
+                    guard.install(1, file);
+                } else {
+                    guard.install(redirect.fd, file);
+                }
+            }
+            RedirOp::DupOut | RedirOp::DupIn => {
+                guard.save(redirect.fd);
+                if target == "-" {
+                    // SAFETY: closing a descriptor the guard will restore.
+                    unsafe { libc::close(redirect.fd) };
+                    continue;
+                }
+                let Ok(source) = target.parse::<i32>() else {
+                    return Err(format!("{}: ambiguous redirect", target));
+                };
+                // SAFETY: duplicating one standard descriptor onto another.
+                if unsafe { libc::dup2(source, redirect.fd) } == -1 {
+                    return Err(format!("{}: {}", source, io::Error::last_os_error()));
+                }
+            }
+        }
+    }
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_text(src: &str) -> Flow {
+        run(&parse(src).unwrap())
+    }
+
+    #[test]
+    fn prefix_assignments_restore_shell_variables() {
+        run_text("eval_test_x=1; eval_test_x=2 true");
+        assert_eq!(state::get_var("eval_test_x").as_deref(), Some("1"));
+        assert!(env::var_os("eval_test_x").is_none());
+    }
+
+    #[test]
+    fn prefix_assignments_are_exported_only_for_the_command() {
+        run_text("eval_test_f() { eval_test_seen=$eval_test_y; }; eval_test_y=in eval_test_f");
+        assert_eq!(state::get_var("eval_test_seen").as_deref(), Some("in"));
+        assert!(state::get_var("eval_test_y").is_none());
+        assert!(env::var_os("eval_test_y").is_none());
+    }
+}
@@ -1,344 +1,343 @@
+use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::os::unix::fs::PermissionsExt;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
 use std::path::Path;
-use std::process::{Command, Stdio};
-use std::time::UNIX_EPOCH;
+use std::process::{Child, Command, Stdio};
 
-use chrono::{DateTime, Local};
 use clearscreen::clear;
 use colored::Colorize;
 
+use crate::eval::{BUILTINS, apply_redirects, run, run_argv, run_source, wait_pid};
 use crate::execute2::execute2;
+use crate::ls::builtin_ls;
+use crate::parse_input::{Node, Pipe, Redirect};
+use crate::printf::builtin_echo;
+use crate::records;
+use crate::state;
+
+/// One stage of a pipeline
+pub(crate) enum Stage<'a> {
+    /// A simple command, already expanded
+    Command {
+        argv: Vec<String>,
+        /// `NAME=value` words before the command, for its environment only
+        assigns: Vec<(String, String)>,
+        redirects: &'a [Redirect],
+    },
+    /// A compound command, run in a forked copy of the shell
+    Compound(&'a Node),
+}
 
-fn substitute_commands(input: &str) -> String {
-    let mut output = String::new();
-    let mut chars = input.chars().peekable();
+fn export_all(assigns: &[(String, String)]) {
+    for (name, value) in assigns {
+        state::export(name, value);
+    }
+}
 
-    while let Some(ch) = chars.next() {
-        if ch == '$' && chars.peek() == Some(&'(') {
-            chars.next(); // Consume '('
-            let mut command = String::new();
-            let mut depth = 1;
+/// Whether `name` has to run inside the shell rather than as a program from `PATH`
+fn runs_in_shell(name: &str) -> bool {
+    BUILTINS.contains(&name) || state::function(name).is_some()
+}
 
-            while let Some(&next) = chars.peek() {
-                chars.next();
-                if next == '(' {
-                    depth += 1;
-                } else if next == ')' {
-                    depth -= 1;
-                    if depth == 0 {
-                        break;
-                    }
+/// Replaces an alias in the first word, as if it had been typed out
+fn expand_alias(argv: &[String], aliases: &HashMap<String, String>) -> Vec<String> {
+    let Some((name, rest)) = argv.split_first() else {
+        return Vec::new();
+    };
+    if state::function(name).is_some() {
+        return argv.to_vec();
+    }
+    match aliases.get(name).map(|a| shell_words::split(a)) {
+        Some(Ok(mut words)) if !words.is_empty() => {
+            words.extend_from_slice(rest);
+            words
+        }
+        _ => argv.to_vec(),
+    }
+}
+
+/// Forks a copy of the shell for one pipeline stage, with `stdin` on fd 0 and the
+/// output chosen by `pipe` going to a new pipe whose read end is returned.
+fn fork_stage(
+    stdin: Option<OwnedFd>,
+    pipe: Option<Pipe>,
+    f: impl FnOnce() -> i32,
+) -> io::Result<(libc::pid_t, Option<OwnedFd>)> {
+    let ends = match pipe {
+        Some(pipe) => Some((pipe, io::pipe()?)),
+        None => None,
+    };
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    // SAFETY: the child only runs shell code and leaves through `_exit`.
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if pid == 0 {
+        // SAFETY: plain descriptor juggling in the child.
+        unsafe {
+            if let Some(fd) = stdin {
+                libc::dup2(fd.as_raw_fd(), 0);
+            }
+            if let Some((pipe, (reader, writer))) = ends {
+                drop(reader);
+                if pipe != Pipe::Stderr {
+                    libc::dup2(writer.as_raw_fd(), 1);
+                }
+                if pipe != Pipe::Stdout {
+                    libc::dup2(writer.as_raw_fd(), 2);
                 }
-                command.push(next);
             }
-
-            if let Ok(output_str) = execute_command(&command) {
-                output.push_str(&output_str);
+            // Die quietly like any other program when the reader goes away
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+            if libc::isatty(1) == 0 || libc::isatty(2) == 0 {
+                colored::control::set_override(false);
             }
-        } else {
-            output.push(ch);
         }
+        state::with(|s| s.interactive = false);
+        let code = f();
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        // SAFETY: terminating the forked child without running parent destructors.
+        unsafe { libc::_exit(code) }
     }
-
-    output
+    Ok((pid, ends.map(|(_, (reader, _))| reader.into())))
 }
 
-fn execute_command(command: &str) -> Result<String, String> {
-    use std::process::Command;
+/// A started pipeline stage, waited for once every stage is running
+enum Job {
+    Spawned(Child),
+    Forked(libc::pid_t),
+    /// The stage has nothing to wait for: it could not be started, or it ran
+    /// in-process as part of a record chain
+    Done(i32),
+}
 
-    let parts: Vec<&str> = command.trim().split_whitespace().collect();
-    if parts.is_empty() {
-        return Err("Empty command".to_string());
+impl Job {
+    fn wait(self) -> i32 {
+        match self {
+            Job::Spawned(child) => wait_pid(child.id() as libc::pid_t),
+            Job::Forked(pid) => wait_pid(pid),
+            Job::Done(code) => code,
+        }
     }
+}
 
-    let output = Command::new(parts[0])
-        .args(&parts[1..])
-        .output()
-        .map_err(|e| e.to_string())?;
-
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+/// How many stages starting at `start` form a record chain: a builtin such as
+/// `ls` followed by filters like `where`, joined by plain pipes
+fn record_chain(commands: &[Stage], argvs: &[Vec<String>], pipes: &[Pipe], start: usize) -> usize {
+    let is = |i: usize, test: fn(&str) -> bool| {
+        matches!(commands[i], Stage::Command { .. })
+            && argvs[i]
+                .first()
+                .is_some_and(|name| test(name) && state::function(name).is_none())
+    };
+    if !is(start, records::is_producer) {
+        return 0;
+    }
+    let mut end = start + 1;
+    while end < commands.len() && pipes[end - 1] == Pipe::Stdout && is(end, records::is_filter) {
+        end += 1;
     }
+    end - start
 }
 
-fn expand_variables(input: &str) -> String {
-    let substituted = substitute_commands(input);
-    let mut output = String::new();
-    let mut chars = substituted.chars().peekable();
+/// Input for the stage after one that failed to start
+fn empty_input() -> Option<OwnedFd> {
+    fs::File::open("/dev/null").ok().map(OwnedFd::from)
+}
+
+/// Connects the output `pipe` selects to a new pipe and returns its read end
+fn pipe_output(cmd: &mut Command, pipe: Pipe) -> io::Result<OwnedFd> {
+    let (reader, writer) = io::pipe()?;
+    match pipe {
+        Pipe::Stdout => cmd.stdout(writer),
+        Pipe::Stderr => cmd.stderr(writer),
+        Pipe::Both => cmd.stderr(writer.try_clone()?).stdout(writer),
+    };
+    Ok(reader.into())
+}
 
-    while let Some(ch) = chars.next() {
-        if ch == '$' {
-            let mut var_name = String::new();
-            while let Some(&next) = chars.peek() {
-                if next.is_alphanumeric() || next == '_' {
-                    var_name.push(chars.next().unwrap());
-                } else {
-                    break;
+/// Runs the stages concurrently, each reading what `pipes` sends it from the one
+/// before; the last writes straight to our stdout. Returns every stage's exit status.
+pub(crate) fn execute_piped_commands(commands: &[Stage], pipes: &[Pipe]) -> Vec<i32> {
+    let mut previous_output: Option<OwnedFd> = None;
+    let mut jobs = Vec::new();
+    // Read once for the whole pipeline; no config file means no aliases
+    let aliases = crate::read_config()
+        .map(|config| config.aliases)
+        .unwrap_or_default();
+    let argvs: Vec<Vec<String>> = commands
+        .iter()
+        .map(|stage| match stage {
+            Stage::Command { argv, .. } => expand_alias(argv, &aliases),
+            Stage::Compound(_) => Vec::new(),
+        })
+        .collect();
+
+    let mut i = 0;
+    while i < commands.len() {
+        let chain = record_chain(commands, &argvs, pipes, i);
+        if chain > 1 {
+            let end = i + chain - 1;
+            let run_chain = || {
+                let mut guards = Vec::new();
+                for stage in &commands[i..=end] {
+                    if let Stage::Command {
+                        assigns, redirects, ..
+                    } = stage
+                    {
+                        export_all(assigns);
+                        match apply_redirects(redirects) {
+                            Ok(guard) => guards.push(guard),
+                            Err(e) => {
+                                eprintln!("{}", format!("xinux: {}", e).red());
+                                return 1;
+                            }
+                        }
+                    }
+                }
+                records::run_chain(&argvs[i..=end])
+            };
+            // Earlier stages of the chain never leave this process, so only the
+            // chain as a whole has a status
+            jobs.extend((i..end).map(|_| Job::Done(0)));
+            match fork_stage(previous_output.take(), pipes.get(end).copied(), run_chain) {
+                Ok((pid, output)) => {
+                    jobs.push(Job::Forked(pid));
+                    previous_output = output;
+                }
+                Err(e) => {
+                    eprintln!("{}", format!("xinux: fork: {}", e).red());
+                    jobs.push(Job::Done(1));
+                    previous_output = empty_input();
                 }
             }
-            if let Ok(val) = env::var(&var_name) {
-                output.push_str(&val);
-            } else {
-                output.push('$');
-                output.push_str(&var_name);
-            }
-        } else {
-            output.push(ch);
+            i = end + 1;
+            continue;
         }
-    }
-
-    output
-}
-
-fn execute_piped_commands(input: &str) {
-    let commands: Vec<&str> = input.split('|').map(|s| s.trim()).collect();
-    if commands.is_empty() {
-        return;
-    }
 
-    let mut previous_output: Option<std::process::ChildStdout> = None;
-
-    for (i, command) in commands.iter().enumerate() {
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if parts.is_empty() {
+        let stage = &commands[i];
+        let argv = &argvs[i];
+        let pipe = pipes.get(i).copied();
+        i += 1;
+        let (assigns, redirects) = match stage {
+            Stage::Command {
+                assigns, redirects, ..
+            } => (&assigns[..], *redirects),
+            Stage::Compound(_) => (&[][..], &[][..]),
+        };
+        let forked = match stage {
+            Stage::Compound(node) => Some(fork_stage(previous_output.take(), pipe, || {
+                run(node).code()
+            })),
+            // Redirections have to be applied after the pipe is in place, so commands
+            // with any are run from a forked shell too
+            Stage::Command { .. }
+                if argv.first().is_none_or(|name| runs_in_shell(name)) || !redirects.is_empty() =>
+            {
+                let run_stage = || {
+                    // The stage is a copy of the shell of its own, so exporting is enough
+                    export_all(assigns);
+                    match apply_redirects(redirects) {
+                        Ok(_guard) if argv.is_empty() => 0,
+                        Ok(_guard) => run_argv(argv).code(),
+                        Err(e) => {
+                            eprintln!("{}", format!("xinux: {}", e).red());
+                            1
+                        }
+                    }
+                };
+                Some(fork_stage(previous_output.take(), pipe, run_stage))
+            }
+            Stage::Command { .. } => None,
+        };
+        if let Some(forked) = forked {
+            match forked {
+                Ok((pid, output)) => {
+                    jobs.push(Job::Forked(pid));
+                    previous_output = output;
+                }
+                Err(e) => {
+                    eprintln!("{}", format!("xinux: fork: {}", e).red());
+                    jobs.push(Job::Done(1));
+                    previous_output = empty_input();
+                }
+            }
             continue;
         }
 
-        let mut cmd = Command::new(parts[0]);
-        cmd.args(&parts[1..]);
+        let command = argv.join(" ");
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]);
+        cmd.envs(assigns.iter().map(|(name, value)| (name, value)));
 
         if let Some(output) = previous_output.take() {
             cmd.stdin(Stdio::from(output));
         }
 
-        if i < commands.len() - 1 {
-            cmd.stdout(Stdio::piped());
-        }
-
-        let mut output = match cmd.spawn() {
-            Ok(child) => child,
+        let output = match pipe.map(|pipe| pipe_output(&mut cmd, pipe)).transpose() {
+            Ok(output) => output,
             Err(e) => {
-                eprintln!("Error executing command '{}': {}", command, e);
-                return;
+                eprintln!("{}", format!("xinux: pipe: {}", e).red());
+                jobs.push(Job::Done(1));
+                previous_output = empty_input();
+                continue;
             }
         };
-
-        if i < commands.len() - 1 {
-            previous_output = output.stdout.take();
-        } else {
-            let _ = output.wait_with_output().map(|output| {
-                if !output.stdout.is_empty() {
-                    print!("{}", String::from_utf8_lossy(&output.stdout));
-                }
-                if !output.stderr.is_empty() {
-                    eprintln!("{}", String::from_utf8_lossy(&output.stderr));
-                }
-            });
+        let spawned = cmd.spawn();
+        // Our copies of the write ends must go, or the next stage never sees EOF
+        drop(cmd);
+        match spawned {
+            Ok(child) => {
+                previous_output = output;
+                jobs.push(Job::Spawned(child));
+            }
+            Err(e) => {
+                eprintln!("Error executing command '{}': {}", command, e);
+                jobs.push(Job::Done(127));
+                previous_output = empty_input();
+            }
         }
     }
+    jobs.into_iter().map(Job::wait).collect()
 }
 
+/// Parses and runs a line of input
 pub fn execute(raw_input: &str) {
-    let input = expand_variables(raw_input);
-
-    if input.contains('|') {
-        execute_piped_commands(&input);
-        return;
-    }
+    run_source(raw_input);
+}
 
-    let mut parts = input.trim().split_whitespace();
-    let cmd = match parts.next() {
-        Some(c) => c,
-        None => return,
+/// Builtin dispatcher for an already-expanded command line
+pub(crate) fn execute_argv(argv: &[String]) -> i32 {
+    let argv: Vec<&str> = argv.iter().map(String::as_str).collect();
+    let Some((&cmd, args)) = argv.split_first() else {
+        return 0;
     };
-    let args: Vec<&str> = parts.collect();
 
     match cmd {
         "cd" => {
             let default_home = env::var("HOME").unwrap_or_else(|_| "/".to_string());
-            let new_dir = args.get(0).map(|s| *s).unwrap_or(&default_home);
+            let new_dir = args.first().copied().unwrap_or(&default_home);
             let path = Path::new(new_dir);
             if let Err(e) = env::set_current_dir(path) {
                 eprintln!("cd: {}", e);
+                return 1;
             }
+            0
         }
 
-        "ls" | "la" => {
-            let show_hidden = cmd == "la";
-            let target_dir = args
-                .get(0)
-                .map(|s| {
-                    if s.starts_with('~') {
-                        s.replacen('~', &env::var("HOME").unwrap_or("/".into()), 1)
-                    } else {
-                        s.to_string()
-                    }
-                })
-                .unwrap_or_else(|| ".".to_string());
-
-            match fs::read_dir(&target_dir) {
-                Ok(entries) => {
-                    let mut files = vec![];
-
-                    for entry in entries.flatten() {
-                        let path = entry.path();
-                        let metadata = match entry.metadata() {
-                            Ok(m) => m,
-                            Err(_) => continue,
-                        };
-                        let name = entry.file_name().to_string_lossy().to_string();
-                        if !show_hidden && name.starts_with('.') {
-                            continue;
-                        }
-
-                        let ext = path
-                            .extension()
-                            .and_then(|e| e.to_str())
-                            .unwrap_or("")
-                            .to_lowercase();
-                        let file_type = if metadata.is_dir() {
-                            "[DIR]".blue().bold()
-                        } else if metadata.permissions().mode() & 0o111 != 0 {
-                            "[EXE]".green().bold()
-                        } else if ["mp4", "mkv", "webm"].contains(&ext.as_str()) {
-                            "[VIDEO]".bright_red()
-                        } else if ["mp3", "wav", "flac"].contains(&ext.as_str()) {
-                            "[AUDIO]".bright_cyan()
-                        } else if ["png", "jpg", "jpeg", "webp", "gif", "bmp", "tiff", "svg"]
-                            .contains(&ext.as_str())
-                        {
-                            "[IMG]".magenta()
-                        } else if ext == "rs" {
-                            "[RUST]".truecolor(255, 100, 50)
-                        } else if ext == "py" {
-                            "[PY]".yellow()
-                        } else if ["html", "htm"].contains(&ext.as_str()) {
-                            "[HTML]".bright_red()
-                        } else if ext == "htmx" {
-                            "[HTMX]".truecolor(255, 80, 80)
-                        } else if ["css", "scss", "sass"].contains(&ext.as_str()) {
-                            "[CSS]".truecolor(255, 105, 180)
-                        } else if ["js", "ts", "mjs", "jsx", "tsx"].contains(&ext.as_str()) {
-                            "[JS/TS]".yellow()
-                        } else if ext == "json" {
-                            "[JSON]".bright_yellow()
-                        } else if ["yaml", "yml"].contains(&ext.as_str()) {
-                            "[YAML]".bright_white()
-                        } else if ext == "lock" {
-                            "[LOCK]".bright_magenta()
-                        } else if ext == "toml" {
-                            "[TOML]".bright_blue()
-                        } else if ["txt", "md", "rst", "norg"].contains(&ext.as_str()) {
-                            "[TEXT]".white()
-                        } else if ext == "c" {
-                            "[C]".cyan()
-                        } else if ["cpp", "cc", "cxx", "h", "hpp"].contains(&ext.as_str()) {
-                            "[C++]".bright_cyan()
-                        } else if ext == "cs" {
-                            "[C#]".purple()
-                        } else if ext == "java" {
-                            "[JAVA]".red()
-                        } else if ["kt", "kts"].contains(&ext.as_str()) {
-                            "[KOTLIN]".truecolor(255, 100, 200)
-                        } else if ext == "go" {
-                            "[GO]".cyan()
-                        } else if ext == "rb" {
-                            "[RUBY]".red()
-                        } else if ext == "php" {
-                            "[PHP]".magenta()
-                        } else if ext == "swift" {
-                            "[SWIFT]".bright_white()
-                        } else if ext == "lua" {
-                            "[LUA]".blue()
-                        } else if ["sh", "bash", "zsh"].contains(&ext.as_str()) {
-                            "[SHELL]".bright_green()
-                        } else if ["asm", "s"].contains(&ext.as_str()) {
-                            "[ASM]".truecolor(180, 180, 180)
-                        } else if ext == "wasm" {
-                            "[WASM]".bright_blue()
-                        } else if ["zip", "tar", "gz", "xz", "7z", "rar"].contains(&ext.as_str()) {
-                            "[ARCH]".bright_yellow()
-                        } else if ext == "pdf" {
-                            "[PDF]".red()
-                        } else if ext == "log" {
-                            "[LOG]".truecolor(128, 128, 128)
-                        } else if ext == "exe" {
-                            "[EXE]".green()
-                        } else if ext == "msi" {
-                            "[INSTALL]".truecolor(255, 180, 0)
-                        } else if ext == "apk" {
-                            "[APK]".truecolor(80, 220, 80)
-                        } else if ext == "iso" {
-                            "[ISO]".truecolor(220, 220, 255)
-                        } else if ["db", "sqlite", "sql"].contains(&ext.as_str()) {
-                            "[DB]".truecolor(255, 100, 255)
-                        } else {
-                            "[FILE]".dimmed()
-                        };
-
-                        fn format_size(bytes: u64) -> String {
-                            const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
-                            let mut size = bytes as f64;
-                            let mut unit = 0;
-                            while size >= 1024.0 && unit < UNITS.len() - 1 {
-                                size /= 1024.0;
-                                unit += 1;
-                            }
-                            format!("{:.1} {}", size, UNITS[unit])
-                        }
-
-                        let size = format_size(metadata.len());
-                        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
-                        let datetime: DateTime<Local> = modified.into();
-                        let formatted_time = datetime.format("%Y-%m-%d %H:%M").to_string();
-
-                        files.push((name, file_type.to_string(), size, formatted_time));
-                    }
-
-                    files.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
-
-                    println!(
-                        "\n{} {}",
-                        "📁".bold(),
-                        target_dir
-                            .replace(&env::var("HOME").unwrap_or_default(), "~")
-                            .bright_white()
-                            .bold()
-                    );
-                    let count = files.len();
-
-                    for (i, (name, ftype, size, time)) in files.into_iter().enumerate() {
-                        let is_last = i == count - 1;
-                        let branch = if is_last { "└──" } else { "├──" };
-                        let pipe = if is_last { "    " } else { "│   " };
-
-                        println!("{} {}", branch, name.bright_white().bold());
-                        println!(
-                            "{} {}   {}   {}",
-                            pipe,
-                            size.bright_green(),
-                            ftype,
-                            time.dimmed()
-                        );
-                    }
-                }
-                Err(e) => eprintln!("{}", format!("ls: {}", e).red()),
-            }
-        }
+        "ls" | "la" => builtin_ls(cmd, args),
 
         "clear" => {
             clear().unwrap();
+            0
         }
-        "echo" => {
-            println!("{}", args.join(" "));
-        }
+        "echo" => builtin_echo(args),
 
-        _ => {
-            execute2(&input);
-        }
+        _ => execute2(&argv),
     }
 }
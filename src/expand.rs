@@ -0,0 +1,514 @@
+use colored::Colorize;
+use glob::{MatchOptions, Pattern};
+
+use crate::arith;
+use crate::parse_input::Word;
+use crate::state::{self, Value};
+
+/// One output word being assembled, with a glob-safe copy of its text
+#[derive(Default)]
+struct Field {
+    text: String,
+    pattern: String,
+    glob: bool,
+}
+
+struct Expander {
+    fields: Vec<Field>,
+    cur: Field,
+    started: bool,
+    split: bool,
+}
+
+impl Expander {
+    fn literal(&mut self, s: &str, quoted: bool) {
+        self.cur.text.push_str(s);
+        if quoted {
+            self.cur.pattern.push_str(&Pattern::escape(s));
+        } else {
+            self.cur.pattern.push_str(s);
+            if s.contains(['*', '?', '[']) {
+                self.cur.glob = true;
+            }
+        }
+        self.started = true;
+    }
+
+    fn finish_field(&mut self) {
+        if self.started {
+            self.fields.push(std::mem::take(&mut self.cur));
+        }
+        self.started = false;
+    }
+
+    /// Adds the result of an expansion, word-splitting it when unquoted
+    fn expansion(&mut self, value: &str, quoted: bool) {
+        if quoted || !self.split {
+            self.literal(value, true);
+            return;
+        }
+        let ifs = state::get_var("IFS").unwrap_or_else(|| " \t\n".into());
+        let is_sep = |c: char| ifs.contains(c);
+        if value.starts_with(is_sep) {
+            self.finish_field();
+        }
+        for (i, piece) in value.split(is_sep).filter(|p| !p.is_empty()).enumerate() {
+            if i > 0 {
+                self.finish_field();
+            }
+            self.literal(piece, false);
+        }
+        if value.ends_with(is_sep) {
+            self.finish_field();
+        }
+    }
+
+    /// Adds a list of values (`$@`, `${arr[@]}`): one field each when quoted
+    fn list(&mut self, items: &[String], quoted: bool) {
+        if !quoted || !self.split {
+            self.expansion(&items.join(" "), quoted);
+            return;
+        }
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.finish_field();
+            }
+            self.literal(item, true);
+        }
+    }
+
+    fn add(&mut self, value: Expanded, quoted: bool) {
+        match value {
+            Expanded::Scalar(s) => self.expansion(&s, quoted),
+            Expanded::List(items) => self.list(&items, quoted),
+        }
+    }
+}
+
+enum Expanded {
+    Scalar(String),
+    List(Vec<String>),
+}
+
+/// Finds the index of the delimiter closing a `$(`, `${` or backquote group
+/// that starts right before `start`.
+fn find_closing(chars: &[char], start: usize, open: char, close: char) -> usize {
+    let mut depth = 1;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' if open != '`' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+            }
+            '"' if open != '`' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            c if c == open => depth += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+fn expand(text: &str, split: bool) -> Vec<Field> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut ex = Expander {
+        fields: Vec::new(),
+        cur: Field::default(),
+        started: false,
+        split,
+    };
+    let mut i = 0;
+
+    if chars.first() == Some(&'~') {
+        let end = chars.iter().position(|&c| c == '/').unwrap_or(chars.len());
+        if end == 1
+            && let Some(home) = state::get_var("HOME")
+        {
+            ex.literal(&home, true);
+            i = 1;
+        }
+    }
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                match chars.get(i + 1) {
+                    Some(c) => ex.literal(&c.to_string(), true),
+                    None => ex.literal("\\", true),
+                }
+                i += 2;
+            }
+            '\'' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&c| c == '\'')
+                    .map_or(chars.len(), |p| i + 1 + p);
+                let inner: String = chars[i + 1..end].iter().collect();
+                ex.literal(&inner, true);
+                i = end + 1;
+            }
+            '"' => i = expand_double_quoted(&chars, i + 1, &mut ex),
+            '$' => i = expand_dollar(&chars, i, &mut ex, false),
+            '`' => {
+                let end = find_closing(&chars, i + 1, '`', '`');
+                let inner = backquote_source(&chars[i + 1..end]);
+                ex.expansion(&crate::eval::capture(&inner), false);
+                i = end + 1;
+            }
+            c => {
+                ex.literal(&c.to_string(), false);
+                i += 1;
+            }
+        }
+    }
+    ex.finish_field();
+    ex.fields
+}
+
+fn expand_double_quoted(chars: &[char], mut i: usize, ex: &mut Expander) -> usize {
+    let start = i;
+    while i < chars.len() && chars[i] != '"' {
+        match chars[i] {
+            '\\' => {
+                match chars.get(i + 1) {
+                    Some(&c) if matches!(c, '$' | '`' | '"' | '\\') => {
+                        ex.literal(&c.to_string(), true)
+                    }
+                    Some('\n') => {}
+                    Some(&c) => ex.literal(&format!("\\{}", c), true),
+                    None => ex.literal("\\", true),
+                }
+                i += 2;
+            }
+            '$' => i = expand_dollar(chars, i, ex, true),
+            '`' => {
+                let end = find_closing(chars, i + 1, '`', '`');
+                let inner = backquote_source(&chars[i + 1..end]);
+                ex.expansion(&crate::eval::capture(&inner), true);
+                i = end + 1;
+            }
+            c => {
+                ex.literal(&c.to_string(), true);
+                i += 1;
+            }
+        }
+    }
+    // `""` is an empty word; `"$@"` with no parameters adds nothing at all
+    if i == start {
+        ex.started = true;
+    }
+    i + 1
+}
+
+fn backquote_source(chars: &[char]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '\\' && matches!(chars.get(i + 1), Some('$' | '`' | '\\')) {
+            i += 1;
+        }
+        out.push(chars[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Expands the `$...` construct at `chars[i]`, returning the index after it
+fn expand_dollar(chars: &[char], i: usize, ex: &mut Expander, quoted: bool) -> usize {
+    match chars.get(i + 1) {
+        Some('(') => {
+            let end = find_closing(chars, i + 2, '(', ')');
+            let is_arith =
+                chars.get(i + 2) == Some(&'(') && end < chars.len() && chars[end - 1] == ')';
+            if is_arith {
+                let inner: String = chars[i + 3..end - 1].iter().collect();
+                match arith::evaluate(&expand_string(&inner)) {
+                    Ok(value) => ex.expansion(&value.to_string(), quoted),
+                    Err(e) => {
+                        eprintln!("{}", format!("xinux: {}: {}", inner.trim(), e).red());
+                        state::set_status(1);
+                    }
+                }
+                return end + 1;
+            }
+            let inner: String = chars[i + 2..end.min(chars.len())].iter().collect();
+            ex.expansion(&crate::eval::capture(&inner), quoted);
+            end + 1
+        }
+        Some('{') => {
+            let end = find_closing(chars, i + 2, '{', '}');
+            let inner: String = chars[i + 2..end.min(chars.len())].iter().collect();
+            let value = expand_parameter(&inner);
+            ex.add(value, quoted);
+            end + 1
+        }
+        Some(&c) if c == '_' || c.is_ascii_alphabetic() => {
+            let mut end = i + 1;
+            while end < chars.len() && (chars[end] == '_' || chars[end].is_ascii_alphanumeric()) {
+                end += 1;
+            }
+            let name: String = chars[i + 1..end].iter().collect();
+            ex.expansion(&state::get_var(&name).unwrap_or_default(), quoted);
+            end
+        }
+        Some(&c) if c.is_ascii_digit() || matches!(c, '@' | '*' | '#' | '?' | '$' | '!' | '-') => {
+            let value =
+                special_parameter(&c.to_string()).unwrap_or(Expanded::Scalar(String::new()));
+            ex.add(value, quoted);
+            i + 2
+        }
+        _ => {
+            ex.literal("$", true);
+            i + 1
+        }
+    }
+}
+
+/// `$0`..`$9`, `$@`, `$*`, `$#`, `$?`, `$$`, `$!`
+fn special_parameter(name: &str) -> Option<Expanded> {
+    let value = match name {
+        "@" => Expanded::List(state::positionals()),
+        "*" => Expanded::Scalar(state::positionals().join(" ")),
+        "#" => Expanded::Scalar(state::positionals().len().to_string()),
+        "?" => Expanded::Scalar(state::status().to_string()),
+        "$" => Expanded::Scalar(std::process::id().to_string()),
+        "!" => Expanded::Scalar(
+            state::with(|s| s.last_background)
+                .map(|pid| pid.to_string())
+                .unwrap_or_default(),
+        ),
+        "-" => Expanded::Scalar(String::new()),
+        "0" => Expanded::Scalar(state::with(|s| s.script_name.clone())),
+        n => {
+            let index: usize = n.parse().ok()?;
+            Expanded::Scalar(state::positionals().get(index.checked_sub(1)?)?.clone())
+        }
+    };
+    Some(value)
+}
+
+/// Looks up `name` or `name[index]`, returning `None` when unset
+fn lookup(name: &str, index: Option<&str>) -> Option<Expanded> {
+    if name.chars().all(|c| c.is_ascii_digit()) || matches!(name, "@" | "*" | "#" | "?" | "$") {
+        return special_parameter(name);
+    }
+    let value = state::get_value(name)?;
+    match (index, value) {
+        (None, v) => Some(Expanded::Scalar(v.as_scalar())),
+        (Some("@" | "*"), Value::Array(items)) => Some(Expanded::List(items)),
+        (Some("@" | "*"), Value::Str(s)) => Some(Expanded::List(vec![s])),
+        (Some(idx), v) => {
+            let idx: usize = expand_string(idx).trim().parse().ok()?;
+            match v {
+                Value::Array(items) => items.get(idx).cloned().map(Expanded::Scalar),
+                Value::Str(s) => (idx == 0).then_some(Expanded::Scalar(s)),
+            }
+        }
+    }
+}
+
+/// Expands the inside of `${...}`
+fn expand_parameter(inner: &str) -> Expanded {
+    // ${#name}, ${#name[@]}, ${#}
+    if let Some(rest) = inner.strip_prefix('#') {
+        if rest.is_empty() {
+            return Expanded::Scalar(state::positionals().len().to_string());
+        }
+        let (name, index) = split_index(rest);
+        let len = match lookup(name, index.as_deref()) {
+            Some(Expanded::List(items)) => items.len(),
+            Some(Expanded::Scalar(s)) => s.chars().count(),
+            None => 0,
+        };
+        return Expanded::Scalar(len.to_string());
+    }
+
+    let name_len = inner
+        .char_indices()
+        .find(|&(i, c)| {
+            if i == 0 {
+                !(c == '_' || c.is_ascii_alphanumeric() || matches!(c, '@' | '*' | '#' | '?' | '$'))
+            } else {
+                !(c == '_' || c.is_ascii_alphanumeric())
+            }
+        })
+        .map_or(inner.len(), |(i, _)| i);
+    let name = &inner[..name_len];
+    let mut rest = &inner[name_len..];
+    let mut index = None;
+    if rest.starts_with('[')
+        && let Some(close) = rest.find(']')
+    {
+        index = Some(rest[1..close].to_string());
+        rest = &rest[close + 1..];
+    }
+
+    let value = lookup(name, index.as_deref());
+    let is_empty = match &value {
+        None => true,
+        Some(Expanded::Scalar(s)) => s.is_empty(),
+        Some(Expanded::List(items)) => items.is_empty(),
+    };
+
+    let ops = [
+        ":-", ":=", ":+", ":?", "-", "=", "+", "?", "##", "#", "%%", "%",
+    ];
+    let Some(op) = ops.iter().find(|op| rest.starts_with(**op)) else {
+        return value.unwrap_or(Expanded::Scalar(String::new()));
+    };
+    let word = &rest[op.len()..];
+    let unset_or_null = if op.starts_with(':') {
+        is_empty
+    } else {
+        value.is_none()
+    };
+
+    match *op {
+        ":-" | "-" if unset_or_null => Expanded::Scalar(expand_string(word)),
+        ":=" | "=" if unset_or_null => {
+            let new = expand_string(word);
+            state::set_var(name, &new);
+            Expanded::Scalar(new)
+        }
+        ":+" | "+" => {
+            if unset_or_null {
+                Expanded::Scalar(String::new())
+            } else {
+                Expanded::Scalar(expand_string(word))
+            }
+        }
+        ":?" | "?" if unset_or_null => {
+            let message = if word.is_empty() {
+                "parameter null or not set".to_string()
+            } else {
+                expand_string(word)
+            };
+            eprintln!("xinux: {}: {}", name, message);
+            Expanded::Scalar(String::new())
+        }
+        "##" | "#" | "%%" | "%" => {
+            let pattern = Pattern::new(&expand_string(word)).ok();
+            let trim = |s: String| match &pattern {
+                Some(p) => trim_pattern(&s, p, op),
+                None => s,
+            };
+            match value {
+                Some(Expanded::List(items)) => {
+                    Expanded::List(items.into_iter().map(trim).collect())
+                }
+                Some(Expanded::Scalar(s)) => Expanded::Scalar(trim(s)),
+                None => Expanded::Scalar(String::new()),
+            }
+        }
+        _ => value.unwrap_or(Expanded::Scalar(String::new())),
+    }
+}
+
+fn split_index(text: &str) -> (&str, Option<String>) {
+    match text.find('[') {
+        Some(open) if text.ends_with(']') => (
+            &text[..open],
+            Some(text[open + 1..text.len() - 1].to_string()),
+        ),
+        _ => (text, None),
+    }
+}
+
+/// Removes the shortest/longest prefix (`#`, `##`) or suffix (`%`, `%%`) matching `pattern`
+fn trim_pattern(s: &str, pattern: &Pattern, op: &str) -> String {
+    let mut bounds: Vec<usize> = s.char_indices().map(|(i, _)| i).collect();
+    bounds.push(s.len());
+    let options = MatchOptions {
+        require_literal_separator: false,
+        ..MatchOptions::new()
+    };
+    match op {
+        "#" | "##" => {
+            let candidates: Box<dyn Iterator<Item = &usize>> = if op == "#" {
+                Box::new(bounds.iter())
+            } else {
+                Box::new(bounds.iter().rev())
+            };
+            for &end in candidates {
+                if pattern.matches_with(&s[..end], options) {
+                    return s[end..].to_string();
+                }
+            }
+        }
+        _ => {
+            let candidates: Box<dyn Iterator<Item = &usize>> = if op == "%" {
+                Box::new(bounds.iter().rev())
+            } else {
+                Box::new(bounds.iter())
+            };
+            for &start in candidates {
+                if pattern.matches_with(&s[start..], options) {
+                    return s[..start].to_string();
+                }
+            }
+        }
+    }
+    s.to_string()
+}
+
+fn glob_field(field: Field) -> Vec<String> {
+    if !field.glob {
+        return vec![field.text];
+    }
+    let options = MatchOptions {
+        require_literal_leading_dot: true,
+        ..MatchOptions::new()
+    };
+    let matches: Vec<String> = glob::glob_with(&field.pattern, options)
+        .map(|paths| {
+            paths
+                .flatten()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    if matches.is_empty() {
+        vec![field.text]
+    } else {
+        matches
+    }
+}
+
+/// Expands a word into zero or more arguments (splitting and globbing)
+pub fn expand_word(text: &str) -> Vec<String> {
+    expand(text, true)
+        .into_iter()
+        .flat_map(glob_field)
+        .collect()
+}
+
+pub fn expand_words(words: &[Word]) -> Vec<String> {
+    words.iter().flat_map(|w| expand_word(&w.text)).collect()
+}
+
+/// Expands a word into exactly one string, without splitting or globbing
+pub fn expand_string(text: &str) -> String {
+    expand(text, false)
+        .into_iter()
+        .map(|f| f.text)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,577 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use colored::{Color, ColoredString, Colorize};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+
+use crate::sniff::{self, Content};
+
+/// One table under `[file_types]` in `config.toml`. Naming a built-in type (`rust`,
+/// `image`, ...) changes only the fields given; any other name adds a type.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct FileTypeConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>,
+    /// Exact file names such as `Makefile`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+    /// Globs matched against the file name, such as `Dockerfile.*`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// How a file type is drawn
+#[derive(Clone, Copy, Default)]
+pub struct Style {
+    color: Option<Color>,
+    bold: bool,
+    dimmed: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Style {
+    /// Reads `bright_red`, `bold #ff6432`, `dimmed` and the like, or ANSI codes such
+    /// as `01;32` in the format of `LS_COLORS`
+    pub fn parse(spec: &str) -> Option<Style> {
+        let spec = spec.trim();
+        if !spec.is_empty() && spec.chars().all(|c| c.is_ascii_digit() || c == ';') {
+            return Some(Style::from_codes(spec));
+        }
+        let mut style = Style::default();
+        let mut words = spec.split_whitespace();
+        while let Some(word) = words.next() {
+            match word.to_lowercase().as_str() {
+                "bold" => style.bold = true,
+                "dim" | "dimmed" => style.dimmed = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "bright" => {
+                    let color = format!("bright {}", words.next()?);
+                    style.color = Some(color.parse().ok()?);
+                }
+                hex if hex.starts_with('#') && hex.len() == 7 => {
+                    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+                    style.color = Some(Color::TrueColor {
+                        r: channel(1)?,
+                        g: channel(3)?,
+                        b: channel(5)?,
+                    });
+                }
+                name => style.color = Some(name.replace('_', " ").parse().ok()?),
+            }
+        }
+        Some(style)
+    }
+
+    /// SGR parameters: attributes, the 16 colors, and `38;5;N` / `38;2;R;G;B`
+    fn from_codes(codes: &str) -> Style {
+        const BASIC: [Color; 8] = [
+            Color::Black,
+            Color::Red,
+            Color::Green,
+            Color::Yellow,
+            Color::Blue,
+            Color::Magenta,
+            Color::Cyan,
+            Color::White,
+        ];
+        const BRIGHT: [Color; 8] = [
+            Color::BrightBlack,
+            Color::BrightRed,
+            Color::BrightGreen,
+            Color::BrightYellow,
+            Color::BrightBlue,
+            Color::BrightMagenta,
+            Color::BrightCyan,
+            Color::BrightWhite,
+        ];
+        let mut style = Style::default();
+        let mut codes = codes.split(';').filter_map(|c| c.parse::<u8>().ok());
+        while let Some(code) = codes.next() {
+            match code {
+                1 => style.bold = true,
+                2 => style.dimmed = true,
+                3 => style.italic = true,
+                4 => style.underline = true,
+                30..=37 => style.color = Some(BASIC[usize::from(code - 30)]),
+                90..=97 => style.color = Some(BRIGHT[usize::from(code - 90)]),
+                38 => match codes.next() {
+                    Some(5) => {
+                        style.color = codes.next().map(|n| match n {
+                            0..=7 => BASIC[usize::from(n)],
+                            8..=15 => BRIGHT[usize::from(n - 8)],
+                            _ => xterm_color(n),
+                        })
+                    }
+                    Some(2) => {
+                        let (r, g, b) = (codes.next(), codes.next(), codes.next());
+                        if let (Some(r), Some(g), Some(b)) = (r, g, b) {
+                            style.color = Some(Color::TrueColor { r, g, b });
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        style
+    }
+
+    pub fn paint(&self, text: &str) -> ColoredString {
+        let mut out = match self.color {
+            Some(color) => text.color(color),
+            None => text.normal(),
+        };
+        if self.bold {
+            out = out.bold();
+        }
+        if self.dimmed {
+            out = out.dimmed();
+        }
+        if self.italic {
+            out = out.italic();
+        }
+        if self.underline {
+            out = out.underline();
+        }
+        out
+    }
+}
+
+/// Colors 16 to 255 of the xterm palette: a 6×6×6 cube, then 24 grays
+fn xterm_color(n: u8) -> Color {
+    if n >= 232 {
+        let level = 8 + (n - 232) * 10;
+        return Color::TrueColor {
+            r: level,
+            g: level,
+            b: level,
+        };
+    }
+    let n = n - 16;
+    let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+    Color::TrueColor {
+        r: level(n / 36),
+        g: level(n / 6 % 6),
+        b: level(n % 6),
+    }
+}
+
+pub struct FileType {
+    pub tag: String,
+    pub style: Style,
+    pub icon: String,
+    pub description: String,
+}
+
+impl FileType {
+    /// `[TAG]` in the type's colors, as `ls` shows it
+    pub fn label(&self) -> ColoredString {
+        self.style.paint(&format!("[{}]", self.tag))
+    }
+}
+
+/// A built-in type: key, tag, color, icon, description, extensions, exact names
+type Builtin = (
+    &'static str,
+    &'static str,
+    &'static str,
+    &'static str,
+    &'static str,
+    &'static [&'static str],
+    &'static [&'static str],
+);
+
+#[rustfmt::skip]
+const BUILTINS: &[Builtin] = &[
+    ("dir", "DIR", "blue bold", "📁", "Directory", &[], &[]),
+    ("link", "LINK", "cyan", "🔗", "Symbolic link", &[], &[]),
+    ("exe", "EXE", "green bold", "🚀", "Executable", &["exe", "bin", "out", "run"], &[]),
+    ("file", "FILE", "dimmed", "📦", "Unknown file type", &[], &[]),
+    ("binary", "BIN", "dimmed", "💾", "Binary data", &[], &[]),
+    ("object", "OBJ", "#c8c8c8", "🧱", "Object file", &["o", "obj"], &[]),
+    ("script", "SCRIPT", "bright_green", "📜", "Script", &[], &[]),
+    ("video", "VIDEO", "bright_red", "🎞️", "Video file", &["mp4", "mkv", "webm", "avi", "mov"], &[]),
+    ("audio", "AUDIO", "bright_cyan", "🎵", "Audio file", &["mp3", "wav", "flac", "ogg", "m4a"], &[]),
+    ("image", "IMG", "magenta", "🖼️", "Image file", &["png", "jpg", "jpeg", "webp", "gif", "bmp", "tiff", "svg", "ico"], &[]),
+    ("font", "FONT", "white", "🔤", "Font file", &["ttf", "otf", "woff", "woff2"], &[]),
+    ("rust", "RUST", "#ff6432", "🦀", "Rust source file", &["rs"], &[]),
+    ("cargo", "CARGO", "#ff6432", "📦", "Cargo manifest", &[], &["Cargo.toml"]),
+    ("python", "PY", "yellow", "🐍", "Python script", &["py"], &[]),
+    ("c", "C", "cyan", "💻", "C source file", &["c"], &[]),
+    ("cpp", "C++", "bright_cyan", "🔧", "C++ source file", &["cpp", "cc", "cxx", "h", "hpp"], &[]),
+    ("csharp", "C#", "magenta", "🎯", "C# source file", &["cs"], &[]),
+    ("zig", "ZIG", "#f7a41d", "⚡", "Zig source file", &["zig"], &[]),
+    ("go", "GO", "cyan", "🐹", "Go source file", &["go"], &[]),
+    ("java", "JAVA", "red", "☕", "Java source file", &["java"], &[]),
+    ("kotlin", "KOTLIN", "#ff64c8", "🅺", "Kotlin file", &["kt", "kts"], &[]),
+    ("swift", "SWIFT", "bright_white", "🕊️", "Swift file", &["swift"], &[]),
+    ("javascript", "JS/TS", "yellow", "🌐", "JavaScript file", &["js", "mjs", "cjs", "jsx"], &[]),
+    ("typescript", "JS/TS", "yellow", "🔷", "TypeScript file", &["ts", "tsx"], &[]),
+    ("ruby", "RUBY", "red", "💎", "Ruby script", &["rb"], &[]),
+    ("php", "PHP", "magenta", "🐘", "PHP script", &["php"], &[]),
+    ("lua", "LUA", "blue", "🌙", "Lua script", &["lua"], &[]),
+    ("perl", "PERL", "blue", "🧬", "Perl script", &["pl"], &[]),
+    ("r", "R", "blue", "📊", "R script", &["r"], &[]),
+    ("shell", "SHELL", "bright_green", "📜", "Shell script", &["sh", "bash", "zsh", "xsh"], &[]),
+    ("batch", "BATCH", "bright_green", "📄", "Batch file", &["bat", "cmd"], &[]),
+    ("powershell", "PS", "blue", "🖥️", "PowerShell script", &["ps1"], &[]),
+    ("asm", "ASM", "#b4b4b4", "🏗️", "Assembly source", &["asm", "s"], &[]),
+    ("verilog", "VERILOG", "#b4b4b4", "📶", "Verilog/SystemVerilog", &["v", "vh", "sv"], &[]),
+    ("wasm", "WASM", "bright_blue", "🌐", "WebAssembly", &["wasm"], &[]),
+    ("html", "HTML", "bright_red", "📰", "HTML file", &["html", "htm"], &[]),
+    ("htmx", "HTMX", "#ff5050", "📰", "HTMX file", &["htmx"], &[]),
+    ("css", "CSS", "#ff69b4", "🎨", "Stylesheet", &["css", "scss", "sass"], &[]),
+    ("xml", "XML", "bright_red", "🧾", "XML file", &["xml"], &[]),
+    ("markdown", "TEXT", "white", "📝", "Markdown", &["md", "markdown"], &[]),
+    ("rst", "TEXT", "white", "🔠", "reStructuredText", &["rst"], &[]),
+    ("asciidoc", "TEXT", "white", "📘", "AsciiDoc", &["adoc"], &[]),
+    ("text", "TEXT", "white", "📄", "Text file", &["txt", "norg"], &[]),
+    ("license", "LICENSE", "bright_white", "📜", "License", &[], &["LICENSE", "LICENSE.md", "LICENSE.txt", "COPYING"]),
+    ("json", "JSON", "bright_yellow", "🗂️", "JSON data", &["json"], &[]),
+    ("toml", "TOML", "bright_blue", "⚙️", "TOML config", &["toml"], &[]),
+    ("yaml", "YAML", "bright_white", "⚙️", "YAML config", &["yaml", "yml"], &[]),
+    ("ini", "CONF", "bright_blue", "🔧", "INI config", &["ini"], &[]),
+    ("config", "CONF", "bright_blue", "🧩", "Configuration file", &["cfg", "conf"], &[]),
+    ("env", "ENV", "green", "🌱", "Environment config", &["env"], &[".env"]),
+    ("git", "GIT", "#f05032", "🌱", "Git configuration", &[], &[".gitignore", ".gitattributes", ".gitmodules"]),
+    ("spreadsheet", "CSV", "bright_green", "📈", "Spreadsheet", &["csv", "tsv"], &[]),
+    ("make", "MAKE", "#ff8c00", "🧱", "Makefile", &["mk"], &["Makefile", "makefile", "GNUmakefile"]),
+    ("build", "BUILD", "#ff8c00", "🏗️", "Build script", &["gradle"], &["pom.xml", "CMakeLists.txt", "meson.build", "build.ninja"]),
+    ("docker", "DOCKER", "blue", "🐳", "Dockerfile", &["dockerfile"], &["Dockerfile", "Containerfile"]),
+    ("lock", "LOCK", "bright_magenta", "🔒", "Lock file", &["lock"], &[]),
+    ("archive", "ARCH", "bright_yellow", "🗜️", "Archive file", &["zip", "tar", "gz", "tgz", "bz2", "xz", "7z", "rar"], &[]),
+    ("pdf", "PDF", "red", "📕", "PDF document", &["pdf"], &[]),
+    ("log", "LOG", "#808080", "🧾", "Log file", &["log"], &[]),
+    ("installer", "INSTALL", "#ffb400", "📦", "Installer", &["msi"], &[]),
+    ("apk", "APK", "#50dc50", "📦", "Android package", &["apk"], &[]),
+    ("package", "PKG", "#50dc50", "📦", "Package file", &["pkg", "deb", "rpm", "appimage"], &[]),
+    ("disk", "ISO", "#dcdcff", "💽", "Disk image", &["iso", "img"], &[]),
+    ("database", "DB", "#ff64ff", "🗄️", "Database file", &["db", "sqlite", "sql", "mdb", "accdb"], &[]),
+    ("library", "LIB", "#c8c8c8", "📚", "Dynamic library", &["dll", "so", "dylib"], &[]),
+    ("backup", "BAK", "dimmed", "💾", "Backup file", &["bak", "old"], &[]),
+    ("rom", "ROM", "magenta", "🕹️", "ROM file", &["cue", "rom"], &[]),
+];
+
+/// Patterns of the built-in types, checked after exact names
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    ("docker", "Dockerfile.*"),
+    ("env", ".env.*"),
+    ("license", "LICENSE-*"),
+];
+
+struct Registry {
+    kinds: HashMap<String, Rc<FileType>>,
+    names: HashMap<String, Rc<FileType>>,
+    patterns: Vec<(Pattern, Rc<FileType>)>,
+    extensions: HashMap<String, Rc<FileType>>,
+    /// What the registry was built from: the config file's modification time and `LS_COLORS`
+    source: (Option<SystemTime>, Option<String>),
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Option<Rc<Registry>>> = const { RefCell::new(None) };
+}
+
+/// The type of a file named `name`. With metadata, directories, links and executables
+/// are told apart first; without it (as for `touch`), only the name counts.
+pub fn classify(name: &str, metadata: Option<&Metadata>) -> Rc<FileType> {
+    let registry = registry();
+    let kind = metadata.and_then(|m| {
+        if m.is_dir() {
+            Some("dir")
+        } else if m.file_type().is_symlink() {
+            Some("link")
+        } else if m.permissions().mode() & 0o111 != 0 {
+            Some("exe")
+        } else {
+            None
+        }
+    });
+    if let Some(kind) = kind {
+        return registry.kinds[kind].clone();
+    }
+    registry
+        .lookup(name)
+        .unwrap_or_else(|| registry.kinds["file"].clone())
+}
+
+/// Like `classify`, but for regular files the first bytes have the last word: binaries,
+/// scripts and images are recognized whatever their name and mode
+pub fn classify_content(path: &Path, name: &str, metadata: &Metadata) -> Rc<FileType> {
+    let Some(content) = sniff::content(path, metadata) else {
+        return classify(name, Some(metadata));
+    };
+    let registry = registry();
+    match content {
+        Content::Specific(key) => registry.kinds[key].clone(),
+        Content::Generic(_) if metadata.permissions().mode() & 0o111 != 0 => {
+            registry.kinds["exe"].clone()
+        }
+        Content::Generic(key) => registry
+            .lookup(name)
+            .unwrap_or_else(|| registry.kinds[key].clone()),
+    }
+}
+
+impl Registry {
+    /// Exact names first, then patterns, then the longest matching extension
+    fn lookup(&self, name: &str) -> Option<Rc<FileType>> {
+        let lower = name.to_lowercase();
+        if let Some(kind) = self.names.get(&lower) {
+            return Some(kind.clone());
+        }
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        if let Some((_, kind)) = self
+            .patterns
+            .iter()
+            .find(|(p, _)| p.matches_with(name, options))
+        {
+            return Some(kind.clone());
+        }
+        // `archive.tar.gz` tries `tar.gz` before `gz`; a leading dot is not an extension
+        lower
+            .char_indices()
+            .skip(1)
+            .filter(|&(_, c)| c == '.')
+            .find_map(|(i, _)| self.extensions.get(&lower[i + 1..]))
+            .cloned()
+    }
+}
+
+fn registry() -> Rc<Registry> {
+    let config_path = crate::config_file_path();
+    let source = (
+        fs::metadata(&config_path).and_then(|m| m.modified()).ok(),
+        env::var("LS_COLORS").ok(),
+    );
+    if let Some(registry) = REGISTRY.with(|r| r.borrow().clone())
+        && registry.source == source
+    {
+        return registry;
+    }
+    let user = crate::read_config()
+        .map(|config| config.file_types)
+        .unwrap_or_default();
+    let registry = Rc::new(build(user, source));
+    REGISTRY.with(|r| *r.borrow_mut() = Some(registry.clone()));
+    registry
+}
+
+fn build(
+    user: BTreeMap<String, FileTypeConfig>,
+    source: (Option<SystemTime>, Option<String>),
+) -> Registry {
+    let mut defs: Vec<(String, FileTypeConfig, bool)> = BUILTINS
+        .iter()
+        .map(|&(key, tag, color, icon, description, extensions, names)| {
+            let patterns = BUILTIN_PATTERNS
+                .iter()
+                .filter(|(k, _)| *k == key)
+                .map(|(_, p)| p.to_string())
+                .collect();
+            let def = FileTypeConfig {
+                extensions: extensions.iter().map(|e| e.to_string()).collect(),
+                names: names.iter().map(|n| n.to_string()).collect(),
+                patterns,
+                tag: Some(tag.to_string()),
+                color: Some(color.to_string()),
+                icon: Some(icon.to_string()),
+                description: Some(description.to_string()),
+            };
+            (key.to_string(), def, false)
+        })
+        .collect();
+    for (key, config) in user {
+        match defs.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, def, is_user)) => {
+                def.extensions.extend(config.extensions);
+                def.names.extend(config.names);
+                def.patterns.splice(0..0, config.patterns);
+                def.tag = config.tag.or(def.tag.take());
+                def.color = config.color.or(def.color.take());
+                def.icon = config.icon.or(def.icon.take());
+                def.description = config.description.or(def.description.take());
+                *is_user = true;
+            }
+            None => defs.push((key, config, true)),
+        }
+    }
+    // Types from the config file claim their names and extensions over the built-in ones
+    defs.sort_by_key(|(_, _, is_user)| *is_user);
+
+    let mut registry = Registry {
+        kinds: HashMap::new(),
+        names: HashMap::new(),
+        patterns: Vec::new(),
+        extensions: HashMap::new(),
+        source,
+    };
+    let mut user_patterns = Vec::new();
+    for (key, def, is_user) in defs {
+        let style = def.color.as_deref().map_or(Some(Style::default()), |spec| {
+            let style = Style::parse(spec);
+            if style.is_none() {
+                eprintln!(
+                    "{}",
+                    format!("xinux: file_types.{}: unknown color `{}`", key, spec).red()
+                );
+            }
+            style
+        });
+        let kind = Rc::new(FileType {
+            tag: def.tag.unwrap_or_else(|| key.to_uppercase()),
+            style: style.unwrap_or_default(),
+            icon: def.icon.unwrap_or_else(|| "📄".to_string()),
+            description: def.description.unwrap_or_else(|| format!("{} file", key)),
+        });
+        for name in def.names {
+            registry.names.insert(name.to_lowercase(), kind.clone());
+        }
+        let patterns = def.patterns.iter().filter_map(|p| match Pattern::new(p) {
+            Ok(pattern) => Some((pattern, kind.clone())),
+            Err(e) => {
+                let message = format!("xinux: file_types.{}: bad pattern `{}`: {}", key, p, e);
+                eprintln!("{}", message.red());
+                None
+            }
+        });
+        if is_user {
+            user_patterns.extend(patterns);
+        } else {
+            registry.patterns.extend(patterns);
+        }
+        for ext in def.extensions {
+            let ext = ext.trim_start_matches('.').to_lowercase();
+            registry.extensions.insert(ext, kind.clone());
+        }
+        registry.kinds.insert(key, kind);
+    }
+    registry.patterns.splice(0..0, user_patterns);
+
+    // `LS_COLORS` fills in extensions nothing else knows, tagged with the extension itself
+    if let Some(ls_colors) = &registry.source.1 {
+        for item in ls_colors.split(':') {
+            let Some((glob, codes)) = item.split_once('=') else {
+                continue;
+            };
+            let Some(ext) = glob.strip_prefix("*.") else {
+                continue;
+            };
+            let ext = ext.to_lowercase();
+            if ext.is_empty() || registry.extensions.contains_key(&ext) {
+                continue;
+            }
+            let kind = Rc::new(FileType {
+                tag: ext.to_uppercase(),
+                style: Style::from_codes(codes),
+                icon: "📄".to_string(),
+                description: format!("{} file", ext.to_uppercase()),
+            });
+            registry.extensions.insert(ext, kind);
+        }
+    }
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(types: &[(&str, FileTypeConfig)]) -> BTreeMap<String, FileTypeConfig> {
+        types
+            .iter()
+            .map(|(key, config)| (key.to_string(), config.clone()))
+            .collect()
+    }
+
+    fn tag(registry: &Registry, name: &str) -> Option<String> {
+        registry.lookup(name).map(|kind| kind.tag.clone())
+    }
+
+    #[test]
+    fn names_then_patterns_then_the_longest_extension() {
+        let registry = build(BTreeMap::new(), (None, None));
+        assert_eq!(tag(&registry, "Cargo.toml").as_deref(), Some("CARGO"));
+        assert_eq!(tag(&registry, "other.toml").as_deref(), Some("TOML"));
+        assert_eq!(tag(&registry, "MAKEFILE").as_deref(), Some("MAKE"));
+        assert_eq!(tag(&registry, "Dockerfile.dev").as_deref(), Some("DOCKER"));
+        assert_eq!(tag(&registry, ".env.local").as_deref(), Some("ENV"));
+        assert_eq!(tag(&registry, "photo.backup.PNG").as_deref(), Some("IMG"));
+        // A leading dot starts a hidden name, not an extension
+        assert_eq!(tag(&registry, ".rs"), None);
+        assert_eq!(tag(&registry, "README"), None);
+    }
+
+    #[test]
+    fn the_config_file_changes_and_adds_types() {
+        let registry = build(
+            user(&[
+                (
+                    "rust",
+                    FileTypeConfig {
+                        tag: Some("RS".into()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "tarball",
+                    FileTypeConfig {
+                        extensions: vec![".tar.gz".into()],
+                        patterns: vec!["Dockerfile.tar*".into()],
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "notes",
+                    FileTypeConfig {
+                        extensions: vec!["md".into()],
+                        names: vec!["TODO".into()],
+                        ..Default::default()
+                    },
+                ),
+            ]),
+            (None, None),
+        );
+        let rust = registry.lookup("main.rs").unwrap();
+        assert_eq!(rust.tag, "RS");
+        assert_eq!(rust.description, "Rust source file");
+        assert_eq!(tag(&registry, "src.tar.gz").as_deref(), Some("TARBALL"));
+        assert_eq!(tag(&registry, "src.gz").as_deref(), Some("ARCH"));
+        assert_eq!(
+            tag(&registry, "Dockerfile.tarball").as_deref(),
+            Some("TARBALL")
+        );
+        assert_eq!(tag(&registry, "README.md").as_deref(), Some("NOTES"));
+        assert_eq!(tag(&registry, "todo").as_deref(), Some("NOTES"));
+        assert_eq!(registry.kinds["notes"].description, "notes file");
+    }
+
+    #[test]
+    fn ls_colors_only_fills_in_unknown_extensions() {
+        let ls_colors = "di=01;34:*.xyz=01;32:*.rs=31:*.=33".to_string();
+        let registry = build(BTreeMap::new(), (None, Some(ls_colors)));
+        assert_eq!(tag(&registry, "a.xyz").as_deref(), Some("XYZ"));
+        assert_eq!(tag(&registry, "a.rs").as_deref(), Some("RUST"));
+        assert_eq!(tag(&registry, "a."), None);
+    }
+}
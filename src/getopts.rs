@@ -0,0 +1,199 @@
+use colored::Colorize;
+
+use crate::state;
+
+fn error(message: String) {
+    eprintln!("{}", format!("xinux: getopts: {}", message).red());
+}
+
+/// `getopts optstring name [args...]`: parses one option per call, keeping
+/// its place in `OPTIND` and putting option values in `OPTARG`.
+/// A leading `:` in the optstring reports problems through `name` instead of stderr.
+pub fn builtin_getopts(args: &[String]) -> i32 {
+    let [optstring, name, rest @ ..] = args else {
+        eprintln!("Usage: getopts optstring name [args...]");
+        return 2;
+    };
+    if !state::is_valid_name(name) {
+        error(format!("`{}`: not a valid identifier", name));
+        return 2;
+    }
+    let params = if rest.is_empty() {
+        state::positionals()
+    } else {
+        rest.to_vec()
+    };
+    let (silent, spec) = match optstring.strip_prefix(':') {
+        Some(spec) => (true, spec),
+        None => (false, optstring.as_str()),
+    };
+    let report = !silent && state::get_var("OPTERR").is_none_or(|v| v != "0");
+
+    let optind = state::get_var("OPTIND")
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|&n| n >= 1)
+        .unwrap_or(1);
+    let mut offset = state::with(|s| match s.getopts_pos {
+        (index, offset) if index == optind => offset,
+        _ => 0,
+    });
+
+    let finish = |next: usize, offset: usize| {
+        state::set_var("OPTIND", &next.to_string());
+        state::with(|s| s.getopts_pos = (next, offset));
+    };
+
+    let Some(arg) = params.get(optind - 1) else {
+        state::set_var(name, "?");
+        finish(optind, 0);
+        return 1;
+    };
+    if offset == 0 {
+        if arg == "--" {
+            state::set_var(name, "?");
+            finish(optind + 1, 0);
+            return 1;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            state::set_var(name, "?");
+            finish(optind, 0);
+            return 1;
+        }
+        offset = 1;
+    }
+
+    let chars: Vec<char> = arg.chars().collect();
+    let opt = chars[offset];
+    offset += 1;
+    let group_done = offset >= chars.len();
+    let (next, next_offset) = if group_done {
+        (optind + 1, 0)
+    } else {
+        (optind, offset)
+    };
+
+    let takes_arg = match spec.find(opt).filter(|_| opt != ':') {
+        Some(i) => spec[i + opt.len_utf8()..].starts_with(':'),
+        None => {
+            if report {
+                error(format!("illegal option -- {}", opt));
+            }
+            state::set_var(name, "?");
+            if silent {
+                state::set_var("OPTARG", &opt.to_string());
+            } else {
+                state::unset_var("OPTARG");
+            }
+            finish(next, next_offset);
+            return 0;
+        }
+    };
+
+    if !takes_arg {
+        state::set_var(name, &opt.to_string());
+        state::unset_var("OPTARG");
+        finish(next, next_offset);
+        return 0;
+    }
+
+    // The value is the rest of this word, or else the next argument
+    if !group_done {
+        state::set_var("OPTARG", &chars[offset..].iter().collect::<String>());
+        state::set_var(name, &opt.to_string());
+        finish(optind + 1, 0);
+        return 0;
+    }
+    match params.get(optind) {
+        Some(value) => {
+            state::set_var("OPTARG", value);
+            state::set_var(name, &opt.to_string());
+            finish(optind + 2, 0);
+        }
+        None => {
+            if silent {
+                state::set_var(name, ":");
+                state::set_var("OPTARG", &opt.to_string());
+            } else {
+                if report {
+                    error(format!("option requires an argument -- {}", opt));
+                }
+                state::set_var(name, "?");
+                state::unset_var("OPTARG");
+            }
+            finish(optind + 1, 0);
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every call until getopts says it is done: the option, `OPTARG` and `OPTIND` after it
+    fn parse_all(optstring: &str, args: &[&str]) -> (Vec<(String, String, String)>, String) {
+        state::set_var("OPTIND", "1");
+        state::with(|s| s.getopts_pos = (0, 0));
+        let mut call = vec![optstring.to_string(), "opt".to_string()];
+        call.extend(args.iter().map(|a| a.to_string()));
+        let mut seen = Vec::new();
+        while builtin_getopts(&call) == 0 {
+            seen.push((
+                state::get_var("opt").unwrap_or_default(),
+                state::get_var("OPTARG").unwrap_or_default(),
+                state::get_var("OPTIND").unwrap_or_default(),
+            ));
+            assert!(seen.len() < 20, "getopts never finished");
+        }
+        (seen, state::get_var("OPTIND").unwrap_or_default())
+    }
+
+    fn found(items: &[(&str, &str, &str)]) -> Vec<(String, String, String)> {
+        items
+            .iter()
+            .map(|(a, b, c)| (a.to_string(), b.to_string(), c.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn grouped_flags_advance_optind_after_the_group() {
+        let (seen, optind) = parse_all("abc", &["-abc", "file"]);
+        assert_eq!(
+            seen,
+            found(&[("a", "", "1"), ("b", "", "1"), ("c", "", "2")])
+        );
+        // OPTIND is left on the first operand
+        assert_eq!(optind, "2");
+    }
+
+    #[test]
+    fn values_come_from_the_rest_of_the_group_or_the_next_word() {
+        let (seen, optind) = parse_all("ab:c:", &["-ab1", "-c", "two", "-a", "rest"]);
+        assert_eq!(
+            seen,
+            found(&[
+                ("a", "", "1"),
+                ("b", "1", "2"),
+                ("c", "two", "4"),
+                ("a", "", "5")
+            ])
+        );
+        assert_eq!(optind, "5");
+    }
+
+    #[test]
+    fn double_dash_and_operands_end_the_options() {
+        let (seen, optind) = parse_all("a", &["-a", "--", "-a"]);
+        assert_eq!(seen.len(), 1);
+        assert_eq!(optind, "3");
+        let (seen, optind) = parse_all("a", &["x", "-a"]);
+        assert!(seen.is_empty());
+        assert_eq!(optind, "1");
+    }
+
+    #[test]
+    fn silent_mode_reports_problems_through_the_variable() {
+        let (seen, _) = parse_all(":ab:", &["-x", "-b"]);
+        assert_eq!(seen, found(&[("?", "x", "2"), (":", "b", "3")]));
+    }
+}
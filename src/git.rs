@@ -0,0 +1,796 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use colored::{ColoredString, Colorize};
+use glob::{MatchOptions, Pattern};
+
+use crate::git_objects::{self, Head, Id, Store};
+
+/// Where an entry stands in its repository, as `ls --git` shows it
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+    Clean,
+    Modified,
+    Staged,
+    StagedModified,
+    Untracked,
+    Ignored,
+    Conflicted,
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Clean => "clean",
+            Status::Modified => "modified",
+            Status::Staged => "staged",
+            Status::StagedModified => "staged+modified",
+            Status::Untracked => "untracked",
+            Status::Ignored => "ignored",
+            Status::Conflicted => "conflicted",
+        }
+    }
+
+    /// The status word for listings; clean entries show nothing
+    pub fn tag(self) -> ColoredString {
+        match self {
+            Status::Clean => "".normal(),
+            Status::Modified => self.name().yellow(),
+            Status::Staged => self.name().green(),
+            Status::StagedModified => self.name().bright_yellow(),
+            Status::Untracked => self.name().red(),
+            Status::Ignored => self.name().dimmed(),
+            Status::Conflicted => self.name().bright_red().bold(),
+        }
+    }
+
+    /// Two letters in the style of `git status --short`, for the `-1` and `-C` layouts
+    pub fn code(self) -> ColoredString {
+        match self {
+            Status::Clean => "  ".normal(),
+            Status::Modified => " M".yellow(),
+            Status::Staged => "M ".green(),
+            Status::StagedModified => "MM".bright_yellow(),
+            Status::Untracked => "??".red(),
+            Status::Ignored => "!!".dimmed(),
+            Status::Conflicted => "UU".bright_red().bold(),
+        }
+    }
+}
+
+/// What the index records about a tracked file
+struct IndexEntry {
+    mtime: (u32, u32),
+    size: u32,
+    mode: u32,
+    id: Id,
+}
+
+/// The parts of the index a listing needs
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, IndexEntry>,
+    conflicted: HashSet<String>,
+    /// Tree ids of directories the index has not changed since they were last
+    /// written, from the cache-tree extension; `""` is the root
+    trees: HashMap<String, Id>,
+}
+
+/// One line of a `.gitignore`
+struct Rule {
+    pattern: Pattern,
+    negate: bool,
+    dir_only: bool,
+    /// Matched against the whole path below its file's directory, not just the name
+    anchored: bool,
+}
+
+struct Repo {
+    root: PathBuf,
+    entries: HashMap<String, IndexEntry>,
+    conflicted: HashSet<String>,
+    staged: HashSet<String>,
+    /// Rules from each directory's `.gitignore`, keyed by the directory's path in the repo
+    ignores: RefCell<HashMap<String, Rc<Vec<Rule>>>>,
+    /// `info/exclude` and the user's global ignore file
+    excludes: Vec<Rule>,
+}
+
+thread_local! {
+    static REPOS: RefCell<HashMap<PathBuf, Option<Rc<Repo>>>> = RefCell::new(HashMap::new());
+}
+
+/// Drops what was read for earlier listings, so every `ls` sees the repository as it is now
+pub fn forget() {
+    REPOS.with(|r| r.borrow_mut().clear());
+}
+
+/// The status of `path`, or `None` outside a repository
+pub fn status(path: &Path, metadata: &Metadata) -> Option<Status> {
+    let path = fs::canonicalize(path.parent()?)
+        .ok()?
+        .join(path.file_name()?);
+    let repo = repo_for(path.parent()?)?;
+    let relative = path.strip_prefix(&repo.root).ok()?.to_str()?.to_string();
+    if relative.is_empty() || relative == ".git" || relative.starts_with(".git/") {
+        return None;
+    }
+    Some(repo.status(&relative, metadata))
+}
+
+fn repo_for(dir: &Path) -> Option<Rc<Repo>> {
+    if let Some(known) = REPOS.with(|r| r.borrow().get(dir).cloned()) {
+        return known;
+    }
+    let repo = dir.ancestors().find_map(|root| {
+        let dot_git = root.join(".git");
+        let git_dir = if dot_git.is_dir() {
+            dot_git
+        } else {
+            // Worktrees and submodules have a `.git` file pointing at the real one
+            let text = fs::read_to_string(&dot_git).ok()?;
+            root.join(text.strip_prefix("gitdir:")?.trim())
+        };
+        // Share one `Repo` between all directories of the same repository
+        if let Some(Some(repo)) = REPOS.with(|r| r.borrow().get(root).cloned()) {
+            return Some(repo);
+        }
+        let repo = Rc::new(Repo::open(root, &git_dir));
+        REPOS.with(|r| {
+            r.borrow_mut()
+                .insert(root.to_path_buf(), Some(repo.clone()))
+        });
+        Some(repo)
+    });
+    REPOS.with(|r| r.borrow_mut().insert(dir.to_path_buf(), repo.clone()));
+    repo
+}
+
+impl Repo {
+    fn open(root: &Path, git_dir: &Path) -> Repo {
+        let index = fs::read(git_dir.join("index"))
+            .ok()
+            .and_then(|data| parse_index(&data))
+            .unwrap_or_default();
+        let mut excludes = Vec::new();
+        if let Ok(text) = fs::read_to_string(git_dir.join("info/exclude")) {
+            excludes.extend(parse_ignore(&text));
+        }
+        if let Some(config) = dirs::config_dir()
+            && let Ok(text) = fs::read_to_string(config.join("git/ignore"))
+        {
+            excludes.extend(parse_ignore(&text));
+        }
+        Repo {
+            root: root.to_path_buf(),
+            staged: staged_paths(git_dir, &index),
+            entries: index.entries,
+            conflicted: index.conflicted,
+            ignores: RefCell::new(HashMap::new()),
+            excludes,
+        }
+    }
+
+    fn status(&self, relative: &str, metadata: &Metadata) -> Status {
+        if metadata.is_dir() {
+            return self.dir_status(relative);
+        }
+        if self.conflicted.contains(relative) {
+            return Status::Conflicted;
+        }
+        let Some(entry) = self.entries.get(relative) else {
+            return if self.is_ignored(relative, false) {
+                Status::Ignored
+            } else {
+                Status::Untracked
+            };
+        };
+        let modified = self.is_modified(relative, entry, metadata);
+        match (self.staged.contains(relative), modified) {
+            (true, true) => Status::StagedModified,
+            (true, false) => Status::Staged,
+            (false, true) => Status::Modified,
+            (false, false) => Status::Clean,
+        }
+    }
+
+    /// A directory takes the most pressing status of the tracked files below it
+    fn dir_status(&self, relative: &str) -> Status {
+        let prefix = format!("{}/", relative);
+        if self.conflicted.iter().any(|p| p.starts_with(&prefix)) {
+            return Status::Conflicted;
+        }
+        let mut tracked = false;
+        let mut modified = false;
+        for (path, entry) in &self.entries {
+            if !path.starts_with(&prefix) {
+                continue;
+            }
+            tracked = true;
+            if !modified {
+                modified = match fs::symlink_metadata(self.root.join(path)) {
+                    Ok(metadata) => self.is_modified(path, entry, &metadata),
+                    Err(_) => true,
+                };
+            }
+        }
+        if !tracked {
+            return if self.is_ignored(relative, true) {
+                Status::Ignored
+            } else {
+                Status::Untracked
+            };
+        }
+        let staged = self.staged.iter().any(|p| p.starts_with(&prefix));
+        match (staged, modified) {
+            (true, true) => Status::StagedModified,
+            (true, false) => Status::Staged,
+            (false, true) => Status::Modified,
+            (false, false) => Status::Clean,
+        }
+    }
+
+    /// Compares the file with the index: size and mode first, then the modification
+    /// time, and only when that differs the content itself
+    fn is_modified(&self, relative: &str, entry: &IndexEntry, metadata: &Metadata) -> bool {
+        let executable = metadata.permissions().mode() & 0o111 != 0;
+        let mode_changed = match entry.mode & 0o170000 {
+            0o120000 => !metadata.file_type().is_symlink(),
+            _ => executable != (entry.mode & 0o111 != 0),
+        };
+        if mode_changed || metadata.size() as u32 != entry.size {
+            return true;
+        }
+        if (metadata.mtime() as u32, metadata.mtime_nsec() as u32) == entry.mtime {
+            return false;
+        }
+        let path = self.root.join(relative);
+        let content = if metadata.file_type().is_symlink() {
+            fs::read_link(&path).map(|target| target.into_os_string().into_encoded_bytes())
+        } else {
+            fs::read(&path)
+        };
+        match content {
+            Ok(content) => blob_id(&content) != entry.id,
+            Err(_) => true,
+        }
+    }
+
+    /// Whether `.gitignore` rules leave out `relative`, or a directory it is in
+    fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        let parts: Vec<&str> = relative.split('/').collect();
+        (1..=parts.len()).any(|depth| {
+            let dir_only_ok = depth < parts.len() || is_dir;
+            self.matches_rules(&parts[..depth], dir_only_ok)
+        })
+    }
+
+    /// Applies the rules that can see `parts` (last match wins)
+    fn matches_rules(&self, parts: &[&str], is_dir: bool) -> bool {
+        let mut ignored = None;
+        let mut check = |rules: &[Rule], below: &[&str]| {
+            let path = below.join("/");
+            let name = below.last().copied().unwrap_or_default();
+            let options = MatchOptions {
+                require_literal_separator: true,
+                ..MatchOptions::new()
+            };
+            for rule in rules {
+                if rule.dir_only && !is_dir {
+                    continue;
+                }
+                let subject = if rule.anchored { path.as_str() } else { name };
+                if rule.pattern.matches_with(subject, options) {
+                    ignored = Some(!rule.negate);
+                }
+            }
+        };
+        check(&self.excludes, parts);
+        for depth in 0..parts.len() {
+            let dir = parts[..depth].join("/");
+            check(&self.rules_in(&dir), &parts[depth..]);
+        }
+        ignored.unwrap_or(false)
+    }
+
+    fn rules_in(&self, dir: &str) -> Rc<Vec<Rule>> {
+        if let Some(rules) = self.ignores.borrow().get(dir) {
+            return rules.clone();
+        }
+        let rules = Rc::new(
+            fs::read_to_string(self.root.join(dir).join(".gitignore"))
+                .map(|text| parse_ignore(&text))
+                .unwrap_or_default(),
+        );
+        self.ignores
+            .borrow_mut()
+            .insert(dir.to_string(), rules.clone());
+        rules
+    }
+}
+
+/// Paths whose index entry differs from HEAD's tree, or that only one of them has.
+/// Directories whose cache-tree id matches HEAD's are skipped without reading them.
+fn staged_paths(git_dir: &Path, index: &Index) -> HashSet<String> {
+    // Worktrees keep their own HEAD but share objects and branches
+    let common_dir = fs::read_to_string(git_dir.join("commondir"))
+        .map(|dir| git_dir.join(dir.trim()))
+        .unwrap_or_else(|_| git_dir.to_path_buf());
+    let store = Store::open(&common_dir.join("objects"));
+    let mut head = HashMap::new();
+    let mut unchanged = HashSet::new();
+    match git_objects::head(git_dir, &common_dir, &store) {
+        Some(Head::Tree(tree)) => walk_tree(&store, &tree, "", index, &mut head, &mut unchanged),
+        Some(Head::Unborn) => {}
+        // Without HEAD nothing can be called staged
+        None => return HashSet::new(),
+    }
+    let skipped = |path: &str| {
+        !unchanged.is_empty()
+            && (unchanged.contains("")
+                || path
+                    .match_indices('/')
+                    .any(|(i, _)| unchanged.contains(&path[..i])))
+    };
+    let mut staged: HashSet<String> = index
+        .entries
+        .iter()
+        .filter(|(path, entry)| {
+            !skipped(path)
+                && head
+                    .get(path.as_str())
+                    .is_none_or(|&(mode, id)| mode != entry.mode || id != entry.id)
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    staged.extend(
+        head.keys()
+            .filter(|path| !index.entries.contains_key(*path) && !index.conflicted.contains(*path))
+            .cloned(),
+    );
+    staged
+}
+
+/// Collects the files of HEAD's tree below `dir` with their mode and id, except in
+/// directories the index records with the same tree, which go in `unchanged`
+fn walk_tree(
+    store: &Store,
+    tree: &Id,
+    dir: &str,
+    index: &Index,
+    files: &mut HashMap<String, (u32, Id)>,
+    unchanged: &mut HashSet<String>,
+) {
+    let entries = match index.trees.get(dir) {
+        Some(id) if id == tree => None,
+        _ => git_objects::read_tree(store, tree),
+    };
+    // A tree that cannot be read is left alone rather than reported as staged
+    let Some(entries) = entries else {
+        unchanged.insert(dir.to_string());
+        return;
+    };
+    for (mode, name, id) in entries {
+        let path = if dir.is_empty() {
+            name
+        } else {
+            format!("{}/{}", dir, name)
+        };
+        if mode == 0o40000 {
+            walk_tree(store, &id, &path, index, files, unchanged);
+        } else {
+            files.insert(path, (mode, id));
+        }
+    }
+}
+
+/// Reads a version 2, 3 or 4 index: the tracked files, those with merge conflicts,
+/// and the cache-tree extension
+fn parse_index(data: &[u8]) -> Option<Index> {
+    let u32_at = |at: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+    };
+    if data.get(..4)? != b"DIRC" {
+        return None;
+    }
+    let version = u32_at(4)?;
+    if !(2..=4).contains(&version) {
+        return None;
+    }
+    let count = u32_at(8)? as usize;
+    let mut index = Index::default();
+    let mut pos = 12;
+    let mut previous: Vec<u8> = Vec::new();
+
+    for _ in 0..count {
+        let start = pos;
+        let entry = IndexEntry {
+            mtime: (u32_at(pos + 8)?, u32_at(pos + 12)?),
+            mode: u32_at(pos + 24)?,
+            size: u32_at(pos + 36)?,
+            id: data.get(pos + 40..pos + 60)?.try_into().ok()?,
+        };
+        let flags = u16::from_be_bytes(data.get(pos + 60..pos + 62)?.try_into().ok()?);
+        pos += 62;
+        if version >= 3 && flags & 0x4000 != 0 {
+            pos += 2;
+        }
+        let path = if version == 4 {
+            // The path is the previous one minus `strip` bytes, plus this suffix
+            let (strip, used) = read_offset(data.get(pos..)?)?;
+            pos += used;
+            let end = pos + data.get(pos..)?.iter().position(|&b| b == 0)?;
+            let mut path = previous[..previous.len().checked_sub(strip)?].to_vec();
+            path.extend_from_slice(&data[pos..end]);
+            pos = end + 1;
+            path
+        } else {
+            let end = pos + data.get(pos..)?.iter().position(|&b| b == 0)?;
+            let path = data[pos..end].to_vec();
+            // Entries are padded with NULs to a multiple of eight bytes
+            pos = start + (end - start + 8) / 8 * 8;
+            path
+        };
+        let name = String::from_utf8_lossy(&path).into_owned();
+        previous = path;
+        if (flags >> 12) & 0x3 != 0 {
+            index.conflicted.insert(name);
+        } else {
+            index.entries.insert(name, entry);
+        }
+    }
+
+    // Extensions are a signature and a size, up to the closing checksum
+    let end = data.len().checked_sub(20)?;
+    while pos + 8 <= end {
+        let size = u32_at(pos + 4)? as usize;
+        let body = data.get(pos + 8..pos + 8 + size)?;
+        if &data[pos..pos + 4] == b"TREE" {
+            let mut at = 0;
+            while at < body.len() {
+                parse_cache_tree(body, &mut at, "", &mut index.trees)?;
+            }
+        }
+        pos += 8 + size;
+    }
+    Some(index)
+}
+
+/// One cache-tree node and its subtrees: `<name>\0<entries> <subtrees>\n`, then the
+/// id unless the entry count is -1, which marks a directory changed since
+fn parse_cache_tree(
+    data: &[u8],
+    pos: &mut usize,
+    parent: &str,
+    trees: &mut HashMap<String, Id>,
+) -> Option<()> {
+    let nul = *pos + data.get(*pos..)?.iter().position(|&b| b == 0)?;
+    let name = String::from_utf8_lossy(&data[*pos..nul]);
+    let path = if parent.is_empty() {
+        name.into_owned()
+    } else {
+        format!("{}/{}", parent, name)
+    };
+    let newline = nul + data.get(nul..)?.iter().position(|&b| b == b'\n')?;
+    let counts = std::str::from_utf8(&data[nul + 1..newline]).ok()?;
+    let (entries, subtrees) = counts.split_once(' ')?;
+    *pos = newline + 1;
+    if entries.parse::<i64>().ok()? >= 0 {
+        trees.insert(path.clone(), data.get(*pos..*pos + 20)?.try_into().ok()?);
+        *pos += 20;
+    }
+    for _ in 0..subtrees.parse::<usize>().ok()? {
+        parse_cache_tree(data, pos, &path, trees)?;
+    }
+    Some(())
+}
+
+/// The variable-length offset encoding of index version 4
+fn read_offset(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (i, &byte) in data.iter().enumerate() {
+        value = (value << 7) | usize::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+        value += 1;
+    }
+    None
+}
+
+fn parse_ignore(text: &str) -> Vec<Rule> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (negate, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let line = line.strip_prefix('/').unwrap_or(line);
+            Some(Rule {
+                pattern: Pattern::new(line).ok()?,
+                negate,
+                dir_only,
+                anchored,
+            })
+        })
+        .collect()
+}
+
+/// The id git gives a file with this content
+fn blob_id(content: &[u8]) -> Id {
+    let mut data = format!("blob {}\0", content.len()).into_bytes();
+    data.extend_from_slice(content);
+    sha1(&data)
+}
+
+fn sha1(data: &[u8]) -> Id {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut out = [0u8; 20];
+    for (chunk, value) in out.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&value.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn hex(id: Id) -> String {
+        id.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha1_matches_known_answers() {
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        // Padding spills into a second block
+        assert_eq!(
+            hex(sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        assert_eq!(
+            hex(sha1(&[b'a'; 1_000_000])),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+        assert_eq!(
+            hex(blob_id(b"")),
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+    }
+
+    /// An index of `version` holding `(path, stage, extended)` entries, each with its
+    /// position in the list as the id's first byte and as the size
+    fn index(version: u32, entries: &[(&str, u16, bool)], extension: &[u8]) -> Vec<u8> {
+        let mut data = b"DIRC".to_vec();
+        data.extend(version.to_be_bytes());
+        data.extend((entries.len() as u32).to_be_bytes());
+        let mut previous = "";
+        for (i, &(path, stage, extended)) in entries.iter().enumerate() {
+            let start = data.len();
+            data.extend([0; 8]);
+            data.extend(7u32.to_be_bytes());
+            data.extend(9u32.to_be_bytes());
+            data.extend([0; 8]);
+            data.extend(0o100644u32.to_be_bytes());
+            data.extend([0; 8]);
+            data.extend((i as u32).to_be_bytes());
+            data.extend([i as u8; 20]);
+            let flags = (stage << 12) | if extended { 0x4000 } else { 0 } | path.len() as u16;
+            data.extend(flags.to_be_bytes());
+            if extended {
+                data.extend([0; 2]);
+            }
+            if version == 4 {
+                let common = previous
+                    .bytes()
+                    .zip(path.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                // Strip counts below 128 take one byte
+                data.push((previous.len() - common) as u8);
+                data.extend(&path.as_bytes()[common..]);
+                data.push(0);
+            } else {
+                data.extend(path.as_bytes());
+                data.push(0);
+                while !(data.len() - start).is_multiple_of(8) {
+                    data.push(0);
+                }
+            }
+            previous = path;
+        }
+        data.extend(extension);
+        data.extend([0; 20]);
+        data
+    }
+
+    fn check(index: &Index) {
+        assert_eq!(index.entries.len(), 2);
+        let entry = &index.entries["src/main.rs"];
+        assert_eq!((entry.mtime, entry.mode, entry.size), ((7, 9), 0o100644, 1));
+        assert_eq!(entry.id, [1; 20]);
+        assert!(index.entries.contains_key("src/lib.rs"));
+        assert!(index.conflicted.contains("README"));
+        assert!(!index.entries.contains_key("README"));
+    }
+
+    const ENTRIES: &[(&str, u16, bool)] = &[
+        ("README", 2, false),
+        ("src/main.rs", 0, false),
+        ("src/lib.rs", 0, false),
+        ("zz", 0, false),
+    ];
+
+    #[test]
+    fn reads_index_version_2() {
+        let index = parse_index(&index(2, &ENTRIES[..3], b"")).unwrap();
+        check(&index);
+    }
+
+    #[test]
+    fn reads_index_version_3_extended_flags() {
+        let entries = [ENTRIES[0], ("src/main.rs", 0, true), ENTRIES[2]];
+        let index = parse_index(&index(3, &entries, b"")).unwrap();
+        check(&index);
+    }
+
+    #[test]
+    fn reads_index_version_4_prefix_compression() {
+        let index = parse_index(&index(4, ENTRIES, b"")).unwrap();
+        assert!(index.entries.contains_key("zz"));
+        assert_eq!(index.entries.len(), 3);
+        assert!(index.entries.contains_key("src/lib.rs"));
+        assert!(index.conflicted.contains("README"));
+    }
+
+    #[test]
+    fn rejects_other_versions_and_truncation() {
+        assert!(parse_index(&index(5, ENTRIES, b"")).is_none());
+        let data = index(2, ENTRIES, b"");
+        assert!(parse_index(&data[..40]).is_none());
+    }
+
+    #[test]
+    fn reads_the_cache_tree() {
+        // The root with two subtrees: `src`, valid, and `docs`, invalidated
+        let mut tree = b"\x003 2\n".to_vec();
+        tree.extend([1; 20]);
+        tree.extend(b"src\x002 0\n");
+        tree.extend([2; 20]);
+        tree.extend(b"docs\0-1 0\n");
+        let mut extension = b"TREE".to_vec();
+        extension.extend((tree.len() as u32).to_be_bytes());
+        extension.extend(tree);
+        let index = parse_index(&index(2, &ENTRIES[..3], &extension)).unwrap();
+        check(&index);
+        assert_eq!(index.trees.get(""), Some(&[1; 20]));
+        assert_eq!(index.trees.get("src"), Some(&[2; 20]));
+        assert!(!index.trees.contains_key("docs"));
+    }
+
+    fn git(dir: &Path, args: &[&str]) -> Option<String> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+            .args(args)
+            .output()
+            .ok()?;
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    fn staged(dir: &Path) -> HashSet<String> {
+        let git_dir = dir.join(".git");
+        let index = fs::read(git_dir.join("index"))
+            .ok()
+            .and_then(|data| parse_index(&data))
+            .unwrap_or_default();
+        staged_paths(&git_dir, &index)
+    }
+
+    fn expected(dir: &Path) -> HashSet<String> {
+        git(dir, &["diff", "--cached", "--name-only", "-z"])
+            .unwrap()
+            .split('\0')
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn staged_paths_agree_with_git() {
+        let dir = std::env::temp_dir().join(format!("xinux-git-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/deep")).unwrap();
+        // Without git there is nothing to compare with
+        if git(&dir, &["init", "-q"]).is_none() {
+            return;
+        }
+        let numbers = |n: usize| (0..n).map(|i| format!("{}\n", i)).collect::<String>();
+        for name in ["a", "b", "src/c", "src/deep/d", "src/deep/e"] {
+            fs::write(dir.join(name), numbers(400)).unwrap();
+        }
+        assert_eq!(staged(&dir).len(), 0, "empty index on an unborn branch");
+        git(&dir, &["add", "-A"]).unwrap();
+        assert_eq!(staged(&dir), expected(&dir));
+        git(&dir, &["commit", "-qm", "one"]).unwrap();
+        fs::write(dir.join("src/deep/d"), numbers(401)).unwrap();
+        git(&dir, &["commit", "-qam", "two"]).unwrap();
+        // Packed, with trees stored as deltas
+        git(&dir, &["gc", "-q", "--aggressive"]).unwrap();
+        assert_eq!(staged(&dir), HashSet::new());
+
+        fs::write(dir.join("src/deep/e"), "changed\n").unwrap();
+        fs::write(dir.join("src/new"), "new\n").unwrap();
+        git(&dir, &["add", "src"]).unwrap();
+        git(&dir, &["rm", "-q", "--cached", "a"]).unwrap();
+        git(&dir, &["update-index", "--chmod=+x", "b"]).unwrap();
+        fs::write(dir.join("src/c"), "not staged\n").unwrap();
+        let staged = staged(&dir);
+        assert_eq!(staged, expected(&dir));
+        assert_eq!(staged.len(), 4);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
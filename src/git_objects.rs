@@ -0,0 +1,407 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+
+/// An object's SHA-1
+pub type Id = [u8; 20];
+
+const COMMIT: u8 = 1;
+const TREE: u8 = 2;
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+
+/// Where `HEAD` points
+pub enum Head {
+    /// A branch with no commits yet
+    Unborn,
+    /// The root tree of the checked-out commit
+    Tree(Id),
+}
+
+/// Reads objects from a repository's loose files and packs
+pub struct Store {
+    objects: PathBuf,
+    packs: Vec<Pack>,
+}
+
+/// A pack and its version 2 index
+struct Pack {
+    file: File,
+    index: Vec<u8>,
+}
+
+/// Reads a file from a position on, without moving a shared cursor
+struct At<'a> {
+    file: &'a File,
+    pos: u64,
+}
+
+impl Read for At<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Store {
+    pub fn open(objects: &Path) -> Store {
+        let packs = fs::read_dir(objects.join("pack"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "idx" {
+                    return None;
+                }
+                let index = fs::read(&path).ok()?;
+                // Version 2 starts with `\xfftOc` and the version
+                if index.get(..8)? != b"\xfftOc\0\0\0\x02" {
+                    return None;
+                }
+                let file = File::open(path.with_extension("pack")).ok()?;
+                Some(Pack { file, index })
+            })
+            .collect();
+        Store {
+            objects: objects.to_path_buf(),
+            packs,
+        }
+    }
+
+    /// The object's type and content
+    pub fn read(&self, id: &Id) -> Option<(u8, Vec<u8>)> {
+        self.read_loose(id).or_else(|| {
+            self.packs
+                .iter()
+                .find_map(|pack| pack.read_at(pack.find(id)?, self))
+        })
+    }
+
+    fn read_loose(&self, id: &Id) -> Option<(u8, Vec<u8>)> {
+        let hex = to_hex(id);
+        let file = File::open(self.objects.join(&hex[..2]).join(&hex[2..])).ok()?;
+        let mut data = Vec::new();
+        ZlibDecoder::new(file).read_to_end(&mut data).ok()?;
+        // `<type> <size>\0<content>`
+        let nul = data.iter().position(|&b| b == 0)?;
+        let kind = match data.get(..data.iter().position(|&b| b == b' ')?)? {
+            b"commit" => COMMIT,
+            b"tree" => TREE,
+            b"blob" => 3,
+            b"tag" => 4,
+            _ => return None,
+        };
+        Some((kind, data.split_off(nul + 1)))
+    }
+}
+
+impl Pack {
+    /// The offset of `id` in the pack, found through the fan-out table
+    fn find(&self, id: &Id) -> Option<u64> {
+        let u32_at = |at: usize| -> Option<u32> {
+            Some(u32::from_be_bytes(
+                self.index.get(at..at + 4)?.try_into().ok()?,
+            ))
+        };
+        let fanout = |byte: usize| u32_at(8 + byte * 4).map(|n| n as usize);
+        let count = fanout(255)?;
+        let low = match id[0] {
+            0 => 0,
+            byte => fanout(byte as usize - 1)?,
+        };
+        let high = fanout(id[0] as usize)?;
+        let ids = 8 + 256 * 4;
+        let mut range = low..high;
+        let position = loop {
+            if range.is_empty() {
+                return None;
+            }
+            let mid = range.start + range.len() / 2;
+            let at = ids + mid * 20;
+            match self.index.get(at..at + 20)?.cmp(&id[..]) {
+                std::cmp::Ordering::Equal => break mid,
+                std::cmp::Ordering::Less => range.start = mid + 1,
+                std::cmp::Ordering::Greater => range.end = mid,
+            }
+        };
+        // Ids, then CRCs, then 4-byte offsets whose top bit points into a table of
+        // 8-byte ones
+        let offsets = ids + count * 24;
+        let offset = u32_at(offsets + position * 4)?;
+        if offset & 0x8000_0000 == 0 {
+            return Some(u64::from(offset));
+        }
+        let at = offsets + count * 4 + (offset & 0x7fff_ffff) as usize * 8;
+        Some(u64::from_be_bytes(
+            self.index.get(at..at + 8)?.try_into().ok()?,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, store: &Store) -> Option<(u8, Vec<u8>)> {
+        let mut reader = At {
+            file: &self.file,
+            pos: offset,
+        };
+        let mut byte = || -> Option<u8> {
+            let mut b = [0];
+            reader.read_exact(&mut b).ok()?;
+            Some(b[0])
+        };
+        // Type in bits 4-6 of the first byte, size in the rest and the bytes after
+        let mut c = byte()?;
+        let kind = (c >> 4) & 7;
+        let mut size = u64::from(c & 0x0f);
+        let mut shift = 4;
+        while c & 0x80 != 0 {
+            c = byte()?;
+            size |= u64::from(c & 0x7f) << shift;
+            shift += 7;
+        }
+        let base = match kind {
+            OFS_DELTA => {
+                let mut c = byte()?;
+                let mut distance = u64::from(c & 0x7f);
+                while c & 0x80 != 0 {
+                    c = byte()?;
+                    distance = ((distance + 1) << 7) | u64::from(c & 0x7f);
+                }
+                Some(self.read_at(offset.checked_sub(distance)?, store)?)
+            }
+            REF_DELTA => {
+                let mut id = [0; 20];
+                reader.read_exact(&mut id).ok()?;
+                Some(store.read(&id)?)
+            }
+            _ => None,
+        };
+        let mut data = Vec::with_capacity(size as usize);
+        ZlibDecoder::new(reader)
+            .take(size)
+            .read_to_end(&mut data)
+            .ok()?;
+        match base {
+            Some((kind, base)) => Some((kind, apply_delta(&base, &data)?)),
+            None => Some((kind, data)),
+        }
+    }
+}
+
+/// Rebuilds an object from its base and a delta of copy and insert instructions
+fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    let mut size = || {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let c = *delta.get(pos)?;
+            pos += 1;
+            value |= usize::from(c & 0x7f) << shift;
+            shift += 7;
+            if c & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    };
+    if size()? != base.len() {
+        return None;
+    }
+    let target_size = size()?;
+    let mut out = Vec::with_capacity(target_size);
+    while let Some(&op) = delta.get(pos) {
+        pos += 1;
+        if op & 0x80 != 0 {
+            // Bits 0-3 say which offset bytes follow, bits 4-6 which size bytes
+            let mut field = |bits: u8, shift: usize| -> Option<usize> {
+                let mut value = 0;
+                for i in 0..bits {
+                    if op & (1 << (shift + i as usize)) != 0 {
+                        value |= usize::from(*delta.get(pos)?) << (8 * i);
+                        pos += 1;
+                    }
+                }
+                Some(value)
+            };
+            let start = field(4, 0)?;
+            let len = match field(3, 4)? {
+                0 => 0x10000,
+                len => len,
+            };
+            out.extend_from_slice(base.get(start..start + len)?);
+        } else if op != 0 {
+            out.extend_from_slice(delta.get(pos..pos + op as usize)?);
+            pos += op as usize;
+        } else {
+            return None;
+        }
+    }
+    (out.len() == target_size).then_some(out)
+}
+
+/// The commit `HEAD` names, through a branch when it is on one. Per-worktree files are
+/// in `git_dir`, the shared refs in `common_dir`. `None` when it cannot be read.
+pub fn head(git_dir: &Path, common_dir: &Path, store: &Store) -> Option<Head> {
+    let text = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let text = text.trim();
+    let commit = match text.strip_prefix("ref:") {
+        Some(name) => {
+            let name = name.trim();
+            let loose = fs::read_to_string(git_dir.join(name))
+                .or_else(|_| fs::read_to_string(common_dir.join(name)));
+            let hex = match loose {
+                Ok(hex) => hex.trim().to_string(),
+                Err(_) => {
+                    let packed =
+                        fs::read_to_string(common_dir.join("packed-refs")).unwrap_or_default();
+                    let found = packed.lines().find_map(|line| {
+                        let (hex, refname) = line.split_once(' ')?;
+                        (refname == name).then(|| hex.to_string())
+                    });
+                    match found {
+                        Some(hex) => hex,
+                        None => return Some(Head::Unborn),
+                    }
+                }
+            };
+            from_hex(&hex)?
+        }
+        None => from_hex(text)?,
+    };
+    let (kind, data) = store.read(&commit)?;
+    if kind != COMMIT {
+        return None;
+    }
+    // A commit starts with `tree <hex>`
+    let hex = data.strip_prefix(b"tree ")?.get(..40)?;
+    Some(Head::Tree(from_hex(std::str::from_utf8(hex).ok()?)?))
+}
+
+/// A tree's entries as mode, name and id
+pub fn read_tree(store: &Store, id: &Id) -> Option<Vec<(u32, String, Id)>> {
+    let (kind, data) = store.read(id)?;
+    if kind != TREE {
+        return None;
+    }
+    parse_tree(&data)
+}
+
+/// `<octal mode> <name>\0<20-byte id>`, over and over
+fn parse_tree(data: &[u8]) -> Option<Vec<(u32, String, Id)>> {
+    let mut entries = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let mode = u32::from_str_radix(std::str::from_utf8(&rest[..space]).ok()?, 8).ok()?;
+        let nul = space + rest[space..].iter().position(|&b| b == 0)?;
+        let name = String::from_utf8_lossy(&rest[space + 1..nul]).into_owned();
+        let id = rest.get(nul + 1..nul + 21)?.try_into().ok()?;
+        entries.push((mode, name, id));
+        rest = &rest[nul + 21..];
+    }
+    Some(entries)
+}
+
+fn to_hex(id: &Id) -> String {
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Id> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut id = [0; 20];
+    for (i, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas_copy_from_the_base_and_insert_new_bytes() {
+        let base = b"hello, world";
+        // Sizes 12 and 11, copy 7 bytes from offset 0, insert 4 bytes
+        let delta = [12, 11, 0x90, 7, 4, b'y', b'o', b'u', b'!'];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello, you!");
+        // A copy with an offset byte
+        let delta = [12, 5, 0x91, 7, 5];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"world");
+        // The base size must match
+        assert!(apply_delta(b"short", &[12, 0]).is_none());
+    }
+
+    #[test]
+    fn trees_list_modes_names_and_ids() {
+        let mut data = b"100644 a.txt\0".to_vec();
+        data.extend([1; 20]);
+        data.extend(b"40000 src\0");
+        data.extend([2; 20]);
+        let entries = parse_tree(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], (0o100644, "a.txt".to_string(), [1; 20]));
+        assert_eq!(entries[1], (0o40000, "src".to_string(), [2; 20]));
+        assert!(parse_tree(b"100644 cut\0\x01\x02").is_none());
+    }
+
+    #[test]
+    fn reads_loose_and_packed_objects_like_git() {
+        use std::process::Command;
+        let dir = std::env::temp_dir().join(format!("xinux-objects-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let git = |args: &[&str]| -> Option<Vec<u8>> {
+            let out = Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .output()
+                .ok()?;
+            out.status.success().then_some(out.stdout)
+        };
+        // Without git there is nothing to compare with
+        if git(&["init", "-q"]).is_none() {
+            return;
+        }
+        let numbers: String = (0..2000).map(|i| format!("{}\n", i)).collect();
+        for round in 0..3 {
+            fs::write(dir.join("numbers"), format!("{}{}\n", numbers, round)).unwrap();
+            git(&["add", "-A"]).unwrap();
+            git(&["commit", "-qm", "round"]).unwrap();
+        }
+        // Similar blobs end up as deltas against each other
+        git(&["gc", "-q"]).unwrap();
+        fs::write(dir.join("loose"), "loose\n").unwrap();
+        git(&["add", "loose"]).unwrap();
+
+        let store = Store::open(&dir.join(".git/objects"));
+        let listing = git(&[
+            "cat-file",
+            "--batch-all-objects",
+            "--batch-check=%(objectname) %(objecttype)",
+        ])
+        .unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        assert!(listing.lines().count() >= 10);
+        for line in listing.lines() {
+            let (hex, kind) = line.split_once(' ').unwrap();
+            let (_, data) = store.read(&from_hex(hex).unwrap()).unwrap();
+            assert_eq!(data, git(&["cat-file", kind, hex]).unwrap(), "{}", hex);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        let hex = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+        assert_eq!(to_hex(&from_hex(hex).unwrap()), hex);
+        assert!(from_hex("da39").is_none());
+    }
+}
@@ -0,0 +1,326 @@
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use sysinfo::{MINIMUM_CPU_UPDATE_INTERVAL, System};
+use unicode_width::UnicodeWidthStr;
+use whoami::fallible;
+
+use crate::detect;
+use crate::eval;
+use crate::filetypes::Style;
+use crate::logos;
+use crate::ls::{format_size, terminal_width};
+use crate::packages;
+use crate::records::{self, Field, Format, Record};
+use crate::top;
+
+/// The built-in fields, by the name `fields` uses, in their default order
+const FIELDS: [&str; 18] = [
+    "host",
+    "machine",
+    "kernel",
+    "distro",
+    "de",
+    "wm",
+    "packages",
+    "shell",
+    "terminal",
+    "resolution",
+    "gpu",
+    "uptime",
+    "cpu",
+    "cpu_load",
+    "memory",
+    "swap",
+    "disk",
+    "battery",
+];
+
+/// Space between the logo and the fields
+const GAP: usize = 3;
+
+/// The `[info]` table in `config.toml`
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct InfoConfig {
+    /// Field names in the order shown; all built-in fields, then the custom ones,
+    /// when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    /// Extra fields: a name and the command whose output is its value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_color: Option<String>,
+    /// `auto` for the running distribution, `none`, or a distribution such as `arch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    /// Art to draw instead of a built-in logo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo_color: Option<String>,
+}
+
+fn error(message: String) {
+    eprintln!("{}", format!("info: {}", message).red());
+}
+
+/// The `info` builtin: system details beside the distribution's logo, or a single
+/// record in the other formats
+pub fn builtin_info(args: &[&str]) -> i32 {
+    let (format, rest) = records::take_format(args);
+    if rest.contains(&"--watch") {
+        let rest: Vec<&str> = rest.into_iter().filter(|&a| a != "--watch").collect();
+        return top::builtin_top(&rest);
+    }
+    let refresh = rest.contains(&"--refresh");
+    let config = crate::read_config().map(|c| c.info).unwrap_or_default();
+    let entries = collect(&config, refresh);
+
+    if format != Format::Pretty {
+        let fields = entries
+            .into_iter()
+            .map(|(key, val)| (record_key(&key), Field::Text(val)))
+            .collect();
+        records::print_record(&Record::new(fields), format);
+        return 0;
+    }
+
+    let style = |spec: &Option<String>, default: &str| {
+        spec.as_deref()
+            .and_then(|s| {
+                let style = Style::parse(s);
+                if style.is_none() {
+                    error(format!("unknown color `{}`", s));
+                }
+                style
+            })
+            .or_else(|| Style::parse(default))
+            .unwrap_or_default()
+    };
+    let title_style = style(&config.title_color, "bold bright_cyan");
+    let key_style = style(&config.key_color, "bright_yellow");
+    let value_style = style(&config.value_color, "bright_white");
+
+    let max_key_len = entries.iter().map(|(k, _)| k.width()).max().unwrap_or(0);
+    let mut lines = vec![title_style.paint("System Info").to_string()];
+    for (i, (key, val)) in entries.iter().enumerate() {
+        let branch = if i == entries.len() - 1 {
+            "└──"
+        } else {
+            "├──"
+        };
+        let pad = " ".repeat(max_key_len - key.width());
+        lines.push(format!(
+            "{} {}{} {} {}",
+            branch.bright_black(),
+            key_style.paint(key),
+            pad,
+            "➜".dimmed(),
+            value_style.paint(val)
+        ));
+    }
+
+    let logo = logo(&config);
+    let logo_width = logo.iter().map(|l| visible_width(l)).max().unwrap_or(0);
+    let text_width = lines.iter().map(|l| visible_width(l)).max().unwrap_or(0);
+    println!();
+    if logo.is_empty() {
+        lines.iter().for_each(|line| println!("{}", line));
+    } else if logo_width + GAP + text_width > terminal_width() {
+        // Too narrow to share the row: the logo goes on top
+        logo.iter().for_each(|line| println!("{}", line));
+        println!();
+        lines.iter().for_each(|line| println!("{}", line));
+    } else {
+        for i in 0..logo.len().max(lines.len()) {
+            let left = logo.get(i).map_or("", String::as_str);
+            let right = lines.get(i).map_or("", String::as_str);
+            let pad = " ".repeat(logo_width - visible_width(left) + GAP);
+            let line = format!("{}{}{}", left, pad, right);
+            println!("{}", line.trim_end());
+        }
+    }
+    println!();
+    0
+}
+
+/// The configured fields as labels and values, computing only those asked for
+fn collect(config: &InfoConfig, refresh: bool) -> Vec<(String, String)> {
+    let names: Vec<String> = if config.fields.is_empty() {
+        FIELDS
+            .iter()
+            .map(|f| f.to_string())
+            .chain(config.custom.keys().cloned())
+            .collect()
+    } else {
+        config.fields.clone()
+    };
+
+    let system = OnceCell::new();
+    let sys = || {
+        system.get_or_init(|| {
+            let mut sys = System::new_all();
+            sys.refresh_all();
+            sys
+        })
+    };
+
+    let mut entries = Vec::new();
+    for name in &names {
+        if let Some(command) = config.custom.get(name) {
+            let output = eval::capture(command);
+            let value = output.split_whitespace().collect::<Vec<_>>().join(" ");
+            let value = if value.is_empty() {
+                "Unknown".into()
+            } else {
+                value
+            };
+            entries.push((name.clone(), value));
+            continue;
+        }
+        let (label, value) = match record_key(name).as_str() {
+            "host" => (
+                "Host",
+                format!(
+                    "{}@{}",
+                    whoami::username(),
+                    fallible::hostname().unwrap_or_else(|_| "Unknown".into())
+                ),
+            ),
+            "machine" => ("Machine", detect::machine()),
+            "kernel" => (
+                "Kernel",
+                System::kernel_version().unwrap_or_else(|| "Unknown".into()),
+            ),
+            "distro" => (
+                "Distro",
+                System::long_os_version().unwrap_or_else(|| "Unknown".into()),
+            ),
+            "de" => ("DE", detect::desktop()),
+            "wm" => ("WM", detect::window_manager(sys())),
+            "packages" => ("Packages", packages::counts(refresh)),
+            "shell" => ("Shell", detect::shell()),
+            "terminal" => ("Terminal", detect::terminal(sys())),
+            "resolution" => ("Resolution", detect::resolution()),
+            "gpu" => ("GPU", detect::gpus()),
+            "uptime" => (
+                "Uptime",
+                humantime::format_duration(Duration::from_secs(System::uptime())).to_string(),
+            ),
+            "cpu" => {
+                let brand = sys()
+                    .cpus()
+                    .first()
+                    .map(|cpu| cpu.brand().to_string())
+                    .unwrap_or("Unknown CPU".into());
+                ("CPU", format!("{} ({})", brand, sys().cpus().len()))
+            }
+            "cpu_load" => ("CPU Load", cpu_load()),
+            "memory" => (
+                "Memory",
+                format!(
+                    "{} / {}",
+                    format_size(sys().used_memory()),
+                    format_size(sys().total_memory())
+                ),
+            ),
+            "swap" => ("Swap", detect::swap(sys())),
+            "disk" | "disks" => {
+                for (mount, usage) in detect::disks() {
+                    entries.push((format!("Disk ({})", mount), usage));
+                }
+                continue;
+            }
+            "battery" => ("Battery", detect::battery()),
+            _ => {
+                error(format!("unknown field `{}`", name));
+                continue;
+            }
+        };
+        entries.push((label.to_string(), value));
+    }
+    entries
+}
+
+/// Usage of all cores together, measured over the shortest interval sysinfo allows
+fn cpu_load() -> String {
+    let mut sys = System::new();
+    sys.refresh_cpu_usage();
+    thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+    sys.refresh_cpu_usage();
+    format!("{:.0}%", sys.global_cpu_usage())
+}
+
+/// The logo's lines, colored; empty for `logo = "none"`
+fn logo(config: &InfoConfig) -> Vec<String> {
+    let color = config.logo_color.as_deref().and_then(Style::parse);
+    if let Some(file) = &config.logo_file {
+        let path = crate::expand::expand_string(file);
+        match fs::read_to_string(&path) {
+            // Art that brings its own colors is drawn as it is
+            Ok(art) if art.contains('\x1b') => {
+                return art.lines().map(|l| l.replace('\t', "    ")).collect();
+            }
+            Ok(art) => {
+                let style = color.unwrap_or_default();
+                return art
+                    .lines()
+                    .map(|l| style.paint(&l.replace('\t', "    ")).to_string())
+                    .collect();
+            }
+            Err(e) => error(format!("cannot read logo file '{}': {}", path, e)),
+        }
+    }
+    let logo = match config.logo.as_deref().unwrap_or("auto") {
+        "none" => return Vec::new(),
+        "auto" => logos::detect(),
+        id => logos::find(id).unwrap_or_else(|| {
+            error(format!("no logo for `{}`", id));
+            logos::detect()
+        }),
+    };
+    let style = color
+        .or_else(|| Style::parse(logo.color))
+        .unwrap_or_default();
+    logo.lines()
+        .into_iter()
+        .map(|l| style.paint(l).to_string())
+        .collect()
+}
+
+/// Columns `text` takes on screen, leaving out escape sequences
+fn visible_width(text: &str) -> usize {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end with a letter
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain.width()
+}
+
+/// `CPU Load` becomes `cpu_load` and `Disk (/home)` becomes `disk_home`
+fn record_key(key: &str) -> String {
+    let key: String = key
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let mut words: Vec<&str> = key.split('_').filter(|w| !w.is_empty()).collect();
+    words.dedup();
+    words.join("_")
+}
@@ -0,0 +1,309 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+
+use colored::Colorize;
+
+use crate::eval::{self, Flow, exit_code, wait_status};
+use crate::parse_input::Node;
+use crate::state::{self, Job};
+
+const JOBS_USAGE: &str = "usage: jobs [-lp]";
+const WAIT_USAGE: &str = "usage: wait [%job | pid]...";
+
+fn error(command: &str, message: String) {
+    eprintln!("{}", format!("xinux: {}: {}", command, message).red());
+}
+
+/// Starts `body` in a forked copy of the shell, in a process group of its own so
+/// that `kill %N` reaches every process it starts, and returns without waiting
+pub fn run_background(body: &Node, source: &str) -> Flow {
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    // SAFETY: the child only runs shell code and leaves through `_exit`.
+    match unsafe { libc::fork() } {
+        -1 => {
+            error("fork", io::Error::last_os_error().to_string());
+            Flow::Next(1)
+        }
+        0 => {
+            // SAFETY: moves this process into a group of its own.
+            unsafe { libc::setpgid(0, 0) };
+            let interactive = state::with(|s| {
+                s.jobs.clear();
+                std::mem::replace(&mut s.interactive, false)
+            });
+            // Like bash without job control: a script's job must not eat the script's input
+            if !interactive && let Ok(null) = File::open("/dev/null") {
+                // SAFETY: plain descriptor juggling in the child.
+                unsafe { libc::dup2(null.as_raw_fd(), 0) };
+            }
+            let code = eval::run(body).code();
+            let _ = io::stdout().flush();
+            let _ = io::stderr().flush();
+            // SAFETY: terminating the forked child without running parent destructors.
+            unsafe { libc::_exit(code) }
+        }
+        pid => {
+            // Also done here, so the group exists before anyone can signal it
+            // SAFETY: `pid` is the child just forked.
+            unsafe { libc::setpgid(pid, pid) };
+            let (id, interactive) = state::with(|s| {
+                let id = s.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+                s.jobs.push(Job {
+                    id,
+                    pid,
+                    command: source.to_string(),
+                    done: None,
+                });
+                s.last_background = Some(pid);
+                (id, s.interactive)
+            });
+            if interactive {
+                eprintln!("[{}] {}", id, pid);
+            }
+            Flow::Next(0)
+        }
+    }
+}
+
+/// Notes which jobs have finished, without waiting for the others
+fn reap() {
+    state::with(|s| {
+        for job in s.jobs.iter_mut().filter(|job| job.done.is_none()) {
+            let mut status = 0;
+            // SAFETY: polls a child we forked ourselves.
+            match unsafe { libc::waitpid(job.pid, &mut status, libc::WNOHANG) } {
+                0 => {}
+                -1 => job.done = Some(127),
+                _ => job.done = Some(exit_code(status)),
+            }
+        }
+    });
+}
+
+/// `Done`, `Exit 3` or the signal that ended a job, such as `Killed`
+fn describe(code: i32) -> String {
+    match code {
+        0 => "Done".into(),
+        129..=159 => match code - 128 {
+            libc::SIGKILL => "Killed".into(),
+            libc::SIGTERM => "Terminated".into(),
+            libc::SIGINT => "Interrupt".into(),
+            libc::SIGHUP => "Hangup".into(),
+            signal => format!("Signal {}", signal),
+        },
+        code => format!("Exit {}", code),
+    }
+}
+
+/// `+` for the newest job, which `%%` names, and `-` for the one before it
+fn marker(index: usize, count: usize) -> char {
+    if index + 1 == count {
+        '+'
+    } else if index + 2 == count {
+        '-'
+    } else {
+        ' '
+    }
+}
+
+/// Reports the jobs that finished since the last prompt and forgets them
+pub fn notify() {
+    reap();
+    let finished = state::with(|s| {
+        let count = s.jobs.len();
+        let finished: Vec<String> = s
+            .jobs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, job)| {
+                let code = job.done?;
+                Some(format!(
+                    "[{}]{} {:<12}{}",
+                    job.id,
+                    marker(i, count),
+                    describe(code),
+                    job.command
+                ))
+            })
+            .collect();
+        s.jobs.retain(|job| job.done.is_none());
+        finished
+    });
+    for line in finished {
+        eprintln!("{}", line.dimmed());
+    }
+}
+
+/// The `jobs` builtin: lists background jobs, with their PIDs for `-l` or only the
+/// PIDs for `-p`, and forgets the finished ones once they are shown
+pub fn builtin_jobs(args: &[String]) -> i32 {
+    let (mut long, mut pids) = (false, false);
+    for arg in args {
+        match arg.as_str() {
+            "-l" => long = true,
+            "-p" => pids = true,
+            "-lp" | "-pl" => (long, pids) = (true, true),
+            _ => {
+                error("jobs", format!("unknown option `{}`\n{}", arg, JOBS_USAGE));
+                return 2;
+            }
+        }
+    }
+    reap();
+    state::with(|s| {
+        let count = s.jobs.len();
+        for (i, job) in s.jobs.iter().enumerate() {
+            if pids {
+                println!("{}", job.pid);
+                continue;
+            }
+            let pid = if long {
+                format!("{} ", job.pid)
+            } else {
+                String::new()
+            };
+            let status = job.done.map_or_else(|| "Running".into(), describe);
+            println!(
+                "[{}]{} {}{:<12}{}",
+                job.id,
+                marker(i, count),
+                pid,
+                status,
+                job.command
+            );
+        }
+        s.jobs.retain(|job| job.done.is_none());
+    });
+    0
+}
+
+/// The job a spec names: `%N`, `%%` or `%+` for the newest, `%-` for the one before,
+/// `%NAME` for the one whose command starts with `NAME`, or `%?TEXT` for the one
+/// whose command contains `TEXT`. Returns its number and process group.
+pub fn find(spec: &str) -> Result<(usize, libc::pid_t), String> {
+    let Some(key) = spec.strip_prefix('%') else {
+        return Err(format!("{}: not a job spec", spec));
+    };
+    state::with(|s| {
+        let jobs = &s.jobs;
+        let found: Vec<&Job> = match key {
+            "" | "%" | "+" => jobs.last().into_iter().collect(),
+            "-" => jobs.iter().rev().nth(1).into_iter().collect(),
+            _ => {
+                if let Ok(id) = key.parse::<usize>() {
+                    jobs.iter().filter(|job| job.id == id).collect()
+                } else if let Some(text) = key.strip_prefix('?') {
+                    jobs.iter()
+                        .filter(|job| job.command.contains(text))
+                        .collect()
+                } else {
+                    jobs.iter()
+                        .filter(|job| job.command.starts_with(key))
+                        .collect()
+                }
+            }
+        };
+        match found[..] {
+            [job] => Ok((job.id, job.pid)),
+            [] => Err(format!("{}: no such job", spec)),
+            _ => Err(format!("{}: ambiguous job spec", spec)),
+        }
+    })
+}
+
+/// Waits for the job and returns its exit code, forgetting it
+fn wait_job(id: usize, pid: libc::pid_t) -> i32 {
+    let done = state::with(|s| s.jobs.iter().find(|job| job.id == id).and_then(|j| j.done));
+    let code = done.unwrap_or_else(|| wait_status(pid).map_or(127, exit_code));
+    state::with(|s| s.jobs.retain(|job| job.id != id));
+    code
+}
+
+/// The `wait` builtin: waits for every background job, or for the jobs and PIDs
+/// given, and returns the exit code of the last one
+pub fn builtin_wait(args: &[String]) -> i32 {
+    if args.is_empty() {
+        let jobs: Vec<(usize, libc::pid_t)> =
+            state::with(|s| s.jobs.iter().map(|job| (job.id, job.pid)).collect());
+        for (id, pid) in jobs {
+            wait_job(id, pid);
+        }
+        return 0;
+    }
+    let mut code = 0;
+    for arg in args {
+        let job = if arg.starts_with('%') {
+            find(arg)
+        } else if let Ok(pid) = arg.parse::<libc::pid_t>() {
+            let id = state::with(|s| s.jobs.iter().find(|job| job.pid == pid).map(|j| j.id));
+            match id {
+                Some(id) => Ok((id, pid)),
+                // Any other child of this shell can be waited for too
+                None => match wait_status(pid) {
+                    Some(status) => {
+                        code = exit_code(status);
+                        continue;
+                    }
+                    None => Err(format!("pid {} is not a child of this shell", pid)),
+                },
+            }
+        } else {
+            error(
+                "wait",
+                format!("`{}` is not a PID or job spec\n{}", arg, WAIT_USAGE),
+            );
+            return 2;
+        };
+        code = match job {
+            Ok((id, pid)) => wait_job(id, pid),
+            Err(e) => {
+                error("wait", e);
+                127
+            }
+        };
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(id: usize, command: &str) {
+        state::with(|s| {
+            s.jobs.push(Job {
+                id,
+                pid: 1000 + id as libc::pid_t,
+                command: command.into(),
+                done: None,
+            })
+        });
+    }
+
+    #[test]
+    fn job_specs_name_jobs_by_number_recency_or_command() {
+        add(1, "sleep 100");
+        add(2, "make -j8 | tee log");
+        add(4, "sleep 5");
+        assert_eq!(find("%2"), Ok((2, 1002)));
+        assert_eq!(find("%%"), Ok((4, 1004)));
+        assert_eq!(find("%+"), Ok((4, 1004)));
+        assert_eq!(find("%"), Ok((4, 1004)));
+        assert_eq!(find("%-"), Ok((2, 1002)));
+        assert_eq!(find("%make"), Ok((2, 1002)));
+        assert_eq!(find("%?tee"), Ok((2, 1002)));
+        assert_eq!(find("%3"), Err("%3: no such job".into()));
+        assert_eq!(find("%sleep"), Err("%sleep: ambiguous job spec".into()));
+        assert!(find("4").is_err());
+    }
+
+    #[test]
+    fn finished_jobs_say_how_they_ended() {
+        assert_eq!(describe(0), "Done");
+        assert_eq!(describe(3), "Exit 3");
+        assert_eq!(describe(128 + libc::SIGKILL), "Killed");
+        assert_eq!(describe(128 + libc::SIGTERM), "Terminated");
+    }
+}
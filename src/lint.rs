@@ -0,0 +1,607 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::eval::BUILTINS;
+use crate::parse_input::{Node, SimpleCommand, Word, parse};
+
+/// One finding, printed with a source snippet or serialized for `--json`
+#[derive(Serialize)]
+struct Diagnostic {
+    file: String,
+    line: usize,
+    col: usize,
+    /// How many characters the caret underlines
+    length: usize,
+    severity: &'static str,
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    help: Option<String>,
+}
+
+/// Walks a parsed script collecting findings and variable usage
+struct Linter<'a> {
+    file: &'a str,
+    diagnostics: Vec<Diagnostic>,
+    /// Plain assignments, in order: name, line, column
+    assigned: Vec<(String, usize, usize)>,
+    used: HashSet<String>,
+    var_use: Regex,
+}
+
+impl Linter<'_> {
+    fn warn(
+        &mut self,
+        at: (usize, usize, usize),
+        code: &'static str,
+        message: String,
+        help: Option<String>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_string(),
+            line: at.0,
+            col: at.1,
+            length: at.2,
+            severity: "warning",
+            code,
+            message,
+            help,
+        });
+    }
+
+    /// `checked` is true where the exit status is tested (conditions, `&&`/`||` left sides)
+    fn visit(&mut self, node: &Node, checked: bool) {
+        match node {
+            Node::Simple(cmd) => self.simple(cmd, checked),
+            Node::Pipeline { stages, .. } => {
+                for stage in stages {
+                    self.visit(stage, checked);
+                }
+            }
+            Node::And(lhs, rhs) | Node::Or(lhs, rhs) => {
+                self.visit(lhs, true);
+                self.visit(rhs, checked);
+            }
+            Node::List(items) => {
+                let mut reported = false;
+                for (i, item) in items.iter().enumerate() {
+                    self.visit(item, checked);
+                    if reported || !is_exit(item) {
+                        continue;
+                    }
+                    if let Some(word) = items.get(i + 1).and_then(first_word) {
+                        reported = true;
+                        self.warn(
+                            (word.line, word.col, word.text.chars().count()),
+                            "unreachable",
+                            "this code can never run because the shell leaves just before it"
+                                .into(),
+                            Some("remove it, or make the `exit`/`return` conditional".into()),
+                        );
+                    }
+                }
+            }
+            Node::Group(body) | Node::Subshell(body) => self.visit(body, checked),
+            Node::Background { body, .. } => self.visit(body, checked),
+            Node::Redirected(body, redirects) => {
+                for redirect in redirects {
+                    self.word(&redirect.target, true);
+                }
+                self.visit(body, checked);
+            }
+            Node::FunctionDef { body, .. } => self.visit(body, false),
+            Node::If {
+                branches,
+                otherwise,
+            } => {
+                for (cond, body) in branches {
+                    self.visit(cond, true);
+                    self.visit(body, checked);
+                }
+                if let Some(otherwise) = otherwise {
+                    self.visit(otherwise, checked);
+                }
+            }
+            Node::While { cond, body, .. } => {
+                self.visit(cond, true);
+                self.visit(body, checked);
+            }
+            Node::For { items, body, .. } => {
+                for item in items.iter().flatten() {
+                    self.word(item, true);
+                }
+                self.visit(body, checked);
+            }
+            Node::Case { word, arms } => {
+                self.word(word, false);
+                for (patterns, body) in arms {
+                    for pattern in patterns {
+                        self.word(pattern, false);
+                    }
+                    self.visit(body, checked);
+                }
+            }
+            Node::Time {
+                options, pipeline, ..
+            } => {
+                for option in options {
+                    self.word(option, true);
+                }
+                self.visit(pipeline, checked);
+            }
+            Node::Cond(words) => {
+                for (i, word) in words.iter().enumerate() {
+                    self.word(word, false);
+                    if word.text == "-v"
+                        && let Some(name) = words.get(i + 1)
+                    {
+                        self.used.insert(name.text.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    fn simple(&mut self, cmd: &SimpleCommand, checked: bool) {
+        for (name, value) in &cmd.assigns {
+            self.word(value, false);
+            if cmd.words.is_empty() {
+                let col = value.col.saturating_sub(name.chars().count() + 1);
+                self.assigned.push((name.clone(), value.line, col));
+            }
+        }
+        for redirect in &cmd.redirects {
+            self.word(&redirect.target, true);
+        }
+        let Some(first) = cmd.words.first() else {
+            return;
+        };
+        let declares = matches!(first.text.as_str(), "local" | "export" | "readonly");
+        for word in &cmd.words {
+            // `local x=$y` assigns without splitting, like a plain assignment
+            let assignment = declares && word.text.contains('=');
+            self.word(word, !assignment);
+        }
+        let args = &cmd.words[1..];
+        match first.text.as_str() {
+            "cd" if !checked => self.warn(
+                (first.line, first.col, 2),
+                "cd-unchecked",
+                "`cd` can fail, and the commands after it would run in the wrong directory".into(),
+                Some("use `cd dir || exit` (or `|| return` in a function)".into()),
+            ),
+            "local" => {
+                for word in args {
+                    if let Some((name, _)) = word.text.split_once('=') {
+                        self.assigned.push((name.to_string(), word.line, word.col));
+                    }
+                }
+            }
+            "export" => {
+                for word in args {
+                    let name = word.text.split('=').next().unwrap_or_default();
+                    self.used.insert(name.to_string());
+                }
+            }
+            "alias" => {
+                for word in args {
+                    if let Some((name, _)) = word.text.split_once('=')
+                        && BUILTINS.contains(&name)
+                    {
+                        self.warn(
+                            (word.line, word.col, name.chars().count()),
+                            "alias-shadows-builtin",
+                            format!("alias `{}` hides the `{}` builtin", name, name),
+                            Some("pick a different alias name".into()),
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Records the variables a word reads and, when `split` is set, flags unquoted expansions
+    fn word(&mut self, word: &Word, split: bool) {
+        for caps in self.var_use.captures_iter(&word.text) {
+            self.used.insert(caps[1].to_string());
+        }
+        for expr in arithmetic_parts(&word.text) {
+            for name in expr.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+                if name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+                    self.used.insert(name.to_string());
+                }
+            }
+        }
+        if !split {
+            return;
+        }
+        for (offset, expansion) in unquoted_expansions(&word.text) {
+            let length = expansion.chars().count();
+            self.warn(
+                (word.line, word.col + offset, length),
+                "unquoted-expansion",
+                format!(
+                    "`{}` is not quoted, so its value will be split on spaces and globbed",
+                    expansion
+                ),
+                Some(format!("write \"{}\"", expansion)),
+            );
+        }
+    }
+
+    fn unused_variables(&mut self) {
+        let assigned = std::mem::take(&mut self.assigned);
+        let mut reported = HashSet::new();
+        for (name, line, col) in assigned {
+            // Upper-case names are usually meant for the environment or the shell itself
+            let environmental = name.chars().all(|c| !c.is_lowercase());
+            if environmental || name.starts_with('_') || self.used.contains(&name) {
+                continue;
+            }
+            if reported.insert(name.clone()) {
+                self.warn(
+                    (line, col, name.chars().count()),
+                    "unused-variable",
+                    format!("`{}` is assigned but never used", name),
+                    Some("remove it, or prefix it with `_` if that is deliberate".into()),
+                );
+            }
+        }
+    }
+}
+
+/// `exit` or `return` as a whole command
+fn is_exit(node: &Node) -> bool {
+    matches!(node, Node::Simple(cmd) if cmd.words.first().is_some_and(|w| w.text == "exit" || w.text == "return"))
+}
+
+/// The first word written in a command, for pointing at it
+fn first_word(node: &Node) -> Option<&Word> {
+    match node {
+        Node::Simple(cmd) => cmd
+            .assigns
+            .first()
+            .map(|(_, value)| value)
+            .or(cmd.words.first())
+            .or(cmd.redirects.first().map(|r| &r.target)),
+        Node::Pipeline { stages: nodes, .. } | Node::List(nodes) => {
+            nodes.first().and_then(first_word)
+        }
+        Node::And(lhs, _) | Node::Or(lhs, _) => first_word(lhs),
+        Node::Group(body) | Node::Subshell(body) | Node::Redirected(body, _) => first_word(body),
+        Node::Background { body, .. } => first_word(body),
+        Node::FunctionDef { body, .. } => first_word(body),
+        Node::If { branches, .. } => branches.first().and_then(|(cond, _)| first_word(cond)),
+        Node::While { cond, .. } => first_word(cond),
+        Node::For { body, .. } => first_word(body),
+        Node::Case { word, .. } => Some(word),
+        Node::Cond(words) => words.first(),
+        Node::Time { pipeline, .. } => first_word(pipeline),
+    }
+}
+
+/// Contents of every `$(( ... ))` in a word, where variables appear without `$`
+fn arithmetic_parts(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("$((") {
+        let body = &rest[start + 3..];
+        let end = body.find("))").unwrap_or(body.len());
+        parts.push(&body[..end]);
+        rest = &body[end..];
+    }
+    parts
+}
+
+/// Index of the character closing the bracket opened just before `start`
+fn matching(chars: &[char], start: usize, open: char, close: char) -> usize {
+    let mut depth = 1;
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i] == open {
+            depth += 1;
+        } else if chars[i] == close {
+            depth -= 1;
+            if depth == 0 {
+                return i;
+            }
+        }
+        i += 1;
+    }
+    chars.len().saturating_sub(1)
+}
+
+/// Expansions outside double quotes that are subject to word splitting,
+/// with their character offset in the word
+fn unquoted_expansions(text: &str) -> Vec<(usize, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut found = Vec::new();
+    let mut in_double = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                i += 2;
+                continue;
+            }
+            '\'' if !in_double => {
+                i = chars[i + 1..]
+                    .iter()
+                    .position(|&c| c == '\'')
+                    .map_or(chars.len(), |p| i + p + 2);
+                continue;
+            }
+            '"' => in_double = !in_double,
+            '$' if i + 1 < chars.len() => {
+                let start = i;
+                let end = match chars[i + 1] {
+                    '(' if chars.get(i + 2) == Some(&'(') => {
+                        // Arithmetic only ever produces a number
+                        i = matching(&chars, i + 3, '(', ')') + 2;
+                        continue;
+                    }
+                    '(' => matching(&chars, i + 2, '(', ')'),
+                    '{' => {
+                        let end = matching(&chars, i + 2, '{', '}');
+                        if chars.get(i + 2) == Some(&'#') {
+                            i = end + 1;
+                            continue;
+                        }
+                        end
+                    }
+                    '@' | '*' | '0'..='9' => i + 1,
+                    c if c == '_' || c.is_alphabetic() => {
+                        let mut end = i + 1;
+                        while chars
+                            .get(end + 1)
+                            .is_some_and(|c| *c == '_' || c.is_alphanumeric())
+                        {
+                            end += 1;
+                        }
+                        end
+                    }
+                    _ => {
+                        i += 2;
+                        continue;
+                    }
+                };
+                if !in_double {
+                    found.push((start, chars[start..=end].iter().collect()));
+                }
+                i = end + 1;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    found
+}
+
+/// Lints one script, returning its diagnostics
+fn lint_source(file: &str, src: &str) -> Vec<Diagnostic> {
+    let ast = match parse(src) {
+        Ok(ast) => ast,
+        Err(e) => {
+            // The end of the input can be past the last line; point just after it instead
+            let (line, col) = match src.lines().count() {
+                last if e.line > last && last > 0 => {
+                    let text = src.lines().last().unwrap_or_default();
+                    (last, text.chars().count() + 1)
+                }
+                _ => (e.line, e.col),
+            };
+            return vec![Diagnostic {
+                file: file.to_string(),
+                line,
+                col,
+                length: 1,
+                severity: "error",
+                code: "syntax",
+                message: e.message,
+                help: None,
+            }];
+        }
+    };
+    let mut linter = Linter {
+        file,
+        diagnostics: Vec::new(),
+        assigned: Vec::new(),
+        used: HashSet::new(),
+        var_use: Regex::new(r"\$\{?[#!]?([A-Za-z_][A-Za-z0-9_]*)").expect("valid regex"),
+    };
+    linter.visit(&ast, false);
+    linter.unused_variables();
+    linter.diagnostics.sort_by_key(|d| (d.line, d.col));
+    linter.diagnostics
+}
+
+/// Aliases in the config that hide a builtin, located by their line in the TOML file
+fn lint_aliases() -> Vec<Diagnostic> {
+    let path = crate::config_file_path();
+    if !path.exists() {
+        return Vec::new();
+    }
+    let text = fs::read_to_string(&path).unwrap_or_default();
+    let mut names: Vec<String> = crate::load_config().aliases.into_keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .filter(|name| BUILTINS.contains(&name.as_str()))
+        .map(|name| {
+            let (line, col) = text
+                .lines()
+                .enumerate()
+                .find_map(|(i, line)| {
+                    let trimmed = line.trim_start();
+                    let key = trimmed
+                        .split('=')
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .trim_matches('"');
+                    (key == name).then(|| (i + 1, line.len() - trimmed.len() + 1))
+                })
+                .unwrap_or((1, 1));
+            Diagnostic {
+                file: path.display().to_string(),
+                line,
+                col,
+                length: name.chars().count(),
+                severity: "warning",
+                code: "alias-shadows-builtin",
+                message: format!("alias `{}` hides the `{}` builtin", name, name),
+                help: Some("rename the alias in the [aliases] table".into()),
+            }
+        })
+        .collect()
+}
+
+fn print_diagnostic(out: &mut impl Write, diag: &Diagnostic, src: &str) -> io::Result<()> {
+    let label = match diag.severity {
+        "error" => format!("{}[{}]", diag.severity, diag.code).red().bold(),
+        _ => format!("{}[{}]", diag.severity, diag.code).yellow().bold(),
+    };
+    writeln!(out, "{}: {}", label, diag.message.bold())?;
+    let number = diag.line.to_string();
+    let gutter = " ".repeat(number.len());
+    writeln!(
+        out,
+        " {}{} {}:{}:{}",
+        gutter,
+        "-->".blue(),
+        diag.file,
+        diag.line,
+        diag.col
+    )?;
+    if let Some(line) = src.lines().nth(diag.line.saturating_sub(1)) {
+        // Keep tabs so the caret lines up under the same characters
+        let indent: String = line
+            .chars()
+            .take(diag.col.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        writeln!(out, " {} {}", gutter, "|".blue())?;
+        writeln!(out, " {} {} {}", number.blue(), "|".blue(), line)?;
+        writeln!(
+            out,
+            " {} {} {}{}",
+            gutter,
+            "|".blue(),
+            indent,
+            "^".repeat(diag.length.max(1)).yellow().bold()
+        )?;
+        if let Some(help) = &diag.help {
+            writeln!(out, " {} {} help: {}", gutter, "=".blue(), help)?;
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Writes the diagnostics and a summary, or the diagnostics as JSON
+fn report(
+    all: &[Diagnostic],
+    sources: &[(String, String)],
+    checked: usize,
+    unreadable: usize,
+    json: bool,
+) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    if json {
+        let text = serde_json::to_string_pretty(all).map_err(io::Error::other)?;
+        return writeln!(out, "{}", text);
+    }
+    for diag in all {
+        let src = sources
+            .iter()
+            .find(|(file, _)| *file == diag.file)
+            .map_or("", |(_, src)| src.as_str());
+        print_diagnostic(&mut out, diag, src)?;
+    }
+    let errors = all.iter().filter(|d| d.severity == "error").count();
+    let warnings = all.len() - errors;
+    if all.is_empty() && unreadable == 0 {
+        writeln!(
+            out,
+            "{} no problems found in {} file(s)",
+            "✔".green(),
+            checked
+        )
+    } else if unreadable > 0 {
+        writeln!(
+            out,
+            "{} error(s), {} warning(s), {} file(s) could not be read",
+            errors, warnings, unreadable
+        )
+    } else {
+        writeln!(out, "{} error(s), {} warning(s)", errors, warnings)
+    }
+}
+
+/// `xinux -n [--json] FILE...` and `xinux lint [--json] [FILE...]`.
+/// Without files it checks the startup files and the config's aliases.
+pub fn run_lint(args: &[String]) -> i32 {
+    let json = args.iter().any(|a| a == "--json");
+    let mut files: Vec<PathBuf> = args
+        .iter()
+        .filter(|a| *a != "--json")
+        .map(PathBuf::from)
+        .collect();
+    let check_setup = files.is_empty();
+    if check_setup {
+        files = [
+            crate::profile_file_path(),
+            crate::rc_file_path(),
+            crate::logout_file_path(),
+        ]
+        .into_iter()
+        .filter(|p| p.exists())
+        .collect();
+    }
+
+    let mut all = Vec::new();
+    let mut sources = Vec::new();
+    let mut unreadable = 0;
+    for path in &files {
+        match fs::read_to_string(path) {
+            Ok(src) => {
+                all.extend(lint_source(&path.display().to_string(), &src));
+                sources.push((path.display().to_string(), src));
+            }
+            Err(e) => {
+                eprintln!("{}", format!("xinux: {}: {}", path.display(), e).red());
+                unreadable += 1;
+            }
+        }
+    }
+    if check_setup {
+        let aliases = lint_aliases();
+        if let Some(first) = aliases.first() {
+            let text = fs::read_to_string(Path::new(&first.file)).unwrap_or_default();
+            sources.push((first.file.clone(), text));
+        }
+        all.extend(aliases);
+    }
+
+    let written = report(&all, &sources, files.len() - unreadable, unreadable, json);
+    if let Err(e) = written
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        eprintln!("{}", format!("xinux: lint: {}", e).red());
+    }
+
+    if unreadable > 0 {
+        2
+    } else {
+        i32::from(!all.is_empty())
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs;
+
+/// A distribution's logo and the color it is drawn in
+pub struct Logo {
+    /// `/etc/os-release` `ID`s it belongs to; the first is its name in `config.toml`
+    pub ids: &'static [&'static str],
+    pub color: &'static str,
+    pub art: &'static str,
+}
+
+const LOGOS: &[Logo] = &[
+    Logo {
+        ids: &["arch", "archarm", "archlinux"],
+        color: "bright_cyan",
+        art: r"
+      /\
+     /  \
+    /\   \
+   /      \
+  /   ,,   \
+ /   |  |  -\
+/_-''    ''-_\",
+    },
+    Logo {
+        ids: &["debian", "raspbian"],
+        color: "bright_red",
+        art: r"
+  _____
+ /  __ \
+|  /    |
+|  \___-
+-_
+  --_",
+    },
+    Logo {
+        ids: &["ubuntu"],
+        color: "#e95420",
+        art: r"
+         _
+     ---(_)
+ _/  ---  \
+(_) |   |
+  \  --- _/
+     ---(_)",
+    },
+    Logo {
+        ids: &["fedora"],
+        color: "bright_blue",
+        art: r"
+      _____
+     /   __)\
+     |  /  \ \
+  ___|  |__/ /
+ / (_    _)_/
+/ /  |  |
+\ \__/  |
+ \(_____/",
+    },
+    Logo {
+        ids: &["linuxmint", "mint"],
+        color: "bright_green",
+        art: r"
+ ___________
+|_          \
+  | | _____ |
+  | | | | | |
+  | | | | | |
+  | \_____/ |
+  \_________/",
+    },
+    Logo {
+        ids: &["manjaro", "manjaro-arm"],
+        color: "green",
+        art: r"
+||||||||| ||||
+||||||||| ||||
+||||      ||||
+|||| |||| ||||
+|||| |||| ||||
+|||| |||| ||||
+|||| |||| ||||",
+    },
+    Logo {
+        ids: &[
+            "opensuse",
+            "opensuse-tumbleweed",
+            "opensuse-leap",
+            "suse",
+            "sles",
+        ],
+        color: "bright_green",
+        art: r"
+  _______
+__|   __ \
+     / .\ \
+     \__/ |
+   _______|
+   \_______
+__________/",
+    },
+    Logo {
+        ids: &["gentoo"],
+        color: "bright_magenta",
+        art: r"
+ _-----_
+(       \
+\    0   \
+ \        )
+ /      _/
+(     _-
+\____-",
+    },
+    Logo {
+        ids: &["alpine"],
+        color: "blue",
+        art: r"
+   /\ /\
+  /  \  \
+ /    \  \
+/      \  \
+\       \  \
+ \          \",
+    },
+    Logo {
+        ids: &["void"],
+        color: "green",
+        art: r"
+    _______
+ _ \______ -
+| \  ___  \ |
+| | /   \ | |
+| | \___/ | |
+| \______ \_|
+ -_______\",
+    },
+    Logo {
+        ids: &["nixos"],
+        color: "bright_blue",
+        art: r"
+  \\  \\ //
+ ==\\__\\/ //
+   //   \\//
+==//     //==
+ //\\___//
+// /\\  \\==
+  // \\  \\",
+    },
+    Logo {
+        ids: &["pop", "pop-os"],
+        color: "bright_cyan",
+        art: r"
+______
+\   _ \        __
+ \ \ \ \      / /
+  \ \_\ \    / /
+   \  ___\  /_/
+    \ \    _
+   __\_\__(_)_
+  (___________)",
+    },
+    Logo {
+        ids: &["endeavouros"],
+        color: "magenta",
+        art: r"
+          /o.
+        /sssso-
+      /ossssssso:
+    /ssssssssssso+
+  /ssssssssssssssso+
+//osssssssssssssso+-
+  `+++++++++++++++-`",
+    },
+    Logo {
+        ids: &["linux"],
+        color: "bright_yellow",
+        art: r"
+    ___
+   (.. |
+   (<> |
+  / __  \
+ ( /  \ /|
+_/\ __)/_)
+\/-____\/",
+    },
+];
+
+impl Logo {
+    /// The art's lines, without the newline that opens each table entry
+    pub fn lines(&self) -> Vec<&'static str> {
+        self.art
+            .strip_prefix('\n')
+            .unwrap_or(self.art)
+            .lines()
+            .collect()
+    }
+}
+
+/// The logo named `id`, as in `logo = "arch"`
+pub fn find(id: &str) -> Option<&'static Logo> {
+    let id = id.to_lowercase();
+    LOGOS.iter().find(|logo| logo.ids.contains(&id.as_str()))
+}
+
+/// The logo for `ID` in `/etc/os-release`, else the first of `ID_LIKE` that has one,
+/// else Tux
+pub fn detect() -> &'static Logo {
+    os_release_ids()
+        .iter()
+        .find_map(|id| find(id))
+        // Tux closes the table
+        .unwrap_or(&LOGOS[LOGOS.len() - 1])
+}
+
+/// `ID` followed by the words of `ID_LIKE`
+fn os_release_ids() -> Vec<String> {
+    let text = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        .unwrap_or_default();
+    let value = |key: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|v| v.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+    };
+    let mut ids: Vec<String> = value("ID").into_iter().collect();
+    if let Some(like) = value("ID_LIKE") {
+        ids.extend(like.split_whitespace().map(str::to_string));
+    }
+    ids
+}
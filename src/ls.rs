@@ -0,0 +1,762 @@
+use std::env;
+use std::ffi::CStr;
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use colored::{ColoredString, Colorize};
+use glob::Pattern;
+use unicode_width::UnicodeWidthStr;
+
+use crate::filetypes;
+use crate::git;
+use crate::records::{self, Format};
+
+const USAGE: &str = "usage: ls [-aAlC1SXtrdRh] [--tree[=DEPTH]] [--dirs-first] [--ignore=GLOB] \
+[--git] [--git-ignore] [--sniff] [--json|--csv|--plain] [path...]";
+
+/// How entries are laid out
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    /// The usual two lines per entry under a folder header
+    Tree,
+    /// `-l`: permissions, links, owner, group, size and date
+    Long,
+    /// `-C`: names in columns that fit the terminal
+    Grid,
+    /// `-1`: one name per line
+    Single,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortBy {
+    Name,
+    Size,
+    Time,
+    Extension,
+}
+
+pub(crate) struct Options {
+    show_hidden: bool,
+    layout: Layout,
+    sort: SortBy,
+    reverse: bool,
+    dirs_first: bool,
+    /// `-d`: list directories themselves, not their contents
+    directory: bool,
+    recursive: bool,
+    /// `--tree[=DEPTH]`: nested listing down to this depth
+    tree_depth: Option<usize>,
+    ignore: Vec<Pattern>,
+    /// `--git`: show each entry's status in its repository
+    pub git: bool,
+    /// `--git-ignore`: leave out what `.gitignore` ignores
+    git_ignore: bool,
+    /// `--sniff`: tell file types from their first bytes, not only their names
+    sniff: bool,
+    pub format: Format,
+    paths: Vec<String>,
+}
+
+/// One directory entry as the `ls` builtin sees it
+pub(crate) struct LsEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub metadata: Metadata,
+    pub label: ColoredString,
+    pub git: Option<git::Status>,
+}
+
+impl LsEntry {
+    fn new(name: String, path: PathBuf, metadata: Metadata, opts: &Options) -> Self {
+        let file_name = path
+            .file_name()
+            .map_or(name.clone(), |n| n.to_string_lossy().into());
+        LsEntry {
+            label: if opts.sniff {
+                filetypes::classify_content(&path, &file_name, &metadata).label()
+            } else {
+                filetypes::classify(&file_name, Some(&metadata)).label()
+            },
+            git: (opts.git || opts.git_ignore)
+                .then(|| git::status(&path, &metadata))
+                .flatten(),
+            name,
+            path,
+            metadata,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.metadata.len()
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        self.metadata.modified().unwrap_or(UNIX_EPOCH)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.metadata.is_dir()
+    }
+
+    fn is_ignored(&self) -> bool {
+        self.git == Some(git::Status::Ignored)
+    }
+
+    /// The name in the colors of its type tag, dimmed when git ignores it
+    fn colored_name(&self) -> ColoredString {
+        if self.is_ignored() {
+            return self.name.dimmed();
+        }
+        let mut name = self.label.clone();
+        name.input = self.name.clone();
+        name
+    }
+
+    /// The colored name, after its git status code when `marked`
+    fn marked_name(&self, marked: bool) -> String {
+        if !marked {
+            return self.colored_name().to_string();
+        }
+        let code = self.git.unwrap_or(git::Status::Clean).code();
+        format!("{} {}", code, self.colored_name())
+    }
+}
+
+/// Parses `ls` flags; `la` is `ls -a`. Short flags can be combined (`-laS`).
+pub(crate) fn parse_options(cmd: &str, args: &[&str]) -> Result<Options, String> {
+    let mut opts = Options {
+        show_hidden: cmd == "la",
+        layout: Layout::Tree,
+        sort: SortBy::Name,
+        reverse: false,
+        dirs_first: false,
+        directory: false,
+        recursive: false,
+        tree_depth: None,
+        ignore: Vec::new(),
+        git: false,
+        git_ignore: false,
+        sniff: false,
+        format: Format::Pretty,
+        paths: Vec::new(),
+    };
+    let glob = |text: &str| {
+        Pattern::new(text).map_err(|e| format!("invalid --ignore pattern `{}`: {}", text, e))
+    };
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        if arg == "--" {
+            opts.paths.extend(args.by_ref().map(|a| a.to_string()));
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            match (name, value) {
+                ("all", None) => opts.show_hidden = true,
+                ("reverse", None) => opts.reverse = true,
+                ("recursive", None) => opts.recursive = true,
+                ("directory", None) => opts.directory = true,
+                ("dirs-first", None) => opts.dirs_first = true,
+                ("git", None) => opts.git = true,
+                ("git-ignore", None) => opts.git_ignore = true,
+                ("sniff", None) => opts.sniff = true,
+                ("json", None) => opts.format = Format::Json,
+                ("csv", None) => opts.format = Format::Csv,
+                ("plain", None) => opts.format = Format::Plain,
+                ("tree", None) => opts.tree_depth = Some(usize::MAX),
+                ("tree", Some(depth)) => match depth.parse() {
+                    Ok(depth) if depth > 0 => opts.tree_depth = Some(depth),
+                    _ => return Err(format!("--tree: `{}` is not a depth", depth)),
+                },
+                ("ignore", Some(pattern)) => opts.ignore.push(glob(pattern)?),
+                ("ignore", None) => match args.next() {
+                    Some(pattern) => opts.ignore.push(glob(pattern)?),
+                    None => return Err("--ignore needs a pattern".into()),
+                },
+                _ => return Err(format!("unknown option `{}`\n{}", arg, USAGE)),
+            }
+        } else if let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) {
+            for (i, flag) in flags.char_indices() {
+                match flag {
+                    'a' | 'A' => opts.show_hidden = true,
+                    'l' => opts.layout = Layout::Long,
+                    'C' => opts.layout = Layout::Grid,
+                    '1' => opts.layout = Layout::Single,
+                    'S' => opts.sort = SortBy::Size,
+                    't' => opts.sort = SortBy::Time,
+                    'X' => opts.sort = SortBy::Extension,
+                    'r' => opts.reverse = true,
+                    'd' => opts.directory = true,
+                    'R' => opts.recursive = true,
+                    // Sizes are always human-readable
+                    'h' => {}
+                    'I' => {
+                        // The pattern is the rest of this word or the next argument
+                        let rest = &flags[i + 1..];
+                        let pattern = match rest {
+                            "" => args.next().ok_or("-I needs a pattern")?,
+                            rest => rest,
+                        };
+                        opts.ignore.push(glob(pattern)?);
+                        break;
+                    }
+                    _ => return Err(format!("unknown option `-{}`\n{}", flag, USAGE)),
+                }
+            }
+        } else {
+            opts.paths.push(expand_home(arg));
+        }
+    }
+    if opts.paths.is_empty() {
+        opts.paths.push(".".to_string());
+    }
+    Ok(opts)
+}
+
+fn expand_home(path: &str) -> String {
+    if path.starts_with('~') {
+        path.replacen('~', &env::var("HOME").unwrap_or("/".into()), 1)
+    } else {
+        path.to_string()
+    }
+}
+
+fn error(message: String) {
+    eprintln!("{}", format!("ls: {}", message).red());
+}
+
+/// The `ls` and `la` builtins
+pub fn builtin_ls(cmd: &str, args: &[&str]) -> i32 {
+    let opts = match parse_options(cmd, args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            error(e);
+            return 2;
+        }
+    };
+    git::forget();
+    if opts.format != Format::Pretty {
+        let (entries, status) = collect(&opts);
+        records::print_records(&records::from_entries(entries, opts.git), opts.format);
+        return status;
+    }
+
+    let mut status = 0;
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for path in &opts.paths {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if opts.directory || !Path::new(path).is_dir() => {
+                files.push(LsEntry::new(
+                    path.clone(),
+                    PathBuf::from(path),
+                    metadata,
+                    &opts,
+                ));
+            }
+            Ok(_) => dirs.push(path.as_str()),
+            Err(e) => {
+                error(format!("cannot access '{}': {}", path, e));
+                status = 1;
+            }
+        }
+    }
+
+    if !files.is_empty() {
+        sort_entries(&mut files, &opts);
+        show(&files, None, &opts);
+    }
+    let headers = dirs.len() > 1 || !files.is_empty() || opts.recursive;
+    for dir in dirs {
+        if list_dir(Path::new(dir), headers, &opts).is_err() {
+            status = 1;
+        }
+    }
+    status
+}
+
+/// Lists one directory, and its subdirectories with `-R`
+fn list_dir(dir: &Path, header: bool, opts: &Options) -> Result<(), ()> {
+    let entries = match read_entries(dir, opts) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error(format!("{}: {}", dir.display(), e));
+            return Err(());
+        }
+    };
+    let title = dir.to_string_lossy();
+    if let Some(depth) = opts.tree_depth {
+        print_folder(&title);
+        print_tree(&entries, "", depth, opts);
+        return Ok(());
+    }
+    show(
+        &entries,
+        (header || opts.layout == Layout::Tree).then_some(&title),
+        opts,
+    );
+
+    let mut result = Ok(());
+    if opts.recursive {
+        for entry in entries.iter().filter(|e| e.is_dir()) {
+            if list_dir(&entry.path, true, opts).is_err() {
+                result = Err(());
+            }
+        }
+    }
+    result
+}
+
+/// Reads `dir` in the order the options ask for, leaving out hidden and ignored names
+pub(crate) fn read_entries(dir: &Path, opts: &Options) -> io::Result<Vec<LsEntry>> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !opts.show_hidden && name.starts_with('.') {
+            continue;
+        }
+        if opts.ignore.iter().any(|p| p.matches(&name)) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let mut entry = LsEntry::new(name, entry.path(), metadata, opts);
+        if opts.git_ignore && entry.is_ignored() {
+            continue;
+        }
+        if !opts.git {
+            entry.git = None;
+        }
+        entries.push(entry);
+    }
+    sort_entries(&mut entries, opts);
+    Ok(entries)
+}
+
+fn sort_entries(entries: &mut [LsEntry], opts: &Options) {
+    let name = |e: &LsEntry| e.name.to_lowercase();
+    match opts.sort {
+        SortBy::Name => entries.sort_by_key(name),
+        SortBy::Size => entries.sort_by(|a, b| b.size().cmp(&a.size()).then(name(a).cmp(&name(b)))),
+        SortBy::Time => {
+            entries.sort_by(|a, b| b.modified().cmp(&a.modified()).then(name(a).cmp(&name(b))))
+        }
+        SortBy::Extension => entries.sort_by_key(|e| {
+            let ext = e
+                .path
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase());
+            (ext, name(e))
+        }),
+    }
+    if opts.reverse {
+        entries.reverse();
+    }
+    if opts.dirs_first {
+        entries.sort_by_key(|e| !e.is_dir());
+    }
+}
+
+/// Every entry the options select, for `--json`, `--csv`, `--plain` and record pipelines.
+/// Entries below the listed directories are named by their path.
+pub(crate) fn collect(opts: &Options) -> (Vec<LsEntry>, i32) {
+    fn walk(dir: &Path, prefix: &str, depth: usize, opts: &Options, out: &mut Vec<LsEntry>) -> i32 {
+        let entries = match read_entries(dir, opts) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error(format!("{}: {}", dir.display(), e));
+                return 1;
+            }
+        };
+        let mut status = 0;
+        for mut entry in entries {
+            entry.name = format!("{}{}", prefix, entry.name);
+            let below = (entry.is_dir() && depth > 1).then(|| entry.path.clone());
+            let prefix = format!("{}/", entry.name);
+            out.push(entry);
+            if let Some(dir) = below {
+                status |= walk(&dir, &prefix, depth - 1, opts, out);
+            }
+        }
+        status
+    }
+
+    let depth = match (opts.tree_depth, opts.recursive) {
+        (Some(depth), _) => depth,
+        (None, true) => usize::MAX,
+        (None, false) => 1,
+    };
+    let several = opts.paths.len() > 1;
+    let mut out = Vec::new();
+    let mut status = 0;
+    for path in &opts.paths {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if opts.directory || !Path::new(path).is_dir() => {
+                out.push(LsEntry::new(
+                    path.clone(),
+                    PathBuf::from(path),
+                    metadata,
+                    opts,
+                ));
+            }
+            Ok(_) => {
+                let prefix = if several || depth > 1 && path != "." {
+                    format!("{}/", path.trim_end_matches('/'))
+                } else {
+                    String::new()
+                };
+                status |= walk(Path::new(path), &prefix, depth, opts, &mut out);
+            }
+            Err(e) => {
+                error(format!("cannot access '{}': {}", path, e));
+                status = 1;
+            }
+        }
+    }
+    (out, status)
+}
+
+fn print_folder(title: &str) {
+    println!(
+        "\n{} {}",
+        "📁".bold(),
+        title
+            .replace(&env::var("HOME").unwrap_or_default(), "~")
+            .bright_white()
+            .bold()
+    );
+}
+
+/// Prints entries in the chosen layout, under a header when given one
+fn show(entries: &[LsEntry], header: Option<&str>, opts: &Options) {
+    match opts.layout {
+        Layout::Tree => {
+            if let Some(title) = header {
+                print_folder(title);
+            }
+            let count = entries.len();
+            for (i, entry) in entries.iter().enumerate() {
+                let is_last = i == count - 1;
+                let branch = if is_last { "└──" } else { "├──" };
+                let pipe = if is_last { "    " } else { "│   " };
+
+                let datetime: DateTime<Local> = entry.modified().into();
+                let name = if entry.is_ignored() {
+                    entry.name.dimmed()
+                } else {
+                    entry.name.bright_white().bold()
+                };
+                println!("{} {}", branch, name);
+                let mut details = format!(
+                    "{} {}   {}   {}",
+                    pipe,
+                    format_size(entry.size()).bright_green(),
+                    entry.label,
+                    datetime.format("%Y-%m-%d %H:%M").to_string().dimmed()
+                );
+                if let Some(status) = entry.git.filter(|s| *s != git::Status::Clean) {
+                    details = format!("{}   {}", details, status.tag());
+                }
+                println!("{}", details);
+            }
+        }
+        layout => {
+            if let Some(title) = header {
+                println!("\n{}:", title.bold());
+            }
+            // With `--git` the short layouts put a two-letter code before each name
+            let marked = entries.iter().any(|e| e.git.is_some());
+            match layout {
+                Layout::Long => print_long(entries),
+                Layout::Grid => print_grid(entries, marked),
+                _ => {
+                    for entry in entries {
+                        println!("{}", entry.marked_name(marked));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `--tree`: names nested under their directories, down to `depth` levels
+fn print_tree(entries: &[LsEntry], prefix: &str, depth: usize, opts: &Options) {
+    for (i, entry) in entries.iter().enumerate() {
+        let is_last = i == entries.len() - 1;
+        let branch = if is_last { "└──" } else { "├──" };
+        match entry.git.filter(|s| *s != git::Status::Clean) {
+            Some(status) => println!(
+                "{}{} {}  {}",
+                prefix,
+                branch,
+                entry.colored_name(),
+                status.tag()
+            ),
+            None => println!("{}{} {}", prefix, branch, entry.colored_name()),
+        }
+        if entry.is_dir() && depth > 1 {
+            let prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+            match read_entries(&entry.path, opts) {
+                Ok(children) => print_tree(&children, &prefix, depth - 1, opts),
+                Err(e) => println!("{}{}", prefix, format!("[{}]", e).red()),
+            }
+        }
+    }
+}
+
+fn print_long(entries: &[LsEntry]) {
+    let rows: Vec<[String; 5]> = entries
+        .iter()
+        .map(|e| {
+            let m = &e.metadata;
+            [
+                permissions(m),
+                m.nlink().to_string(),
+                user_name(m.uid()),
+                group_name(m.gid()),
+                format_size(e.size()),
+            ]
+        })
+        .collect();
+    let width = |col: usize| rows.iter().map(|r| r[col].width()).max().unwrap_or(0);
+    let widths = [width(0), width(1), width(2), width(3), width(4)];
+    // The git column only appears with `--git`, wide enough for the longest status
+    let git_width = entries
+        .iter()
+        .filter_map(|e| e.git)
+        .filter(|s| *s != git::Status::Clean)
+        .map(|s| s.name().len())
+        .max();
+
+    for (entry, row) in entries.iter().zip(&rows) {
+        let datetime: DateTime<Local> = entry.modified().into();
+        let mut name = entry.colored_name().to_string();
+        if entry.metadata.file_type().is_symlink()
+            && let Ok(target) = fs::read_link(&entry.path)
+        {
+            name = format!("{} -> {}", name, target.display());
+        }
+        if let Some(width) = git_width {
+            let status = entry.git.filter(|s| *s != git::Status::Clean);
+            let pad = width - status.map_or(0, |s| s.name().len());
+            let tag = status.map(|s| s.tag().to_string()).unwrap_or_default();
+            name = format!("{}{} {}", tag, " ".repeat(pad), name);
+        }
+        println!(
+            "{} {:>w1$} {:<w2$} {:<w3$} {:>w4$} {} {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4].bright_green(),
+            datetime.format("%Y-%m-%d %H:%M").to_string().dimmed(),
+            name,
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        );
+    }
+}
+
+/// `drwxr-xr-x` for the file's type and mode
+fn permissions(metadata: &Metadata) -> String {
+    let kind = metadata.file_type();
+    let mut text = String::with_capacity(10);
+    text.push(if kind.is_dir() {
+        'd'
+    } else if kind.is_symlink() {
+        'l'
+    } else if kind.is_fifo() {
+        'p'
+    } else if kind.is_socket() {
+        's'
+    } else if kind.is_char_device() {
+        'c'
+    } else if kind.is_block_device() {
+        'b'
+    } else {
+        '-'
+    });
+    let mode = metadata.permissions().mode();
+    // Set-id and sticky bits replace the execute letter of their triple
+    let specials = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+    for (shift, (special, letter)) in [6, 3, 0].into_iter().zip(specials) {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 2 != 0 { 'w' } else { '-' });
+        text.push(match (bits & 1 != 0, mode & special != 0) {
+            (true, true) => letter,
+            (false, true) => letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
+}
+
+fn user_name(uid: u32) -> String {
+    // SAFETY: getpwuid returns null or a record in static storage, copied out at once.
+    unsafe {
+        let pw = libc::getpwuid(uid);
+        if pw.is_null() {
+            return uid.to_string();
+        }
+        CStr::from_ptr((*pw).pw_name).to_string_lossy().into_owned()
+    }
+}
+
+fn group_name(gid: u32) -> String {
+    // SAFETY: getgrgid returns null or a record in static storage, copied out at once.
+    unsafe {
+        let gr = libc::getgrgid(gid);
+        if gr.is_null() {
+            return gid.to_string();
+        }
+        CStr::from_ptr((*gr).gr_name).to_string_lossy().into_owned()
+    }
+}
+
+/// Columns of stdout, falling back to `$COLUMNS` and then 80
+pub(crate) fn terminal_width() -> usize {
+    // SAFETY: TIOCGWINSZ only fills in the zeroed struct.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+        return size.ws_col.into();
+    }
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Names in as few rows as fit the terminal, filled column by column
+fn print_grid(entries: &[LsEntry], marked: bool) {
+    if entries.is_empty() {
+        return;
+    }
+    let code_width = if marked { 3 } else { 0 };
+    let widths: Vec<usize> = entries
+        .iter()
+        .map(|e| e.name.width() + code_width)
+        .collect();
+    let max = terminal_width();
+    let count = entries.len();
+    let (rows, columns) = (1..=count)
+        .map(|rows| {
+            let columns: Vec<usize> = widths
+                .chunks(rows)
+                .map(|c| c.iter().copied().max().unwrap_or(0))
+                .collect();
+            (rows, columns)
+        })
+        .find(|(_, columns)| columns.iter().sum::<usize>() + 2 * (columns.len() - 1) <= max)
+        .unwrap_or_else(|| (count, vec![widths.iter().copied().max().unwrap_or(0)]));
+
+    for row in 0..rows {
+        let mut line = String::new();
+        for (column, width) in columns.iter().enumerate() {
+            let index = column * rows + row;
+            let Some(entry) = entries.get(index) else {
+                break;
+            };
+            line.push_str(&entry.marked_name(marked));
+            if entries.get(index + rows).is_some() {
+                line.push_str(&" ".repeat(width - widths[index] + 2));
+            }
+        }
+        println!("{}", line);
+    }
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A directory of files with known sizes and ages, removed when dropped
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(test: &str) -> Self {
+            let dir = env::temp_dir().join(format!("xinux-ls-{}-{}", test, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("sub")).unwrap();
+            // Name, size and age in hours
+            for (name, size, age) in [
+                ("b.txt", 30, 3),
+                ("A.rs", 10, 1),
+                ("c.md", 20, 2),
+                (".hidden", 5, 4),
+            ] {
+                let path = dir.join(name);
+                fs::write(&path, vec![b'x'; size]).unwrap();
+                let time = SystemTime::now() - Duration::from_secs(age * 3600);
+                fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(time)
+                    .unwrap();
+            }
+            Fixture(dir)
+        }
+
+        fn names(&self, args: &[&str]) -> Vec<String> {
+            let opts = parse_options("ls", args).unwrap();
+            read_entries(&self.0, &opts)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn sort_keys_order_entries() {
+        let dir = Fixture::new("sort");
+        assert_eq!(dir.names(&[]), ["A.rs", "b.txt", "c.md", "sub"]);
+        assert_eq!(dir.names(&["-S", "-I", "sub"]), ["b.txt", "c.md", "A.rs"]);
+        assert_eq!(dir.names(&["-t"])[..3], ["sub", "A.rs", "c.md"]);
+        assert_eq!(dir.names(&["-X"]), ["sub", "c.md", "A.rs", "b.txt"]);
+        assert_eq!(dir.names(&["-SrIsub"]), ["A.rs", "c.md", "b.txt"]);
+        assert_eq!(
+            dir.names(&["-r", "--dirs-first"]),
+            ["sub", "c.md", "b.txt", "A.rs"]
+        );
+    }
+
+    #[test]
+    fn hidden_and_ignored_names_are_filtered() {
+        let dir = Fixture::new("filter");
+        assert_eq!(dir.names(&["-a"])[0], ".hidden");
+        assert_eq!(dir.names(&["-I*.rs", "--ignore", "s*"]), ["b.txt", "c.md"]);
+        assert_eq!(dir.names(&["--ignore=*.*", "-A"]), ["sub"]);
+        assert!(parse_options("ls", &["--ignore=[", "."]).is_err());
+        assert!(parse_options("ls", &["-I"]).is_err());
+        assert!(parse_options("la", &[]).unwrap().show_hidden);
+    }
+}
@@ -1,5 +1,4 @@
 use execute::execute;
-use hostname;
 use rustyline::completion::{Completer, Pair};
 use rustyline::config::Builder as ConfigBuilder;
 use rustyline::error::ReadlineError;
@@ -8,16 +7,46 @@ use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::{Context, Editor, Helper, hint::Hinter};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::env;
 use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
-
+use std::path::{Path, PathBuf};
+use std::process;
+
+pub mod argparse;
+pub mod arith;
+pub mod bench;
+pub mod cond;
+pub mod debugger;
+pub mod detect;
+pub mod eval;
 pub mod execute;
 pub mod execute2;
 pub mod execute3;
+pub mod expand;
+pub mod filetypes;
+pub mod getopts;
+pub mod git;
+pub mod git_objects;
+pub mod info;
+pub mod jobs;
+pub mod lint;
+pub mod logos;
+pub mod ls;
+pub mod packages;
+pub mod parse_input;
+pub mod printf;
+pub mod procs;
+pub mod read;
+pub mod records;
+pub mod sniff;
+pub mod state;
+pub mod timing;
+pub mod top;
+pub mod touch;
 pub mod xinsays;
 
 #[derive(Serialize, Deserialize)]
@@ -25,6 +54,12 @@ struct XinuxConfig {
     prompt_style: String,
     aliases: HashMap<String, String>,
     autostart_commands: Vec<String>, // New field for autostart commands
+    #[serde(default)]
+    functions: HashMap<String, String>, // Saved with `funcsave`
+    #[serde(default)]
+    file_types: BTreeMap<String, filetypes::FileTypeConfig>,
+    #[serde(default)]
+    info: info::InfoConfig,
 }
 
 impl Default for XinuxConfig {
@@ -33,6 +68,9 @@ impl Default for XinuxConfig {
             prompt_style: "single_line".into(),
             aliases: HashMap::new(),
             autostart_commands: Vec::new(), // Default to an empty list
+            functions: HashMap::new(),
+            file_types: BTreeMap::new(),
+            info: info::InfoConfig::default(),
         }
     }
 }
@@ -50,10 +88,33 @@ fn config_file_path() -> PathBuf {
     xinux_dir().join("config.toml")
 }
 
+/// The config file as it stands, without the first-time setup when there is none
+fn read_config() -> Option<XinuxConfig> {
+    toml::from_str(&fs::read_to_string(config_file_path()).ok()?).ok()
+}
+
 fn history_file_path() -> PathBuf {
     xinux_dir().join("history.txt")
 }
 
+/// Startup file for interactive shells; `XINUX_RC` points somewhere else
+fn rc_file_path() -> PathBuf {
+    match env::var_os("XINUX_RC") {
+        Some(path) => PathBuf::from(path),
+        None => xinux_dir().join("xinuxrc"),
+    }
+}
+
+/// Sourced by login shells before the rc file
+fn profile_file_path() -> PathBuf {
+    xinux_dir().join("profile")
+}
+
+/// Sourced when a login shell exits
+fn logout_file_path() -> PathBuf {
+    xinux_dir().join("logout")
+}
+
 fn load_or_create_config() -> XinuxConfig {
     let config_path = config_file_path();
     if config_path.exists() {
@@ -157,10 +218,10 @@ fn load_or_create_config() -> XinuxConfig {
             io::stdout().flush().unwrap();
             let mut input = String::new();
             io::stdin().read_line(&mut input).unwrap();
-            if let Ok(idx) = input.trim().parse::<usize>() {
-                if idx < prompt_options.len() {
-                    break prompt_options[idx].0.to_string();
-                }
+            if let Ok(idx) = input.trim().parse::<usize>()
+                && idx < prompt_options.len()
+            {
+                break prompt_options[idx].0.to_string();
             }
             println!("Invalid selection. Try again.");
         };
@@ -170,8 +231,7 @@ fn load_or_create_config() -> XinuxConfig {
             ..Default::default()
         };
 
-        fs::write(&config_path, toml::to_string(&config).unwrap())
-            .expect("Could not write config");
+        fs::write(&config_path, toml::to_string(&config).unwrap()).expect("Could not write config");
 
         println!(
             "\n\x1b[32m✔ Configuration saved to {}\x1b[0m",
@@ -181,7 +241,7 @@ fn load_or_create_config() -> XinuxConfig {
     }
 }
 
-fn reselect_prompt_style() {
+fn reselect_prompt_style() -> i32 {
     let prompt_options = vec![
         (
             "single_line",
@@ -278,10 +338,10 @@ fn reselect_prompt_style() {
         io::stdout().flush().unwrap();
         let mut input = String::new();
         io::stdin().read_line(&mut input).unwrap();
-        if let Ok(idx) = input.trim().parse::<usize>() {
-            if idx < prompt_options.len() {
-                break prompt_options[idx].0.to_string();
-            }
+        if let Ok(idx) = input.trim().parse::<usize>()
+            && idx < prompt_options.len()
+        {
+            break prompt_options[idx].0.to_string();
         }
         println!("Invalid selection. Try again.");
     };
@@ -290,7 +350,7 @@ fn reselect_prompt_style() {
     config.prompt_style = style;
     save_config(&config);
     println!("\n\x1b[32m✔ Prompt style updated. Reloading shell...\x1b[0m");
-    main();
+    run_shell()
 }
 
 struct XinuxHelper {
@@ -329,18 +389,15 @@ impl Completer for XinuxHelper {
             }
 
             // Check if the last part starts with the input's last part
-            if valid {
-                if let (Some(command_last_part), Some(input_last_part)) = (
-                    command_parts.get(input_parts.len() - 1),
-                    input_parts.last(),
-                ) {
-                    if command_last_part.starts_with(input_last_part) {
-                        suggestions.push(Pair {
-                            display: command.to_string(),
-                            replacement: command.to_string(),
-                        });
-                    }
-                }
+            if valid
+                && let (Some(command_last_part), Some(input_last_part)) =
+                    (command_parts.get(input_parts.len() - 1), input_parts.last())
+                && command_last_part.starts_with(input_last_part)
+            {
+                suggestions.push(Pair {
+                    display: command.to_string(),
+                    replacement: command.to_string(),
+                });
             }
         }
 
@@ -396,8 +453,12 @@ impl Highlighter for XinuxHelper {
 }
 
 impl Validator for XinuxHelper {
-    fn validate(&self, _: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
-        Ok(ValidationResult::Valid(None))
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
+        // Keep reading lines while a function body, quote or group is still open
+        match parse_input::parse(ctx.input()) {
+            Err(e) if e.incomplete => Ok(ValidationResult::Incomplete),
+            _ => Ok(ValidationResult::Valid(None)),
+        }
     }
 }
 
@@ -467,8 +528,33 @@ fn collect_autocomplete_commands(history_path: &PathBuf) -> Vec<String> {
         "quit",
         "help",
         "echo",
+        "printf",
         "alias",
+        "local",
+        "return",
+        "export",
+        "unset",
+        "shift",
+        "functions",
+        "funcsave",
+        "source",
+        "read",
+        "test",
+        "getopts",
+        "trap",
+        "argparse",
+        "where",
+        "sort-by",
+        "select",
+        "first",
+        "to json",
+        "to csv",
+        "to table",
+        "exit",
+        "break",
+        "continue",
         "xinux config where",
+        "xinux lint",
         "xinux config prompt",
     ];
     for cmd in builtins {
@@ -481,10 +567,11 @@ fn collect_autocomplete_commands(history_path: &PathBuf) -> Vec<String> {
             if let Ok(entries) = fs::read_dir(&path) {
                 for entry in entries.flatten() {
                     let path = entry.path();
-                    if path.is_file() && is_executable(&path) {
-                        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                            commands.insert(name.to_string());
-                        }
+                    if path.is_file()
+                        && is_executable(&path)
+                        && let Some(name) = path.file_name().and_then(|n| n.to_str())
+                    {
+                        commands.insert(name.to_string());
                     }
                 }
             }
@@ -501,12 +588,27 @@ fn collect_autocomplete_commands(history_path: &PathBuf) -> Vec<String> {
 
     let config = load_config();
     commands.extend(config.aliases.keys().cloned());
+    commands.extend(function_completions());
 
     let mut sorted: Vec<String> = commands.into_iter().collect();
     sorted.sort();
     sorted
 }
 
+/// Function names, plus `name --flag` for every flag their `argparse` calls declare
+fn function_completions() -> Vec<String> {
+    let mut completions = Vec::new();
+    for name in state::function_names() {
+        if let Some(function) = state::function(&name) {
+            for flag in argparse::function_flags(&function) {
+                completions.push(format!("{} {}", name, flag));
+            }
+        }
+        completions.push(name);
+    }
+    completions
+}
+
 fn is_executable(path: &PathBuf) -> bool {
     use std::os::unix::fs::PermissionsExt;
     fs::metadata(path)
@@ -544,16 +646,149 @@ fn handle_alias(input: &str) {
     println!("Alias '{}' set to: {}", name, command);
 }
 
+/// Sources a startup file if it exists, remembering an `exit` inside it
+fn source_startup_file(path: &Path) {
+    if path.is_file()
+        && let eval::Flow::Exit(code) = eval::source_file(path, None)
+    {
+        state::with(|s| s.exit_requested = Some(code));
+    }
+}
+
+fn exit_requested() -> Option<i32> {
+    state::with(|s| s.exit_requested)
+}
+
+fn print_usage() {
+    println!("Usage: xinux [-l|--login] [--norc] [-c command [name [args...]] | script [args...]]");
+    println!("       xinux --debug script [args...]   step through a script with breakpoints");
+    println!("       xinux -n [--json] script...      check scripts without running them");
+    println!("       xinux lint [--json] [script...]  same; defaults to the startup files");
+}
+
 fn main() {
+    // Decide on colors while stdout is still the real one, before any redirection
+    colored::control::SHOULD_COLORIZE.should_colorize();
+    let mut args = env::args();
+    let argv0 = args.next().unwrap_or_default();
+    let mut login = argv0.starts_with('-');
+    let mut norc = false;
+    let mut debug = false;
+    let mut rest: Vec<String> = args.collect();
+
+    if rest.first().is_some_and(|a| a == "lint") {
+        process::exit(lint::run_lint(&rest[1..]));
+    }
+
+    while let Some(flag) = rest.first().cloned() {
+        match flag.as_str() {
+            "-l" | "--login" => login = true,
+            "--norc" => norc = true,
+            "--debug" => debug = true,
+            "-n" => process::exit(lint::run_lint(&rest[1..])),
+            "-h" | "--help" => {
+                print_usage();
+                return;
+            }
+            "-c" => {
+                rest.remove(0);
+                if rest.is_empty() {
+                    eprintln!("xinux: -c: option requires an argument");
+                    process::exit(2);
+                }
+                let command = rest.remove(0);
+                if !rest.is_empty() {
+                    let name = rest.remove(0);
+                    state::with(|s| s.script_name = name);
+                }
+                if debug {
+                    debugger::start("-c", &command);
+                }
+                process::exit(run_script(|| eval::run_source(&command), rest));
+            }
+            "--" => {
+                rest.remove(0);
+                break;
+            }
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                eprintln!("xinux: {}: invalid option", flag);
+                print_usage();
+                process::exit(2);
+            }
+            _ => break,
+        }
+        rest.remove(0);
+    }
+
+    if !rest.is_empty() {
+        let script = rest.remove(0);
+        state::with(|s| s.script_name = script.clone());
+        let path = PathBuf::from(&script);
+        if debug {
+            match fs::read_to_string(&path) {
+                Ok(source) => debugger::start(&script, &source),
+                Err(e) => {
+                    eprintln!("xinux: {}: {}", script, e);
+                    process::exit(127);
+                }
+            }
+        }
+        process::exit(run_script(|| eval::source_file(&path, None).code(), rest));
+    }
+
+    if debug {
+        eprintln!("xinux: --debug needs a script or -c command");
+        process::exit(2);
+    }
+
     // Ensure the configuration directory exists before doing anything else
     let _ = xinux_dir();
+    state::with(|s| s.interactive = true);
+    if login {
+        source_startup_file(&profile_file_path());
+    }
+    if !norc {
+        source_startup_file(&rc_file_path());
+    }
+
+    let code = match exit_requested() {
+        Some(code) => code,
+        None => run_shell(),
+    };
+
+    eval::run_exit_trap();
+    if login {
+        state::with(|s| s.exit_requested = None);
+        source_startup_file(&logout_file_path());
+    }
+    process::exit(code);
+}
+
+/// Runs a non-interactive script with the given positional parameters
+fn run_script(run: impl FnOnce() -> i32, args: Vec<String>) -> i32 {
+    if config_file_path().exists() {
+        eval::load_functions(&load_config().functions);
+    }
+    state::set_positionals(args);
+    let code = run();
+    let code = exit_requested().unwrap_or(code);
+    eval::run_exit_trap();
+    code
+}
 
+/// The interactive loop; also re-entered after the prompt style changes or a reset
+fn run_shell() -> i32 {
     let config = load_config();
+    eval::load_functions(&config.functions);
 
-    // Run autostart commands
+    // Run autostart commands; kept for configs written before the rc file existed
     for command in &config.autostart_commands {
         execute(command);
+        if let Some(code) = exit_requested() {
+            return code;
+        }
     }
+    let mut exit_code = 0;
 
     let history_path = history_file_path();
     let autocomplete_cmds = collect_autocomplete_commands(&history_path);
@@ -573,6 +808,7 @@ fn main() {
     let _ = rl.load_history(&history_path);
 
     loop {
+        jobs::notify();
         let cwd = env::current_dir()
             .map(|p| p.display().to_string())
             .unwrap_or("?".into());
@@ -590,21 +826,104 @@ fn main() {
                 }
 
                 match input {
-                    "exit" | "quit" => break,
+                    "exit" | "quit" => {
+                        exit_code = state::status();
+                        break;
+                    }
                     "help" => {
                         println!("Available commands:");
                         println!("├── cd: Teleport to another folder dimension!");
-                        println!("├── ls: Summon a list of files and directories in your current realm.");
+                        println!(
+                            "├── ls: Summon a list of files and directories (`-l`, `-C`, `-S`/`-t`/`-X`/`-r`, `-R`, `--tree`, `--git`, `--sniff`)."
+                        );
                         println!("├── clear: Wipe the terminal clean like a wizard's spell.");
                         println!("├── exit: Escape the Xinux universe and return to reality.");
                         println!("├── help: Summon this magical scroll of wisdom.");
-                        println!("├── echo: Make the terminal repeat your words like a loyal parrot.");
+                        println!(
+                            "├── echo: Make the terminal repeat your words like a loyal parrot (`-n`, `-e`, `-E`)."
+                        );
+                        println!(
+                            "├── printf: Format output with C-style `%s %d %x %f %q`, or store it with `-v var`."
+                        );
                         println!("├── xinsay: Let Xin speak with the charm of cowsay!");
-                        println!("├── alias: Forge shortcuts to commands like a true hacker-smith.");
-                        println!("├── clear history: Erase your command history like a secret agent.");
-                        println!("├── xinux config prompt: Redesign your prompt with style and flair.");
-                        println!("├── xinux config where: Reveal the sacred location of the config file.");
-                        println!("└── xinux reset: Reset Xinux to its pristine state, like a phoenix reborn.");
+                        println!(
+                            "├── alias: Forge shortcuts to commands like a true hacker-smith."
+                        );
+                        println!(
+                            "├── name() {{ ...; }}: Craft your own spells with arguments, `local` and `return`."
+                        );
+                        println!(
+                            "├── functions: List your spellbook, or read a spell with `functions name`."
+                        );
+                        println!(
+                            "├── getopts / argparse: Parse flags in scripts; `argparse` adds `--help` and completion."
+                        );
+                        println!(
+                            "├── trap: Run a command before each command (DEBUG) or when the shell exits (EXIT)."
+                        );
+                        println!(
+                            "├── funcsave: Bind a function into your config so it survives restarts."
+                        );
+                        println!(
+                            "├── source: Channel a script into this very shell (also spelled `.`)."
+                        );
+                        println!(
+                            "├── read: Listen for input, with prompts, secrets, timeouts and `--select` menus."
+                        );
+                        println!(
+                            "├── touch: Create files or set their times (`-c`, `-a`/`-m`, `-d DATE`, `-r FILE`, `-h`, `-p`, `--template`)."
+                        );
+                        println!(
+                            "├── info: Reveal your machine, desktop and packages beside your distro logo (`--refresh` counts packages anew; fields and logo in [info])."
+                        );
+                        println!(
+                            "├── top / info --watch: Watch cores, memory, I/O and processes live; sort, filter and kill from the table."
+                        );
+                        println!(
+                            "├── ps [--tree] [--user NAME] [--sort KEY]: List processes as a table or records (`ps | where cpu > 5`)."
+                        );
+                        println!(
+                            "├── kill / pkill [-SIGNAL] [-y] pid|%job|name: Signal processes or jobs; asks before signalling several or loose matches by name."
+                        );
+                        println!(
+                            "├── command &, jobs, wait: Run a command in the background, list what is running (`-l`, `-p`), and wait for it (`wait %1`, `wait $!`)."
+                        );
+                        println!(
+                            "├── time [-p] pipeline: Clock real, user and system time, peak memory, faults and switches (`TIMEFORMAT`)."
+                        );
+                        println!(
+                            "├── time --runs N [--warmup N] 'cmd1' 'cmd2': Benchmark and rank commands; `--export-json`/`--export-markdown` save the results."
+                        );
+                        println!(
+                            "├── ls | where size > 1MB | sort-by modified | select name size | first 10: Filter `ls` as records."
+                        );
+                        println!(
+                            "├── to json / to csv / to table: Render records for other programs or for your eyes."
+                        );
+                        println!(
+                            "├── ls / info / time --json|--csv|--plain: Trade the decorations for parseable output."
+                        );
+                        println!(
+                            "├── if / while / for / case: Bend the flow of fate with real control structures."
+                        );
+                        println!(
+                            "├── test / [ / [[: Question files, strings and numbers; `[[` adds globs and `=~` regexes."
+                        );
+                        println!(
+                            "├── clear history: Erase your command history like a secret agent."
+                        );
+                        println!(
+                            "├── xinux config prompt: Redesign your prompt with style and flair."
+                        );
+                        println!(
+                            "├── xinux lint [--json] [file...]: Check scripts for mistakes without running them."
+                        );
+                        println!(
+                            "├── xinux config where: Reveal the sacred location of the config file."
+                        );
+                        println!(
+                            "└── xinux reset: Reset Xinux to its pristine state, like a phoenix reborn."
+                        );
                     }
                     "clear" => {
                         if clearscreen::clear().is_err() {
@@ -612,7 +931,7 @@ fn main() {
                         }
                     }
                     "xinux config prompt" => {
-                        reselect_prompt_style();
+                        exit_code = reselect_prompt_style();
                         break;
                     }
                     "xinux config where" => {
@@ -622,11 +941,16 @@ fn main() {
                             config_path.display()
                         );
                     }
+                    _ if input == "xinux lint" || input.starts_with("xinux lint ") => {
+                        let args =
+                            shell_words::split(&input["xinux lint".len()..]).unwrap_or_default();
+                        lint::run_lint(&args);
+                    }
                     "xinux reset" => {
                         let config_path = config_file_path();
                         if fs::remove_file(&config_path).is_ok() {
                             println!("\x1b[32m✔ Configuration reset. Restarting Xinux...\x1b[0m");
-                            main(); // Restart the application
+                            exit_code = run_shell(); // Restart the application
                             break;
                         } else {
                             eprintln!("\x1b[31m✘ Failed to reset configuration.\x1b[0m");
@@ -660,12 +984,22 @@ fn main() {
                         } else {
                             execute(input);
                         }
+                        if let Some(h) = rl.helper_mut() {
+                            for completion in function_completions() {
+                                if !h.commands.contains(&completion) {
+                                    h.commands.push(completion);
+                                }
+                            }
+                            h.commands.sort();
+                        }
+                        if let Some(code) = exit_requested() {
+                            exit_code = code;
+                            break;
+                        }
                     }
                 }
             }
-            Err(ReadlineError::Interrupted) => {
-                
-            }
+            Err(ReadlineError::Interrupted) => {}
             Err(ReadlineError::Eof) => {
                 println!("\nEOF");
                 break;
@@ -680,4 +1014,5 @@ fn main() {
     if let Err(e) = rl.save_history(&history_path) {
         eprintln!("Warning: Could not save history: {}", e);
     }
+    exit_code
 }
@@ -0,0 +1,352 @@
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rayon::ThreadPoolBuilder;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How long counts are reused before the managers are asked again
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long a package manager gets to answer
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where a package manager's count comes from
+enum Source {
+    /// Read straight from its database
+    Direct(fn() -> Option<usize>),
+    /// A command whose output lines are counted, less a header
+    Command {
+        program: &'static str,
+        args: &'static [&'static str],
+        header: usize,
+    },
+    /// The database when it can be found, else the command
+    Either(fn() -> Option<usize>, &'static Source),
+}
+
+/// A count from the output lines of `program args`, less `header` lines
+const fn run(program: &'static str, args: &'static [&'static str], header: usize) -> Source {
+    Source::Command {
+        program,
+        args,
+        header,
+    }
+}
+
+const MANAGERS: [(&str, Source); 14] = [
+    ("dpkg", Source::Direct(dpkg)),
+    ("pacman", Source::Direct(pacman)),
+    ("apk", Source::Direct(apk)),
+    ("rpm", run("rpm", &["-qa"], 0)),
+    ("eopkg", run("eopkg", &["list-installed"], 0)),
+    ("xbps", run("xbps-query", &["-l"], 0)),
+    ("nix", run("nix-env", &["-q"], 0)),
+    ("flatpak", Source::Direct(flatpak)),
+    ("snap", run("snap", &["list"], 1)),
+    ("brew", Source::Direct(brew)),
+    ("cargo", Source::Direct(cargo)),
+    (
+        "pip",
+        Source::Either(pip, &run("pip", &["list", "--format=freeze"], 0)),
+    ),
+    ("conda", Source::Direct(conda)),
+    (
+        "npm",
+        Source::Either(
+            npm,
+            &run("npm", &["ls", "-g", "--depth=0", "--parseable"], 1),
+        ),
+    ),
+];
+
+/// Databases whose change makes the cached counts stale
+const DATABASES: [&str; 3] = [
+    "/var/lib/dpkg/status",
+    "/var/lib/pacman/local",
+    "/lib/apk/db/installed",
+];
+
+#[derive(Serialize, Deserialize)]
+struct Cache {
+    /// Seconds since the epoch when the counts were taken
+    time: u64,
+    counts: Vec<(String, usize)>,
+}
+
+/// `dpkg: 1234, flatpak: 12`, from the cache when it is fresh enough
+pub fn counts(refresh: bool) -> String {
+    let cached = (!refresh).then(read_cache).flatten();
+    let counts = match cached {
+        Some(counts) => counts,
+        None => {
+            // The probes mostly wait, so each gets a thread however few cores there are
+            let probe = || -> Vec<(String, usize)> {
+                MANAGERS
+                    .par_iter()
+                    .filter_map(|(name, source)| Some((name.to_string(), count(source)?)))
+                    .filter(|(_, n)| *n > 0)
+                    .collect()
+            };
+            let counts = match ThreadPoolBuilder::new().num_threads(MANAGERS.len()).build() {
+                Ok(pool) => pool.install(probe),
+                Err(_) => probe(),
+            };
+            write_cache(&counts);
+            counts
+        }
+    };
+    if counts.is_empty() {
+        return "Unknown".into();
+    }
+    counts
+        .iter()
+        .map(|(name, n)| format!("{}: {}", name, n))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn cache_path() -> PathBuf {
+    crate::xinux_dir().join("cache").join("packages.json")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The cached counts, unless they are older than the TTL or a package database
+/// changed since
+fn read_cache() -> Option<Vec<(String, usize)>> {
+    let cache: Cache = serde_json::from_str(&fs::read_to_string(cache_path()).ok()?).ok()?;
+    if now().saturating_sub(cache.time) > CACHE_TTL.as_secs() {
+        return None;
+    }
+    let changed = DATABASES.iter().any(|db| {
+        fs::metadata(db)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .is_some_and(|t| t.as_secs() >= cache.time)
+    });
+    (!changed).then_some(cache.counts)
+}
+
+fn write_cache(counts: &[(String, usize)]) {
+    let path = cache_path();
+    let cache = Cache {
+        time: now(),
+        counts: counts.to_vec(),
+    };
+    if let Some(dir) = path.parent()
+        && fs::create_dir_all(dir).is_ok()
+        && let Ok(text) = serde_json::to_string(&cache)
+    {
+        let _ = fs::write(path, text);
+    }
+}
+
+fn count(source: &Source) -> Option<usize> {
+    match source {
+        Source::Direct(read) => read(),
+        Source::Either(read, fallback) => read().or_else(|| count(fallback)),
+        Source::Command {
+            program,
+            args,
+            header,
+        } => {
+            let output = run_with_timeout(program, args)?;
+            Some(output.lines().count().saturating_sub(*header))
+        }
+    }
+}
+
+/// Where `program` is on `PATH`
+fn which(program: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| {
+            fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+}
+
+/// The command's output, or `None` if it is not installed, fails or takes too long
+fn run_with_timeout(program: &str, args: &[&str]) -> Option<String> {
+    let mut child = Command::new(which(program)?)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .ok()?;
+    // Read on another thread so a full pipe cannot stall the child while we wait.
+    // The thread is never joined: something the child left behind may still hold
+    // the pipe open, and the read would then never finish.
+    let mut stdout = child.stdout.take()?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut text = String::new();
+        let _ = sender.send(stdout.read_to_string(&mut text).ok().map(|_| text));
+    });
+    let deadline = Instant::now() + TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                // The whole group, so helpers it started let go of the pipe too
+                // SAFETY: only sends a signal to the group the child leads.
+                unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL) };
+                let _ = child.wait();
+                break None;
+            }
+        }
+    };
+    status.filter(|s| s.success())?;
+    receiver
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .ok()?
+}
+
+fn count_entries(dir: impl AsRef<Path>) -> Option<usize> {
+    Some(
+        fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter(|e| e.path().is_dir())
+            .count(),
+    )
+}
+
+fn dpkg() -> Option<usize> {
+    let status = fs::read_to_string("/var/lib/dpkg/status").ok()?;
+    Some(
+        status
+            .lines()
+            .filter(|l| l.starts_with("Status:") && l.ends_with(" installed"))
+            .count(),
+    )
+}
+
+fn pacman() -> Option<usize> {
+    count_entries("/var/lib/pacman/local")
+}
+
+fn apk() -> Option<usize> {
+    let db = fs::read_to_string("/lib/apk/db/installed").ok()?;
+    Some(db.lines().filter(|l| l.starts_with("P:")).count())
+}
+
+fn flatpak() -> Option<usize> {
+    let user = dirs::data_dir().map(|d| d.join("flatpak/app"));
+    let counts = [Some(PathBuf::from("/var/lib/flatpak/app")), user]
+        .into_iter()
+        .flatten()
+        .filter_map(count_entries);
+    counts.reduce(|a, b| a + b)
+}
+
+fn brew() -> Option<usize> {
+    [
+        "/home/linuxbrew/.linuxbrew/Cellar",
+        "/opt/homebrew/Cellar",
+        "/usr/local/Cellar",
+    ]
+    .into_iter()
+    .find_map(count_entries)
+}
+
+/// Installed binaries recorded by `cargo install`
+fn cargo() -> Option<usize> {
+    let home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".cargo")))?;
+    let text = fs::read_to_string(home.join(".crates2.json")).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&text).ok()?;
+    Some(json.get("installs")?.as_object()?.len())
+}
+
+/// Packages in the active environment, or the base one
+fn conda() -> Option<usize> {
+    let home = dirs::home_dir().unwrap_or_default();
+    let prefixes = [
+        env::var_os("CONDA_PREFIX").map(PathBuf::from),
+        // `PREFIX/bin/conda` or `PREFIX/condabin/conda`
+        which("conda").and_then(|conda| Some(conda.parent()?.parent()?.to_path_buf())),
+        Some(home.join("miniconda")),
+        Some(home.join("miniconda3")),
+        Some(home.join("anaconda3")),
+        Some(home.join("miniforge3")),
+        Some(PathBuf::from("/opt/conda")),
+    ];
+    prefixes.into_iter().flatten().find_map(|prefix| {
+        let entries = fs::read_dir(prefix.join("conda-meta")).ok()?;
+        Some(
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+                .count(),
+        )
+    })
+}
+
+/// Distributions in the site-packages of the Python that `pip` runs with
+fn pip() -> Option<usize> {
+    let script = fs::read_to_string(which("pip")?).ok()?;
+    let python = PathBuf::from(script.lines().next()?.strip_prefix("#!")?.trim());
+    if !python.file_name()?.to_str()?.starts_with("python") {
+        return None;
+    }
+    let lib = fs::canonicalize(python)
+        .ok()?
+        .parent()?
+        .parent()?
+        .join("lib");
+    let site = fs::read_dir(lib)
+        .ok()?
+        .flatten()
+        .find(|e| e.file_name().to_string_lossy().starts_with("python3"))?
+        .path()
+        .join("site-packages");
+    let count = fs::read_dir(site)
+        .ok()?
+        .flatten()
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            name.ends_with(".dist-info") || name.ends_with(".egg-info")
+        })
+        .count();
+    Some(count)
+}
+
+/// Packages in the global `node_modules` next to `npm`, counting each scoped one
+fn npm() -> Option<usize> {
+    let npm = fs::canonicalize(which("npm")?).ok()?;
+    // `PREFIX/lib/node_modules/npm/bin/npm-cli.js` or `PREFIX/bin/npm`
+    let modules = npm
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|n| n == "node_modules"))
+        .map(Path::to_path_buf)
+        .or_else(|| Some(npm.parent()?.parent()?.join("lib/node_modules")))?;
+    let mut count = 0;
+    for entry in fs::read_dir(modules).ok()?.flatten() {
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('@') {
+            count += count_entries(entry.path()).unwrap_or(0);
+        } else if !name.to_string_lossy().starts_with('.') {
+            count += 1;
+        }
+    }
+    Some(count)
+}
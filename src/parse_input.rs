@@ -0,0 +1,1215 @@
+use std::fmt;
+use std::rc::Rc;
+
+/// A single shell word, kept in its raw (quoted) form until expansion
+#[derive(Debug, Clone)]
+pub struct Word {
+    pub text: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirOp {
+    /// `<`
+    In,
+    /// `>`
+    Out,
+    /// `>>`
+    Append,
+    /// `>&`
+    DupOut,
+    /// `<&`
+    DupIn,
+    /// `&>`
+    OutErr,
+    /// `&>>`
+    AppendErr,
+}
+
+/// Which output of a pipeline stage feeds the next one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pipe {
+    /// `|`
+    Stdout,
+    /// `|&`, stdout and stderr together
+    Both,
+    /// `^|`, stderr only; stdout stays where it was
+    Stderr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub fd: i32,
+    pub op: RedirOp,
+    pub target: Word,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimpleCommand {
+    pub assigns: Vec<(String, Word)>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    Simple(SimpleCommand),
+    /// `pipes[i]` connects `stages[i]` to `stages[i + 1]`
+    Pipeline {
+        stages: Vec<Node>,
+        pipes: Vec<Pipe>,
+        negate: bool,
+    },
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    List(Vec<Node>),
+    Group(Box<Node>),
+    Subshell(Box<Node>),
+    /// `command &`, run as a job without waiting; `source` is the command as written
+    Background {
+        body: Box<Node>,
+        source: String,
+    },
+    Redirected(Box<Node>, Vec<Redirect>),
+    FunctionDef {
+        name: String,
+        body: Rc<Node>,
+        source: String,
+        line: usize,
+    },
+    If {
+        branches: Vec<(Node, Node)>,
+        otherwise: Option<Box<Node>>,
+    },
+    While {
+        cond: Box<Node>,
+        body: Box<Node>,
+        until: bool,
+    },
+    For {
+        var: String,
+        items: Option<Vec<Word>>,
+        body: Box<Node>,
+    },
+    Case {
+        word: Word,
+        arms: Vec<(Vec<Word>, Node)>,
+    },
+    /// `[[ expression ]]`, kept as raw words so quoting still matters when evaluated
+    Cond(Vec<Word>),
+    /// `time [options] pipeline`; `source` is the pipeline as written
+    Time {
+        options: Vec<Word>,
+        pipeline: Box<Node>,
+        source: String,
+    },
+}
+
+/// Words that close a compound command and cannot start one
+const CLOSERS: [&str; 8] = ["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub col: usize,
+    /// The input ended before the construct was closed (more lines may fix it)
+    pub incomplete: bool,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Op(&'static str),
+    IoNumber(i32),
+    Newline,
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    line: usize,
+    col: usize,
+    start: usize,
+    end: usize,
+}
+
+/// Longest operators first so that `>>` wins over `>`
+const OPERATORS: [&str; 17] = [
+    "&>>", "&&", "||", "|&", "^|", ";;", "&>", ">>", ">&", "<&", ";", "&", "|", "(", ")", "<", ">",
+];
+
+const REDIRECT_OPS: [&str; 7] = ["<", ">", ">>", ">&", "<&", "&>", "&>>"];
+
+fn is_meta(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\t' | '\n' | ';' | '&' | '|' | '(' | ')' | '<' | '>'
+    )
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    col: usize,
+}
+
+impl Lexer {
+    fn new(src: &str) -> Self {
+        Self {
+            chars: src.chars().collect(),
+            pos: 0,
+            line: 1,
+            col: 1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    /// Input that ended inside something opened at `line`:`col`, reported there
+    fn unclosed(&self, message: &str, (line, col): (usize, usize)) -> ParseError {
+        ParseError {
+            message: message.to_string(),
+            line,
+            col,
+            incomplete: true,
+        }
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = Vec::new();
+        loop {
+            // Blanks, line continuations and comments
+            while let Some(c) = self.peek() {
+                if c == ' ' || c == '\t' {
+                    self.bump();
+                } else if c == '\\' && self.peek_at(1) == Some('\n') {
+                    self.bump();
+                    self.bump();
+                } else if c == '#' {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            let (line, col, start) = (self.line, self.col, self.pos);
+            let Some(c) = self.peek() else {
+                tokens.push(Token {
+                    tok: Tok::Eof,
+                    line,
+                    col,
+                    start,
+                    end: start,
+                });
+                return Ok(tokens);
+            };
+
+            let tok = if c == '\n' {
+                self.bump();
+                Tok::Newline
+            } else if let Some(op) = self.match_operator() {
+                Tok::Op(op)
+            } else {
+                let word = self.read_word()?;
+                let all_digits = !word.is_empty() && word.chars().all(|c| c.is_ascii_digit());
+                if all_digits && matches!(self.peek(), Some('<' | '>')) {
+                    Tok::IoNumber(word.parse().unwrap_or(1))
+                } else {
+                    Tok::Word(word)
+                }
+            };
+            tokens.push(Token {
+                tok,
+                line,
+                col,
+                start,
+                end: self.pos,
+            });
+        }
+    }
+
+    fn match_operator(&mut self) -> Option<&'static str> {
+        for op in OPERATORS {
+            let matches = op
+                .chars()
+                .enumerate()
+                .all(|(i, c)| self.peek_at(i) == Some(c));
+            if matches {
+                for _ in 0..op.chars().count() {
+                    self.bump();
+                }
+                return Some(op);
+            }
+        }
+        None
+    }
+
+    fn read_word(&mut self) -> Result<String, ParseError> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if is_meta(c) {
+                break;
+            }
+            match c {
+                '\\' => {
+                    self.bump();
+                    match self.bump() {
+                        Some('\n') => {}
+                        Some(next) => {
+                            word.push('\\');
+                            word.push(next);
+                        }
+                        None => word.push('\\'),
+                    }
+                }
+                '\'' => self.read_single_quoted(&mut word)?,
+                '"' => self.read_double_quoted(&mut word)?,
+                '$' if matches!(self.peek_at(1), Some('(' | '{')) => self.read_dollar(&mut word)?,
+                '`' => self.read_backquoted(&mut word)?,
+                _ => {
+                    word.push(c);
+                    self.bump();
+                }
+            }
+        }
+        Ok(word)
+    }
+
+    fn read_single_quoted(&mut self, out: &mut String) -> Result<(), ParseError> {
+        let start = (self.line, self.col);
+        out.push(self.bump().unwrap_or('\''));
+        loop {
+            match self.bump() {
+                Some('\'') => {
+                    out.push('\'');
+                    return Ok(());
+                }
+                Some(c) => out.push(c),
+                None => return Err(self.unclosed("unterminated single quote", start)),
+            }
+        }
+    }
+
+    fn read_double_quoted(&mut self, out: &mut String) -> Result<(), ParseError> {
+        let start = (self.line, self.col);
+        out.push(self.bump().unwrap_or('"'));
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.bump();
+                    out.push('"');
+                    return Ok(());
+                }
+                Some('\\') => {
+                    self.bump();
+                    out.push('\\');
+                    if let Some(next) = self.bump() {
+                        out.push(next);
+                    }
+                }
+                Some('$') if matches!(self.peek_at(1), Some('(' | '{')) => self.read_dollar(out)?,
+                Some('`') => self.read_backquoted(out)?,
+                Some(c) => {
+                    self.bump();
+                    out.push(c);
+                }
+                None => return Err(self.unclosed("unterminated double quote", start)),
+            }
+        }
+    }
+
+    fn read_backquoted(&mut self, out: &mut String) -> Result<(), ParseError> {
+        let start = (self.line, self.col);
+        out.push(self.bump().unwrap_or('`'));
+        loop {
+            match self.bump() {
+                Some('`') => {
+                    out.push('`');
+                    return Ok(());
+                }
+                Some('\\') => {
+                    out.push('\\');
+                    if let Some(next) = self.bump() {
+                        out.push(next);
+                    }
+                }
+                Some(c) => out.push(c),
+                None => return Err(self.unclosed("unterminated backquote", start)),
+            }
+        }
+    }
+
+    /// Reads `$( ... )`, `$(( ... ))` or `${ ... }`, honouring nesting and quotes
+    fn read_dollar(&mut self, out: &mut String) -> Result<(), ParseError> {
+        let start = (self.line, self.col);
+        out.push(self.bump().unwrap_or('$'));
+        let open = self.bump().unwrap_or('(');
+        out.push(open);
+        let close = if open == '{' { '}' } else { ')' };
+        let mut depth = 1;
+        loop {
+            match self.peek() {
+                Some('\'') => self.read_single_quoted(out)?,
+                Some('"') => self.read_double_quoted(out)?,
+                Some('\\') => {
+                    self.bump();
+                    out.push('\\');
+                    if let Some(next) = self.bump() {
+                        out.push(next);
+                    }
+                }
+                Some('$') if matches!(self.peek_at(1), Some('(' | '{')) => self.read_dollar(out)?,
+                Some(c) => {
+                    self.bump();
+                    out.push(c);
+                    if c == open {
+                        depth += 1;
+                    } else if c == close {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Ok(());
+                        }
+                    }
+                }
+                None => {
+                    let what = if open == '{' {
+                        "unterminated ${"
+                    } else {
+                        "unterminated $("
+                    };
+                    return Err(self.unclosed(what, start));
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    chars: &'a [char],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn peek_tok(&self) -> &Tok {
+        &self.peek().tok
+    }
+
+    fn peek_nth(&self, n: usize) -> &Tok {
+        &self.tokens[(self.pos + n).min(self.tokens.len() - 1)].tok
+    }
+
+    fn next(&mut self) -> Token {
+        let tok = self.peek().clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.peek_tok(), Tok::Op(o) if *o == op)
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek_tok(), Tok::Word(w) if w == word)
+    }
+
+    fn skip_newlines(&mut self) {
+        while *self.peek_tok() == Tok::Newline {
+            self.next();
+        }
+    }
+
+    fn error_here(&self, message: String) -> ParseError {
+        let token = self.peek();
+        ParseError {
+            message,
+            line: token.line,
+            col: token.col,
+            incomplete: token.tok == Tok::Eof,
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        let what = match self.peek_tok() {
+            Tok::Word(w) => format!("unexpected `{}`", w),
+            Tok::Op(op) => format!("unexpected `{}`", op),
+            Tok::IoNumber(n) => format!("unexpected `{}`", n),
+            Tok::Newline => "unexpected newline".to_string(),
+            Tok::Eof => "unexpected end of input".to_string(),
+        };
+        self.error_here(what)
+    }
+
+    /// The input ended before what `opener` started was closed: point at `opener`,
+    /// since the end of the input is past the last line
+    fn unclosed(&self, expected: &str, opener: &Token) -> ParseError {
+        let what = match &opener.tok {
+            Tok::Word(w) => w.clone(),
+            Tok::Op(op) => op.to_string(),
+            _ => String::new(),
+        };
+        ParseError {
+            message: format!("expected `{}` to close this `{}`", expected, what),
+            line: opener.line,
+            col: opener.col,
+            incomplete: true,
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, opener: &Token) -> Result<(), ParseError> {
+        if self.is_word(word) {
+            self.next();
+            Ok(())
+        } else if *self.peek_tok() == Tok::Eof {
+            Err(self.unclosed(word, opener))
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn expect_op(&mut self, op: &str, opener: &Token) -> Result<(), ParseError> {
+        if self.is_op(op) {
+            self.next();
+            Ok(())
+        } else if *self.peek_tok() == Tok::Eof {
+            Err(self.unclosed(op, opener))
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn at_terminator(&self, terminators: &[&str]) -> bool {
+        match self.peek_tok() {
+            Tok::Eof => true,
+            Tok::Word(w) => terminators.contains(&w.as_str()),
+            Tok::Op(op) => terminators.contains(op) || *op == ";;",
+            _ => false,
+        }
+    }
+
+    /// Parses commands separated by `;` or newlines until a terminator word
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<Node, ParseError> {
+        let mut items = Vec::new();
+        self.skip_newlines();
+        while !self.at_terminator(terminators) {
+            let first = self.peek().clone();
+            let item = self.parse_and_or()?;
+            match self.peek_tok() {
+                Tok::Op(";") | Tok::Newline => {
+                    items.push(item);
+                    self.next();
+                    self.skip_newlines();
+                }
+                Tok::Op("&") => {
+                    let end = self.tokens[self.pos - 1].end;
+                    items.push(Node::Background {
+                        body: Box::new(item),
+                        source: self.chars[first.start..end].iter().collect(),
+                    });
+                    self.next();
+                    self.skip_newlines();
+                }
+                _ => {
+                    items.push(item);
+                    break;
+                }
+            }
+        }
+        Ok(Node::List(items))
+    }
+
+    /// Parses the body of a compound command, which must not be empty
+    fn parse_compound_list(&mut self, terminators: &[&str]) -> Result<Node, ParseError> {
+        let list = self.parse_list(terminators)?;
+        match &list {
+            Node::List(items) if items.is_empty() => Err(self.unexpected()),
+            _ => Ok(list),
+        }
+    }
+
+    fn parse_and_or(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.parse_pipeline()?;
+        loop {
+            let is_and = self.is_op("&&");
+            if !is_and && !self.is_op("||") {
+                return Ok(node);
+            }
+            self.next();
+            self.skip_newlines();
+            let rhs = self.parse_pipeline()?;
+            node = if is_and {
+                Node::And(Box::new(node), Box::new(rhs))
+            } else {
+                Node::Or(Box::new(node), Box::new(rhs))
+            };
+        }
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Node, ParseError> {
+        if self.is_word("time")
+            && let Some(node) = self.parse_time()?
+        {
+            return Ok(node);
+        }
+        let negate = self.is_word("!");
+        if negate {
+            self.next();
+        }
+        let mut stages = vec![self.parse_command()?];
+        let mut pipes = Vec::new();
+        loop {
+            let pipe = match self.peek_tok() {
+                Tok::Op("|") => Pipe::Stdout,
+                Tok::Op("|&") => Pipe::Both,
+                Tok::Op("^|") => Pipe::Stderr,
+                _ => break,
+            };
+            self.next();
+            self.skip_newlines();
+            pipes.push(pipe);
+            stages.push(self.parse_command()?);
+        }
+        if stages.len() == 1 && !negate {
+            return Ok(stages.remove(0));
+        }
+        Ok(Node::Pipeline {
+            stages,
+            pipes,
+            negate,
+        })
+    }
+
+    /// `time [options] pipeline`, or `None` when nothing follows to be timed and
+    /// `time` runs as an ordinary command
+    fn parse_time(&mut self) -> Result<Option<Node>, ParseError> {
+        // `time() { ...; }` defines a function
+        if *self.peek_nth(1) == Tok::Op("(") && *self.peek_nth(2) == Tok::Op(")") {
+            return Ok(None);
+        }
+        let start = self.pos;
+        self.next();
+        let mut options = Vec::new();
+        while let Tok::Word(text) = self.peek_tok().clone() {
+            let Some(values) = crate::timing::option_arity(&text) else {
+                break;
+            };
+            let token = self.next();
+            if text == "--" {
+                break;
+            }
+            options.push(Word {
+                text: text.clone(),
+                line: token.line,
+                col: token.col,
+            });
+            for _ in 0..values {
+                let Tok::Word(value) = self.peek_tok().clone() else {
+                    return Err(self.error_here(format!("`time {}` needs a value", text)));
+                };
+                let token = self.next();
+                options.push(Word {
+                    text: value,
+                    line: token.line,
+                    col: token.col,
+                });
+            }
+        }
+        let starts_command = match self.peek_tok() {
+            Tok::Word(w) => !CLOSERS.contains(&w.as_str()),
+            Tok::Op(op) => *op == "(",
+            _ => false,
+        };
+        if !starts_command {
+            self.pos = start;
+            return Ok(None);
+        }
+        let first = self.peek().clone();
+        let pipeline = self.parse_pipeline()?;
+        let end = self.tokens[self.pos - 1].end;
+        Ok(Some(Node::Time {
+            options,
+            pipeline: Box::new(pipeline),
+            source: self.chars[first.start..end].iter().collect(),
+        }))
+    }
+
+    fn parse_command(&mut self) -> Result<Node, ParseError> {
+        let node = match self.peek_tok().clone() {
+            Tok::Word(w) if w == "{" => {
+                let open = self.next();
+                let body = self.parse_compound_list(&["}"])?;
+                self.expect_word("}", &open)?;
+                Node::Group(Box::new(body))
+            }
+            Tok::Word(w) if w == "function" => return self.parse_function(true),
+            Tok::Word(w) if w == "if" => self.parse_if()?,
+            Tok::Word(w) if w == "while" || w == "until" => {
+                let open = self.next();
+                let cond = self.parse_compound_list(&["do"])?;
+                self.expect_word("do", &open)?;
+                let body = self.parse_compound_list(&["done"])?;
+                self.expect_word("done", &open)?;
+                Node::While {
+                    cond: Box::new(cond),
+                    body: Box::new(body),
+                    until: w == "until",
+                }
+            }
+            Tok::Word(w) if w == "for" => self.parse_for()?,
+            Tok::Word(w) if w == "case" => self.parse_case()?,
+            Tok::Word(w) if w == "[[" => self.parse_cond()?,
+            Tok::Word(w) if w == "!" || CLOSERS.contains(&w.as_str()) => {
+                return Err(self.unexpected());
+            }
+            Tok::Word(_)
+                if *self.peek_nth(1) == Tok::Op("(") && *self.peek_nth(2) == Tok::Op(")") =>
+            {
+                return self.parse_function(false);
+            }
+            Tok::Op("(") => {
+                let open = self.next();
+                let body = self.parse_compound_list(&[")"])?;
+                self.expect_op(")", &open)?;
+                Node::Subshell(Box::new(body))
+            }
+            _ => return self.parse_simple(),
+        };
+
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.parse_redirect()? {
+            redirects.push(redirect);
+        }
+        if redirects.is_empty() {
+            Ok(node)
+        } else {
+            Ok(Node::Redirected(Box::new(node), redirects))
+        }
+    }
+
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`
+    fn parse_if(&mut self) -> Result<Node, ParseError> {
+        let open = self.next();
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let cond = self.parse_compound_list(&["then"])?;
+            self.expect_word("then", &open)?;
+            let body = self.parse_compound_list(&["elif", "else", "fi"])?;
+            branches.push((cond, body));
+            if self.is_word("elif") {
+                self.next();
+                continue;
+            }
+            if self.is_word("else") {
+                self.next();
+                otherwise = Some(Box::new(self.parse_compound_list(&["fi"])?));
+            }
+            self.expect_word("fi", &open)?;
+            return Ok(Node::If {
+                branches,
+                otherwise,
+            });
+        }
+    }
+
+    /// `for name [in words...]; do list; done`
+    fn parse_for(&mut self) -> Result<Node, ParseError> {
+        let open = self.next();
+        let var = match self.peek_tok().clone() {
+            Tok::Word(name) if crate::state::is_valid_name(&name) => {
+                self.next();
+                name
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.skip_newlines();
+        let mut items = None;
+        if self.is_word("in") {
+            self.next();
+            let mut words = Vec::new();
+            while let Token {
+                tok: Tok::Word(text),
+                line,
+                col,
+                ..
+            } = self.peek().clone()
+            {
+                self.next();
+                words.push(Word { text, line, col });
+            }
+            items = Some(words);
+        }
+        if self.is_op(";") {
+            self.next();
+        }
+        self.skip_newlines();
+        self.expect_word("do", &open)?;
+        let body = self.parse_compound_list(&["done"])?;
+        self.expect_word("done", &open)?;
+        Ok(Node::For {
+            var,
+            items,
+            body: Box::new(body),
+        })
+    }
+
+    /// `case word in [(]pattern[|pattern]...) list;; ... esac`
+    fn parse_case(&mut self) -> Result<Node, ParseError> {
+        let open = self.next();
+        let word = match self.peek().clone() {
+            Token {
+                tok: Tok::Word(text),
+                line,
+                col,
+                ..
+            } => {
+                self.next();
+                Word { text, line, col }
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.skip_newlines();
+        self.expect_word("in", &open)?;
+        self.skip_newlines();
+        let mut arms = Vec::new();
+        while !self.is_word("esac") {
+            if self.is_op("(") {
+                self.next();
+            }
+            let mut patterns = Vec::new();
+            loop {
+                match self.peek().clone() {
+                    Token {
+                        tok: Tok::Word(text),
+                        line,
+                        col,
+                        ..
+                    } => {
+                        self.next();
+                        patterns.push(Word { text, line, col });
+                    }
+                    _ => return Err(self.unexpected()),
+                }
+                if !self.is_op("|") {
+                    break;
+                }
+                self.next();
+            }
+            self.expect_op(")", &open)?;
+            let body = self.parse_list(&["esac"])?;
+            arms.push((patterns, body));
+            if self.is_op(";;") {
+                self.next();
+                self.skip_newlines();
+            } else if !self.is_word("esac") {
+                return Err(if *self.peek_tok() == Tok::Eof {
+                    self.unclosed("esac", &open)
+                } else {
+                    self.unexpected()
+                });
+            }
+        }
+        self.next();
+        Ok(Node::Case { word, arms })
+    }
+
+    /// `[[ expression ]]`: operators like `&&`, `<` and `(` become plain words
+    fn parse_cond(&mut self) -> Result<Node, ParseError> {
+        let open = self.next();
+        let mut words: Vec<Word> = Vec::new();
+        loop {
+            self.skip_newlines();
+            let token = self.peek().clone();
+            let text = match &token.tok {
+                Tok::Word(w) if w == "]]" => break,
+                Tok::Word(w) => w.clone(),
+                Tok::Op(op @ ("&&" | "||" | "(" | ")" | "<" | ">")) => op.to_string(),
+                Tok::IoNumber(n) => n.to_string(),
+                Tok::Eof => return Err(self.unclosed("]]", &open)),
+                _ => return Err(self.unexpected()),
+            };
+            self.next();
+            if words.last().is_some_and(|w| w.text == "=~") {
+                // The regex runs up to the next blank, whatever it contains
+                let mut end = token.end;
+                while !matches!(self.peek_tok(), Tok::Eof | Tok::Newline)
+                    && self.peek().start == end
+                    && !matches!(self.peek_tok(), Tok::Word(w) if w == "]]")
+                {
+                    end = self.next().end;
+                }
+                let text = self.chars[token.start..end].iter().collect();
+                words.push(Word {
+                    text,
+                    line: token.line,
+                    col: token.col,
+                });
+                continue;
+            }
+            words.push(Word {
+                text,
+                line: token.line,
+                col: token.col,
+            });
+        }
+        self.next();
+        if words.is_empty() {
+            return Err(self.error_here("expected an expression inside `[[ ]]`".into()));
+        }
+        Ok(Node::Cond(words))
+    }
+
+    /// `name() body` or `function name [()] body`
+    fn parse_function(&mut self, keyword: bool) -> Result<Node, ParseError> {
+        let first = self.peek().clone();
+        if keyword {
+            self.next();
+        }
+        let name_tok = self.peek().clone();
+        let Tok::Word(name) = name_tok.tok else {
+            return Err(self.unexpected());
+        };
+        self.next();
+        if !is_function_name(&name) {
+            return Err(ParseError {
+                message: format!("`{}` is not a valid function name", name),
+                line: name_tok.line,
+                col: name_tok.col,
+                incomplete: false,
+            });
+        }
+        if self.is_op("(") {
+            let open = self.next();
+            self.expect_op(")", &open)?;
+        }
+        self.skip_newlines();
+        if !self.is_word("{") && !self.is_op("(") {
+            return Err(if *self.peek_tok() == Tok::Eof {
+                self.error_here("expected function body".into())
+            } else {
+                self.unexpected()
+            });
+        }
+        let body = self.parse_command()?;
+        let end = self.tokens[self.pos - 1].end;
+        let source: String = self.chars[first.start..end].iter().collect();
+        Ok(Node::FunctionDef {
+            name,
+            body: Rc::new(body),
+            source,
+            line: first.line,
+        })
+    }
+
+    fn parse_redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        let (fd, has_number) = match self.peek_tok() {
+            Tok::IoNumber(n) => (Some(*n), true),
+            Tok::Op(op) if REDIRECT_OPS.contains(op) => (None, false),
+            _ => return Ok(None),
+        };
+        if has_number {
+            self.next();
+        }
+        let op = match self.peek_tok() {
+            Tok::Op("<") => RedirOp::In,
+            Tok::Op(">") => RedirOp::Out,
+            Tok::Op(">>") => RedirOp::Append,
+            Tok::Op(">&") => RedirOp::DupOut,
+            Tok::Op("<&") => RedirOp::DupIn,
+            Tok::Op("&>") => RedirOp::OutErr,
+            Tok::Op("&>>") => RedirOp::AppendErr,
+            _ => return Err(self.unexpected()),
+        };
+        self.next();
+        let fd = fd.unwrap_or(match op {
+            RedirOp::In | RedirOp::DupIn => 0,
+            _ => 1,
+        });
+        let target = match self.peek().clone() {
+            Token {
+                tok: Tok::Word(text),
+                line,
+                col,
+                ..
+            } => {
+                self.next();
+                Word { text, line, col }
+            }
+            _ => return Err(self.unexpected()),
+        };
+        Ok(Some(Redirect { fd, op, target }))
+    }
+
+    fn parse_simple(&mut self) -> Result<Node, ParseError> {
+        let line = self.peek().line;
+        let mut command = SimpleCommand {
+            assigns: Vec::new(),
+            words: Vec::new(),
+            redirects: Vec::new(),
+            line,
+        };
+        loop {
+            // `where size > 1MB` compares rather than redirects
+            if command.words.first().is_some_and(|w| w.text == "where")
+                && matches!(self.peek_tok(), Tok::Op("<" | ">"))
+            {
+                let token = self.next();
+                let mut end = token.end;
+                while matches!(self.peek_tok(), Tok::Word(_) | Tok::Op("<" | ">"))
+                    && self.peek().start == end
+                {
+                    end = self.next().end;
+                }
+                command.words.push(Word {
+                    text: self.chars[token.start..end].iter().collect(),
+                    line: token.line,
+                    col: token.col,
+                });
+                continue;
+            }
+            if let Some(redirect) = self.parse_redirect()? {
+                command.redirects.push(redirect);
+                continue;
+            }
+            let Token {
+                tok: Tok::Word(text),
+                line,
+                col,
+                ..
+            } = self.peek().clone()
+            else {
+                break;
+            };
+            self.next();
+            if command.words.is_empty()
+                && let Some((name, value)) = split_assignment(&text)
+            {
+                let value = Word {
+                    text: value.to_string(),
+                    line,
+                    col: col + name.chars().count() + 1,
+                };
+                command.assigns.push((name.to_string(), value));
+                continue;
+            }
+            command.words.push(Word { text, line, col });
+        }
+        if command.words.is_empty() && command.assigns.is_empty() && command.redirects.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(Node::Simple(command))
+    }
+}
+
+/// Splits `NAME=value` into its parts when `NAME` is a valid variable name
+pub fn split_assignment(text: &str) -> Option<(&str, &str)> {
+    let (name, value) = text.split_once('=')?;
+    crate::state::is_valid_name(name).then_some((name, value))
+}
+
+fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| matches!(c, '\'' | '"' | '$' | '`' | '\\' | '=' | '/'))
+}
+
+/// Parses a complete command line or script into a syntax tree
+pub fn parse(src: &str) -> Result<Node, ParseError> {
+    let tokens = Lexer::new(src).tokenize()?;
+    let chars: Vec<char> = src.chars().collect();
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        chars: &chars,
+    };
+    let program = parser.parse_list(&[])?;
+    if *parser.peek_tok() != Tok::Eof {
+        return Err(parser.unexpected());
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A compact rendering of the tree: `[words]` for commands, operators in parentheses
+    fn shape(node: &Node) -> String {
+        let words = |words: &[Word]| {
+            words
+                .iter()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match node {
+            Node::Simple(cmd) => {
+                let mut parts: Vec<String> = cmd
+                    .assigns
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value.text))
+                    .collect();
+                parts.push(words(&cmd.words));
+                parts.extend(
+                    cmd.redirects
+                        .iter()
+                        .map(|r| format!("{}{:?}{}", r.fd, r.op, r.target.text)),
+                );
+                format!("[{}]", parts.join(" ").trim())
+            }
+            Node::Pipeline { stages, negate, .. } => format!(
+                "({}{})",
+                if *negate { "! " } else { "" },
+                stages.iter().map(shape).collect::<Vec<_>>().join(" | ")
+            ),
+            Node::And(a, b) => format!("({} && {})", shape(a), shape(b)),
+            Node::Or(a, b) => format!("({} || {})", shape(a), shape(b)),
+            Node::List(nodes) => nodes.iter().map(shape).collect::<Vec<_>>().join("; "),
+            Node::Group(body) => format!("{{ {} }}", shape(body)),
+            Node::Subshell(body) => format!("( {} )", shape(body)),
+            Node::Background { body, .. } => format!("{} &", shape(body)),
+            Node::FunctionDef { name, body, .. } => format!("{}() {}", name, shape(body)),
+            Node::If {
+                branches,
+                otherwise,
+            } => {
+                let mut text: Vec<String> = branches
+                    .iter()
+                    .map(|(cond, body)| format!("if {} then {}", shape(cond), shape(body)))
+                    .collect();
+                if let Some(otherwise) = otherwise {
+                    text.push(format!("else {}", shape(otherwise)));
+                }
+                text.join(" ")
+            }
+            Node::For { var, items, body } => format!(
+                "for {} in {} do {}",
+                var,
+                items.as_deref().map(words).unwrap_or_default(),
+                shape(body)
+            ),
+            Node::Cond(w) => format!("[[ {} ]]", words(w)),
+            other => format!("{:?}", other),
+        }
+    }
+
+    fn parsed(src: &str) -> String {
+        shape(&parse(src).unwrap_or_else(|e| panic!("{}: {}", src, e)))
+    }
+
+    #[test]
+    fn and_or_bind_left_to_right_and_looser_than_pipes() {
+        assert_eq!(parsed("a && b || c"), "(([a] && [b]) || [c])");
+        assert_eq!(parsed("a || b && c"), "(([a] || [b]) && [c])");
+        assert_eq!(parsed("a | b && c"), "(([a] | [b]) && [c])");
+        assert_eq!(parsed("! a | b"), "(! [a] | [b])");
+    }
+
+    #[test]
+    fn lists_split_on_semicolons_and_newlines() {
+        assert_eq!(parsed("a; b\nc"), "[a]; [b]; [c]");
+        assert_eq!(parsed("a && b; c"), "([a] && [b]); [c]");
+        assert_eq!(parsed("{ a; b; } || ( c )"), "({ [a]; [b] } || ( [c] ))");
+    }
+
+    #[test]
+    fn ampersand_runs_the_and_or_list_before_it_in_the_background() {
+        assert_eq!(parsed("a && b & c"), "([a] && [b]) &; [c]");
+        assert_eq!(parsed("a | b &\nc &"), "([a] | [b]) &; [c] &");
+        match parse("sleep 1 | cat  & echo").unwrap() {
+            Node::List(items) => match &items[0] {
+                Node::Background { source, .. } => assert_eq!(source, "sleep 1 | cat"),
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn quotes_keep_operators_inside_one_word() {
+        assert_eq!(parsed("echo 'a; b' \"c | d\""), "[echo 'a; b' \"c | d\"]");
+        assert_eq!(parsed(r"echo a\;b"), r"[echo a\;b]");
+        assert_eq!(parsed("echo \"$(a; b)\" x"), "[echo \"$(a; b)\" x]");
+        assert_eq!(parsed("echo x'a b'y \"it's\""), "[echo x'a b'y \"it's\"]");
+    }
+
+    #[test]
+    fn assignments_and_redirections_are_separated_from_words() {
+        assert_eq!(parsed("A=1 B='x y' cmd"), "[A=1 B='x y' cmd]");
+        assert_eq!(parsed("cmd A=1"), "[cmd A=1]");
+        assert_eq!(
+            parsed("cmd >out 2>>err <in"),
+            "[cmd 1Outout 2Appenderr 0Inin]"
+        );
+        assert_eq!(parsed("cmd 2>&1"), "[cmd 2DupOut1]");
+    }
+
+    #[test]
+    fn compound_commands() {
+        assert_eq!(
+            parsed("if a; then b; elif c; then d; else e; fi"),
+            "if [a] then [b] if [c] then [d] else [e]"
+        );
+        assert_eq!(
+            parsed("for x in 1 2; do echo $x; done"),
+            "for x in 1 2 do [echo $x]"
+        );
+        assert_eq!(parsed("f() { a; }"), "f() { [a] }");
+        assert_eq!(parsed("[[ $a == 'b c' ]]"), "[[ $a == 'b c' ]]");
+    }
+
+    #[test]
+    fn unfinished_input_is_incomplete() {
+        for src in ["if a; then b", "echo 'open", "a &&", "f() {", "echo \"x"] {
+            let error = parse(src).unwrap_err();
+            assert!(error.incomplete, "{} should be incomplete", src);
+        }
+        // Reported where the unclosed construct starts, not past the end
+        let error = parse("echo ok\necho 'abc\nmore\n").unwrap_err();
+        assert_eq!((error.line, error.col), (2, 6));
+        let error = parse("x\n  while true; do\n  echo\n").unwrap_err();
+        assert_eq!((error.line, error.col), (2, 3));
+        assert!(error.message.contains("`done`"));
+        let error = parse("a; fi").unwrap_err();
+        assert!(!error.incomplete);
+        assert_eq!(error.line, 1);
+    }
+}
@@ -0,0 +1,640 @@
+use std::io::{self, Write};
+
+use colored::Colorize;
+
+use crate::state;
+
+fn error(message: String) {
+    eprintln!("{}", format!("xinux: printf: {}", message).red());
+}
+
+/// Decodes backslash escapes. `echo_style` is for `echo -e` and `%b`: octal
+/// needs a leading `\0` and `\c` stops all further output (reported as `true`).
+pub fn unescape(text: &str, echo_style: bool) -> (Vec<u8>, bool) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        if c != '\\' || i == chars.len() {
+            push_char(&mut out, c);
+            continue;
+        }
+        let e = chars[i];
+        i += 1;
+        let simple = match e {
+            'n' => Some(b'\n'),
+            't' => Some(b'\t'),
+            'r' => Some(b'\r'),
+            'a' => Some(0x07),
+            'b' => Some(0x08),
+            'f' => Some(0x0c),
+            'v' => Some(0x0b),
+            'e' | 'E' => Some(0x1b),
+            '\\' => Some(b'\\'),
+            '"' if !echo_style => Some(b'"'),
+            '\'' if !echo_style => Some(b'\''),
+            _ => None,
+        };
+        if let Some(byte) = simple {
+            out.push(byte);
+            continue;
+        }
+        match e {
+            'c' if echo_style => return (out, true),
+            '0'..='7' => {
+                if echo_style && e != '0' {
+                    out.push(b'\\');
+                    push_char(&mut out, e);
+                    continue;
+                }
+                // `\0NNN` in echo style, `\NNN` otherwise
+                let start = if echo_style { i } else { i - 1 };
+                let digits = take_digits(&chars, start, 3, 8);
+                i = start + digits.len();
+                out.push(u32::from_str_radix(&digits, 8).unwrap_or(0) as u8);
+            }
+            'x' => {
+                let digits = take_digits(&chars, i, 2, 16);
+                if digits.is_empty() {
+                    out.extend_from_slice(b"\\x");
+                } else {
+                    i += digits.len();
+                    out.push(u8::from_str_radix(&digits, 16).unwrap_or(0));
+                }
+            }
+            'u' | 'U' => {
+                let max = if e == 'u' { 4 } else { 8 };
+                let digits = take_digits(&chars, i, max, 16);
+                let decoded = u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32);
+                match decoded {
+                    Some(ch) => {
+                        i += digits.len();
+                        push_char(&mut out, ch);
+                    }
+                    None => {
+                        out.push(b'\\');
+                        push_char(&mut out, e);
+                    }
+                }
+            }
+            _ => {
+                out.push(b'\\');
+                push_char(&mut out, e);
+            }
+        }
+    }
+    (out, false)
+}
+
+fn push_char(out: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+fn take_digits(chars: &[char], start: usize, max: usize, radix: u32) -> String {
+    chars[start.min(chars.len())..]
+        .iter()
+        .take(max)
+        .take_while(|c| c.is_digit(radix))
+        .collect()
+}
+
+/// Quotes a string so the shell reads it back as the same word (`%q`)
+pub fn shell_quote(text: &str) -> String {
+    if text.is_empty() {
+        return "''".into();
+    }
+    if text.chars().any(char::is_control) {
+        let mut quoted = String::from("$'");
+        for c in text.chars() {
+            match c {
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                '\x1b' => quoted.push_str("\\E"),
+                '\'' | '\\' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                c if c.is_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        return quoted;
+    }
+    let mut quoted = String::new();
+    for c in text.chars() {
+        if !(c.is_alphanumeric() || "_-./,:@%+=".contains(c)) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted
+}
+
+/// One `%...` conversion
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+    conv: char,
+}
+
+/// Feeds the arguments to the format, which is reused while some remain
+struct Args<'a> {
+    args: &'a [String],
+    next: usize,
+    failed: bool,
+}
+
+impl Args<'_> {
+    fn string(&mut self) -> String {
+        let arg = self.args.get(self.next).cloned().unwrap_or_default();
+        self.next += 1;
+        arg
+    }
+
+    fn integer(&mut self) -> i64 {
+        let present = self.next < self.args.len();
+        let arg = self.string();
+        if !present {
+            return 0;
+        }
+        match parse_integer(&arg) {
+            Some(n) => n,
+            None => {
+                error(format!("{}: invalid number", arg));
+                self.failed = true;
+                0
+            }
+        }
+    }
+
+    fn float(&mut self) -> f64 {
+        let present = self.next < self.args.len();
+        let arg = self.string();
+        if !present {
+            return 0.0;
+        }
+        let trimmed = arg.trim();
+        if let Some(n) = char_code(trimmed) {
+            return n as f64;
+        }
+        match trimmed.parse() {
+            Ok(n) => n,
+            Err(_) => match parse_integer(trimmed) {
+                Some(n) => n as f64,
+                None => {
+                    error(format!("{}: invalid number", arg));
+                    self.failed = true;
+                    0.0
+                }
+            },
+        }
+    }
+}
+
+/// `'c` or `"c` means the character code of `c`
+fn char_code(text: &str) -> Option<i64> {
+    let rest = text.strip_prefix('\'').or_else(|| text.strip_prefix('"'))?;
+    Some(rest.chars().next().map_or(0, |c| c as i64))
+}
+
+fn parse_integer(text: &str) -> Option<i64> {
+    let text = text.trim();
+    if let Some(n) = char_code(text) {
+        return Some(n);
+    }
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Pads `body` to the field width; `prefix` (sign, `0x`) stays in front of zero padding
+fn pad(spec: &Spec, prefix: &str, body: &str, zero_ok: bool) -> String {
+    let len = prefix.chars().count() + body.chars().count();
+    let fill = spec.width.unwrap_or(0).saturating_sub(len);
+    if spec.left {
+        format!("{}{}{}", prefix, body, " ".repeat(fill))
+    } else if spec.zero && zero_ok {
+        format!("{}{}{}", prefix, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), prefix, body)
+    }
+}
+
+fn sign(spec: &Spec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+fn format_integer(spec: &Spec, value: i64) -> String {
+    let magnitude = value.unsigned_abs();
+    let (mut digits, prefix) = match spec.conv {
+        'x' => (
+            format!("{:x}", magnitude),
+            if spec.alt && value != 0 { "0x" } else { "" },
+        ),
+        'X' => (
+            format!("{:X}", magnitude),
+            if spec.alt && value != 0 { "0X" } else { "" },
+        ),
+        'o' => (format!("{:o}", magnitude), if spec.alt { "0" } else { "" }),
+        _ => (magnitude.to_string(), ""),
+    };
+    // Unsigned conversions print the two's complement of negative numbers
+    if value < 0 && matches!(spec.conv, 'x' | 'X' | 'o' | 'u') {
+        let bits = value as u64;
+        digits = match spec.conv {
+            'x' => format!("{:x}", bits),
+            'X' => format!("{:X}", bits),
+            'o' => format!("{:o}", bits),
+            _ => bits.to_string(),
+        };
+    }
+    let signed = matches!(spec.conv, 'd' | 'i');
+    if let Some(precision) = spec.precision {
+        if precision == 0 && value == 0 {
+            digits.clear();
+        } else if digits.len() < precision {
+            digits = format!("{}{}", "0".repeat(precision - digits.len()), digits);
+        }
+    }
+    let sign = if signed { sign(spec, value < 0) } else { "" };
+    pad(
+        spec,
+        &format!("{}{}", sign, prefix),
+        &digits,
+        spec.precision.is_none(),
+    )
+}
+
+/// C-style exponent notation: `1.500000e+02`
+fn exponent_form(value: f64, precision: usize, upper: bool) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exp) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let exp: i32 = exp.parse().unwrap_or(0);
+    let e = if upper { 'E' } else { 'e' };
+    format!(
+        "{}{}{}{:02}",
+        mantissa,
+        e,
+        if exp < 0 { '-' } else { '+' },
+        exp.abs()
+    )
+}
+
+fn strip_trailing_zeros(number: &str) -> String {
+    let (mantissa, exp) = match number.find(['e', 'E']) {
+        Some(i) => number.split_at(i),
+        None => (number, ""),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}{}", mantissa, exp)
+}
+
+fn format_float(spec: &Spec, value: f64) -> String {
+    let upper = spec.conv.is_ascii_uppercase();
+    let body = if value.is_nan() || value.is_infinite() {
+        let text = if value.is_nan() { "nan" } else { "inf" };
+        if upper {
+            text.to_uppercase()
+        } else {
+            text.to_string()
+        }
+    } else {
+        let magnitude = value.abs();
+        let precision = spec.precision.unwrap_or(6);
+        match spec.conv.to_ascii_lowercase() {
+            'e' => exponent_form(magnitude, precision, upper),
+            'g' => {
+                let significant = precision.max(1);
+                let probe = format!("{:.*e}", significant - 1, magnitude);
+                let exp: i32 = probe
+                    .split_once('e')
+                    .map_or(0, |(_, e)| e.parse().unwrap_or(0));
+                let text = if exp < -4 || exp >= significant as i32 {
+                    exponent_form(magnitude, significant - 1, upper)
+                } else {
+                    format!(
+                        "{:.*}",
+                        (significant as i32 - 1 - exp).max(0) as usize,
+                        magnitude
+                    )
+                };
+                if spec.alt {
+                    text
+                } else {
+                    strip_trailing_zeros(&text)
+                }
+            }
+            _ => {
+                let text = format!("{:.*}", precision, magnitude);
+                if spec.alt && precision == 0 {
+                    format!("{}.", text)
+                } else {
+                    text
+                }
+            }
+        }
+    };
+    let negative = value.is_sign_negative() && !value.is_nan() && value != 0.0;
+    pad(spec, sign(spec, negative), &body, value.is_finite())
+}
+
+fn truncate(spec: &Spec, text: &str) -> String {
+    match spec.precision {
+        Some(precision) => text.chars().take(precision).collect(),
+        None => text.to_string(),
+    }
+}
+
+/// Parses flags, width and precision after a `%`; returns `None` at a bad spec
+fn parse_spec(chars: &[char], i: &mut usize, args: &mut Args) -> Option<Spec> {
+    let mut spec = Spec::default();
+    while let Some(&c) = chars.get(*i) {
+        match c {
+            '-' => spec.left = true,
+            '+' => spec.plus = true,
+            ' ' => spec.space = true,
+            '#' => spec.alt = true,
+            '0' => spec.zero = true,
+            '\'' => {}
+            _ => break,
+        }
+        *i += 1;
+    }
+    let number = |i: &mut usize, args: &mut Args| -> Option<i64> {
+        if chars.get(*i) == Some(&'*') {
+            *i += 1;
+            return Some(args.integer());
+        }
+        let digits: String = chars[*i..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        *i += digits.len();
+        digits.parse().ok()
+    };
+    if let Some(width) = number(i, args) {
+        if width < 0 {
+            spec.left = true;
+        }
+        spec.width = Some(width.unsigned_abs() as usize);
+    }
+    if chars.get(*i) == Some(&'.') {
+        *i += 1;
+        spec.precision = Some(number(i, args).unwrap_or(0).max(0) as usize);
+    }
+    // Length modifiers mean nothing here
+    while chars.get(*i).is_some_and(|c| "hlLjzt".contains(*c)) {
+        *i += 1;
+    }
+    spec.conv = *chars.get(*i)?;
+    *i += 1;
+    Some(spec)
+}
+
+/// Runs the format once; returns `Err` on a bad conversion, `true` when `\c` stopped output
+fn format_once(format: &[char], args: &mut Args, out: &mut Vec<u8>) -> Result<bool, String> {
+    let mut i = 0;
+    let mut literal = String::new();
+    while i < format.len() {
+        let c = format[i];
+        i += 1;
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        if format.get(i) == Some(&'%') {
+            i += 1;
+            literal.push('%');
+            continue;
+        }
+        out.extend(unescape(&literal, false).0);
+        literal.clear();
+
+        let start = i - 1;
+        let Some(spec) = parse_spec(format, &mut i, args) else {
+            return Err(format!(
+                "`{}`: missing format character",
+                format[start..].iter().collect::<String>()
+            ));
+        };
+        let text = match spec.conv {
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' => format_integer(&spec, args.integer()),
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => format_float(&spec, args.float()),
+            's' => pad(&spec, "", &truncate(&spec, &args.string()), false),
+            'q' => pad(&spec, "", &shell_quote(&args.string()), false),
+            'c' => {
+                let arg = args.string();
+                pad(
+                    &spec,
+                    "",
+                    &arg.chars().next().map(String::from).unwrap_or_default(),
+                    false,
+                )
+            }
+            'b' => {
+                let (bytes, stop) = unescape(&args.string(), true);
+                let text = String::from_utf8_lossy(&bytes).into_owned();
+                out.extend(pad(&spec, "", &truncate(&spec, &text), false).into_bytes());
+                if stop {
+                    return Ok(true);
+                }
+                continue;
+            }
+            other => return Err(format!("`{}`: invalid format character", other)),
+        };
+        out.extend(text.into_bytes());
+    }
+    out.extend(unescape(&literal, false).0);
+    Ok(false)
+}
+
+/// `printf [-v var] format [arguments...]`
+pub fn builtin_printf(args: &[String]) -> i32 {
+    let mut rest = args;
+    let mut var = None;
+    if rest.first().is_some_and(|a| a == "-v") {
+        let Some(name) = rest.get(1) else {
+            error("-v: option requires an argument".into());
+            return 2;
+        };
+        var = Some(name.as_str());
+        rest = &rest[2..];
+    }
+    if rest.first().is_some_and(|a| a == "--") {
+        rest = &rest[1..];
+    }
+    let Some((format, values)) = rest.split_first() else {
+        eprintln!("Usage: printf [-v var] format [arguments...]");
+        return 2;
+    };
+    if let Some(name) = var
+        && !state::is_valid_name(name)
+    {
+        error(format!("`{}`: not a valid identifier", name));
+        return 2;
+    }
+
+    let format: Vec<char> = format.chars().collect();
+    let mut args = Args {
+        args: values,
+        next: 0,
+        failed: false,
+    };
+    let mut out = Vec::new();
+    loop {
+        let before = args.next;
+        match format_once(&format, &mut args, &mut out) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(e) => {
+                error(e);
+                args.failed = true;
+                break;
+            }
+        }
+        if args.next >= values.len() || args.next == before {
+            break;
+        }
+    }
+
+    match var {
+        Some(name) => state::set_var(name, &String::from_utf8_lossy(&out)),
+        None => {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(&out);
+            let _ = stdout.flush();
+        }
+    }
+    i32::from(args.failed)
+}
+
+/// `echo [-neE] [words...]`
+pub fn builtin_echo(args: &[&str]) -> i32 {
+    let mut newline = true;
+    let mut escapes = false;
+    let mut words = args;
+    while let Some((first, rest)) = words.split_first() {
+        let Some(flags) = first.strip_prefix('-') else {
+            break;
+        };
+        if flags.is_empty() || !flags.chars().all(|c| matches!(c, 'n' | 'e' | 'E')) {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        words = rest;
+    }
+
+    let text = words.join(" ");
+    let mut out = if escapes {
+        let (bytes, stop) = unescape(&text, true);
+        if stop {
+            newline = false;
+        }
+        bytes
+    } else {
+        text.into_bytes()
+    };
+    if newline {
+        out.push(b'\n');
+    }
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(&out);
+    let _ = stdout.flush();
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `printf` writes, captured through `-v`
+    fn printf(args: &[&str]) -> String {
+        let mut all = vec!["-v".to_string(), "printf_test_out".to_string()];
+        all.extend(args.iter().map(|a| a.to_string()));
+        assert_eq!(builtin_printf(&all), 0, "printf {:?}", args);
+        state::get_var("printf_test_out").unwrap_or_default()
+    }
+
+    #[test]
+    fn q_quotes_for_the_shell() {
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("plain-word_1.txt"), "plain-word_1.txt");
+        assert_eq!(shell_quote("a b'c"), r"a\ b\'c");
+        assert_eq!(shell_quote("$HOME;*"), r"\$HOME\;\*");
+        assert_eq!(shell_quote("tab\there\n"), r"$'tab\there\n'");
+        assert_eq!(shell_quote("it's\x01"), r"$'it\'s\001'");
+        assert_eq!(printf(&["[%q] [%8q]", "a b", "x"]), r"[a\ b] [       x]");
+    }
+
+    #[test]
+    fn b_expands_escapes_in_the_argument() {
+        assert_eq!(printf(&["%b|", "a\\tb\\n"]), "a\tb\n|");
+        // Octal needs a leading zero in `%b`, unlike in the format itself
+        assert_eq!(printf(&["%b", "\\0101\\101"]), "A\\101");
+        assert_eq!(printf(&["\\101"]), "A");
+        // `\c` ends all output, the rest of the format included
+        assert_eq!(printf(&["%b-%s", "stop\\chere", "never"]), "stop");
+        assert_eq!(printf(&["%s", "a\\tb"]), "a\\tb");
+    }
+
+    #[test]
+    fn format_is_reused_while_arguments_remain() {
+        assert_eq!(printf(&["%s=%d;", "a", "1", "b"]), "a=1;b=0;");
+        assert_eq!(printf(&["100%%"]), "100%");
+    }
+
+    #[test]
+    fn numbers_and_padding() {
+        assert_eq!(printf(&["%05d|%-4d|%+d", "42", "7", "3"]), "00042|7   |+3");
+        assert_eq!(printf(&["%x %#o %X", "255", "8", "0x1f"]), "ff 010 1F");
+        assert_eq!(
+            printf(&["%.2f %e", "3.14159", "1234.5"]),
+            "3.14 1.234500e+03"
+        );
+        assert_eq!(printf(&["%d", "'A"]), "65");
+        assert_eq!(printf(&["%.3s|%5s", "abcdef", "ab"]), "abc|   ab");
+    }
+}
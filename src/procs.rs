@@ -0,0 +1,679 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use colored::Colorize;
+use dialoguer::Confirm;
+use sysinfo::{ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, System, UpdateKind, Users};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::jobs;
+use crate::ls::terminal_width;
+use crate::records::{self, Field, Format, Record};
+
+const PS_USAGE: &str = "usage: ps [--tree] [--user NAME] [--sort pid|ppid|user|cpu|mem|start|command] [--json|--csv|--plain]";
+const KILL_USAGE: &str =
+    "usage: kill [-s SIGNAL | -SIGNAL] [-yf] pid|%job|name... | kill -l [SIGNAL]";
+const PKILL_USAGE: &str = "usage: pkill [-s SIGNAL | -SIGNAL] [-yf] name...";
+
+/// Signals by the name `kill` accepts, without the `SIG` prefix
+const SIGNALS: [(&str, libc::c_int); 29] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+/// How `ps` lists processes
+pub struct PsOptions {
+    tree: bool,
+    user: Option<String>,
+    sort: String,
+}
+
+/// A process as `ps` and `kill` see it
+struct Proc {
+    pid: u32,
+    ppid: Option<u32>,
+    uid: Option<u32>,
+    user: String,
+    /// Exited but not yet reaped, so there is nothing left to signal
+    zombie: bool,
+    /// Percent of one CPU over the process's lifetime, like `ps`
+    cpu: f64,
+    memory: u64,
+    start: u64,
+    name: String,
+    command: String,
+}
+
+fn error(command: &str, message: String) {
+    eprintln!("{}", format!("{}: {}", command, message).red());
+}
+
+/// Every process except threads, with its owner and command line
+fn processes() -> Vec<Proc> {
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing()
+            .with_cpu()
+            .with_memory()
+            .with_user(UpdateKind::Always)
+            .with_cmd(UpdateKind::Always),
+    );
+    let users = Users::new_with_refreshed_list();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    sys.processes()
+        .values()
+        .filter(|p| p.thread_kind().is_none())
+        .map(|p| {
+            let name = p.name().to_string_lossy().into_owned();
+            let command = p
+                .cmd()
+                .iter()
+                .map(|a| a.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let elapsed = now.saturating_sub(p.start_time()).max(1);
+            Proc {
+                pid: p.pid().as_u32(),
+                ppid: p.parent().map(|pid| pid.as_u32()),
+                uid: p.user_id().map(|uid| **uid),
+                user: p
+                    .user_id()
+                    .and_then(|uid| users.get_user_by_id(uid))
+                    .map_or_else(|| "?".into(), |u| u.name().to_string()),
+                zombie: p.status() == ProcessStatus::Zombie,
+                cpu: p.accumulated_cpu_time() as f64 / 10.0 / elapsed as f64,
+                memory: p.memory(),
+                start: p.start_time(),
+                command: if command.is_empty() {
+                    format!("[{}]", name)
+                } else {
+                    command
+                },
+                name,
+            }
+        })
+        .collect()
+}
+
+/// The `ps` and `procs` builtins: a table of processes, optionally as a tree
+pub fn builtin_ps(args: &[&str]) -> i32 {
+    let (format, rest) = records::take_format(args);
+    let opts = match parse_ps_options(&rest) {
+        Ok(opts) => opts,
+        Err(e) => {
+            error("ps", e);
+            return 2;
+        }
+    };
+    let mut records = ps_records(&opts, format == Format::Pretty);
+    if format == Format::Pretty {
+        fit_commands(&mut records);
+    }
+    records::print_records(&records, format);
+    0
+}
+
+pub fn parse_ps_options(args: &[&str]) -> Result<PsOptions, String> {
+    let mut opts = PsOptions {
+        tree: false,
+        user: None,
+        sort: "pid".into(),
+    };
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(|v| v.to_string())
+                .ok_or_else(|| format!("{} needs a value\n{}", arg, PS_USAGE))
+        };
+        match arg {
+            "-t" | "--tree" => opts.tree = true,
+            "-u" | "--user" => opts.user = Some(value()?),
+            "-s" | "--sort" => {
+                let key = value()?;
+                if !matches!(
+                    key.as_str(),
+                    "pid" | "ppid" | "user" | "cpu" | "mem" | "start" | "command"
+                ) {
+                    return Err(format!("cannot sort by `{}`\n{}", key, PS_USAGE));
+                }
+                opts.sort = key;
+            }
+            _ => return Err(format!("unknown option `{}`\n{}", arg, PS_USAGE)),
+        }
+    }
+    Ok(opts)
+}
+
+/// The processes as records; `branches` draws the tree into the command column
+pub fn ps_records(opts: &PsOptions, branches: bool) -> Vec<Record> {
+    let mut procs = processes();
+    if let Some(user) = &opts.user {
+        procs.retain(|p| &p.user == user || p.uid.is_some_and(|uid| uid.to_string() == *user));
+    }
+    sort_procs(&mut procs, &opts.sort);
+
+    let rows: Vec<(&Proc, String)> = if opts.tree {
+        tree_order(&procs)
+    } else {
+        procs.iter().map(|p| (p, String::new())).collect()
+    };
+    rows.into_iter()
+        .map(|(p, prefix)| {
+            let start = DateTime::from_timestamp(p.start as i64, 0)
+                .map(|t| t.with_timezone(&Local))
+                .unwrap_or_default();
+            let command = if branches {
+                format!("{}{}", prefix, p.command)
+            } else {
+                p.command.clone()
+            };
+            Record::new(vec![
+                ("pid".into(), Field::Number(p.pid.into())),
+                ("ppid".into(), Field::Number(p.ppid.unwrap_or(0).into())),
+                ("user".into(), Field::Text(p.user.clone())),
+                ("cpu".into(), Field::Number((p.cpu * 10.0).round() / 10.0)),
+                ("mem".into(), Field::Size(p.memory)),
+                ("start".into(), Field::Time(start)),
+                ("command".into(), Field::Text(command)),
+            ])
+        })
+        .collect()
+}
+
+/// CPU and memory go from the most; the rest in ascending order
+fn sort_procs(procs: &mut [Proc], key: &str) {
+    match key {
+        "ppid" => procs.sort_by_key(|p| (p.ppid, p.pid)),
+        "user" => procs.sort_by(|a, b| a.user.cmp(&b.user).then(a.pid.cmp(&b.pid))),
+        "cpu" => procs.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(a.pid.cmp(&b.pid))),
+        "mem" => procs.sort_by(|a, b| b.memory.cmp(&a.memory).then(a.pid.cmp(&b.pid))),
+        "start" => procs.sort_by_key(|p| (p.start, p.pid)),
+        "command" => procs.sort_by(|a, b| a.command.cmp(&b.command).then(a.pid.cmp(&b.pid))),
+        _ => procs.sort_by_key(|p| p.pid),
+    }
+}
+
+/// Parents before their children, each with the branches that lead to it.
+/// Processes whose parent is not listed start their own trees.
+fn tree_order(procs: &[Proc]) -> Vec<(&Proc, String)> {
+    let listed: HashMap<u32, usize> = procs.iter().enumerate().map(|(i, p)| (p.pid, i)).collect();
+    let mut children: HashMap<u32, Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (i, p) in procs.iter().enumerate() {
+        match p
+            .ppid
+            .filter(|ppid| listed.contains_key(ppid) && *ppid != p.pid)
+        {
+            Some(ppid) => children.entry(ppid).or_default().push(i),
+            None => roots.push(i),
+        }
+    }
+    fn walk<'a>(
+        procs: &'a [Proc],
+        children: &HashMap<u32, Vec<usize>>,
+        index: usize,
+        lead: &str,
+        branch: &str,
+        out: &mut Vec<(&'a Proc, String)>,
+    ) {
+        let p = &procs[index];
+        out.push((p, format!("{}{}", lead, branch)));
+        let Some(kids) = children.get(&p.pid) else {
+            return;
+        };
+        // Under the last child the line stops
+        let lead = match branch {
+            "├─ " => format!("{}│  ", lead),
+            "└─ " => format!("{}   ", lead),
+            _ => lead.to_string(),
+        };
+        for (n, &kid) in kids.iter().enumerate() {
+            let branch = if n + 1 == kids.len() {
+                "└─ "
+            } else {
+                "├─ "
+            };
+            walk(procs, children, kid, &lead, branch, out);
+        }
+    }
+    let mut out = Vec::with_capacity(procs.len());
+    for root in roots {
+        walk(procs, &children, root, "", "", &mut out);
+    }
+    out
+}
+
+/// Puts each command line on one table row, shortened to fit the terminal when there
+/// is one, keeping at least a few words
+fn fit_commands(records: &mut [Record]) {
+    // Seven columns: a border before each, one after the last, and a space either side
+    const FRAME: usize = 7 * 3 + 1;
+    let mut widths = [0usize; 6];
+    for record in records.iter() {
+        for (i, width) in record.display_widths().into_iter().take(6).enumerate() {
+            widths[i] = widths[i].max(width);
+        }
+    }
+    // SAFETY: isatty only inspects the descriptor.
+    let room = if unsafe { libc::isatty(1) } == 1 {
+        terminal_width()
+            .saturating_sub(widths.iter().sum::<usize>() + FRAME)
+            .max(20)
+    } else {
+        usize::MAX
+    };
+    for record in records.iter_mut() {
+        record.map_text("command", |command| {
+            let command = command.replace(['\n', '\t'], " ");
+            if command.width() <= room {
+                return command;
+            }
+            let mut used = 0;
+            let cut: String = command
+                .chars()
+                .take_while(|c| {
+                    used += c.width().unwrap_or(0);
+                    used < room
+                })
+                .collect();
+            format!("{}…", cut)
+        });
+    }
+}
+
+/// The `kill` builtin: signals processes given by PID, job spec or name
+pub fn builtin_kill(args: &[&str]) -> i32 {
+    signal_processes("kill", args)
+}
+
+/// The `pkill` builtin: signals processes by name
+pub fn builtin_pkill(args: &[&str]) -> i32 {
+    signal_processes("pkill", args)
+}
+
+fn signal_number(name: &str) -> Option<libc::c_int> {
+    if let Ok(number) = name.parse::<libc::c_int>() {
+        return (number == 0 || SIGNALS.iter().any(|&(_, n)| n == number)).then_some(number);
+    }
+    let upper = name.to_uppercase();
+    let bare = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS.iter().find(|&&(n, _)| n == bare).map(|&(_, n)| n)
+}
+
+fn signal_name(number: libc::c_int) -> String {
+    SIGNALS
+        .iter()
+        .find(|&&(_, n)| n == number)
+        .map_or_else(|| number.to_string(), |(name, _)| format!("SIG{}", name))
+}
+
+/// What `kill` or `pkill` was asked to do
+#[derive(Debug, PartialEq)]
+enum SignalRequest<'a> {
+    /// `kill -l [SIGNAL]`
+    List(Option<&'a str>),
+    Send {
+        signal: libc::c_int,
+        /// `-y`: do not ask before signalling several processes
+        yes: bool,
+        /// `-f`: match names against the whole command line
+        full: bool,
+        targets: Vec<&'a str>,
+    },
+}
+
+fn parse_signal_options<'a>(command: &str, args: &[&'a str]) -> Result<SignalRequest<'a>, String> {
+    let usage = if command == "pkill" {
+        PKILL_USAGE
+    } else {
+        KILL_USAGE
+    };
+    let mut signal = libc::SIGTERM;
+    let mut yes = false;
+    let mut full = false;
+    let mut targets = Vec::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        // Options only come before the first target
+        if !targets.is_empty() {
+            targets.push(arg);
+            continue;
+        }
+        match arg {
+            "--" => targets.extend(args.by_ref()),
+            "-l" | "--list" if command == "kill" => {
+                return Ok(SignalRequest::List(args.next().copied()));
+            }
+            "-y" | "--yes" => yes = true,
+            "-f" | "--full" => full = true,
+            "-s" | "-n" | "--signal" => {
+                let Some(&name) = args.next() else {
+                    return Err(format!("{} needs a signal\n{}", arg, usage));
+                };
+                signal = signal_number(name).ok_or_else(|| format!("unknown signal `{}`", name))?;
+            }
+            _ => match arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+                Some(name) => {
+                    signal = signal_number(name)
+                        .ok_or_else(|| format!("unknown signal `{}`\n{}", name, usage))?;
+                }
+                None => targets.push(arg),
+            },
+        }
+    }
+    if targets.is_empty() {
+        return Err(usage.into());
+    }
+    Ok(SignalRequest::Send {
+        signal,
+        yes,
+        full,
+        targets,
+    })
+}
+
+fn signal_processes(command: &str, args: &[&str]) -> i32 {
+    let (signal, yes, full, targets) = match parse_signal_options(command, args) {
+        Ok(SignalRequest::List(which)) => return list_signals(which),
+        Ok(SignalRequest::Send {
+            signal,
+            yes,
+            full,
+            targets,
+        }) => (signal, yes, full, targets),
+        Err(e) => {
+            error(command, e);
+            return 2;
+        }
+    };
+
+    let mut status = 0;
+    let mut procs = None;
+    for target in targets {
+        let targets = if command == "kill" && target.starts_with('%') {
+            // The whole process group, so every command in the job gets the signal
+            match jobs::find(target) {
+                Ok((_, pgid)) => vec![(-pgid, None)],
+                Err(e) => {
+                    error(command, e);
+                    status = 1;
+                    continue;
+                }
+            }
+        } else if command == "kill"
+            && let Ok(pid) = target.parse::<libc::pid_t>()
+        {
+            vec![(pid, None)]
+        } else {
+            let procs = procs.get_or_insert_with(processes);
+            match by_name(command, procs, target, signal, yes, full) {
+                Some(found) => found
+                    .into_iter()
+                    .map(|p| (p.pid as libc::pid_t, Some(p.name.as_str())))
+                    .collect(),
+                None => {
+                    status = 1;
+                    continue;
+                }
+            }
+        };
+        for (pid, name) in targets {
+            // SAFETY: kill only sends a signal; a stale pid makes it fail with ESRCH.
+            if unsafe { libc::kill(pid, signal) } != 0 {
+                error(
+                    command,
+                    format!("({}) - {}", pid, std::io::Error::last_os_error()),
+                );
+                status = 1;
+            } else if let Some(name) = name {
+                println!(
+                    "{} {} {} ({})",
+                    "✔️ ".green(),
+                    format!("Sent {} to", signal_name(signal)).bright_green(),
+                    pid,
+                    name
+                );
+            }
+        }
+    }
+    status
+}
+
+/// The processes whose name (or command line, for `-f`) is `pattern`; failing that,
+/// that contain it; failing that, whose name is a near miss. Anything but a single
+/// exact match is only signalled after the user agrees, or with `-y`.
+fn by_name<'a>(
+    command: &str,
+    procs: &'a [Proc],
+    pattern: &str,
+    signal: libc::c_int,
+    yes: bool,
+    full: bool,
+) -> Option<Vec<&'a Proc>> {
+    // SAFETY: getuid cannot fail.
+    let uid = unsafe { libc::getuid() };
+    let own = std::process::id();
+    let candidates: Vec<&Proc> = procs
+        .iter()
+        // Only root can signal other users' processes
+        .filter(|p| p.pid != own && !p.zombie && (uid == 0 || p.uid == Some(uid)))
+        .collect();
+    let lower = pattern.to_lowercase();
+    // With `-f` the whole command line is searched, so a pattern can tell apart
+    // processes of the same program
+    let text = |p: &Proc| {
+        if full {
+            p.command.to_lowercase()
+        } else {
+            p.name.to_lowercase()
+        }
+    };
+    let tries: [&dyn Fn(&Proc) -> bool; 3] =
+        [&|p| text(p) == lower, &|p| text(p).contains(&lower), &|p| {
+            !full && levenshtein::levenshtein(&text(p), &lower) <= (lower.len() / 4).max(1)
+        }];
+    let (tier, matches) = tries
+        .iter()
+        .map(|test| {
+            candidates
+                .iter()
+                .copied()
+                .filter(|p| test(p))
+                .collect::<Vec<_>>()
+        })
+        .enumerate()
+        .find(|(_, found)| !found.is_empty())
+        .unwrap_or_default();
+    let exact = tier == 0;
+
+    match matches.len() {
+        0 => {
+            error(command, format!("no process matches `{}`", pattern));
+            return None;
+        }
+        1 if exact => {}
+        n if !yes => {
+            // SAFETY: isatty only inspects the descriptor.
+            if unsafe { libc::isatty(0) } != 1 {
+                let message = if exact {
+                    format!(
+                        "`{}` matches {} processes; pass -y to signal them all",
+                        pattern, n
+                    )
+                } else {
+                    format!(
+                        "no process is named `{}`, only similar ones; pass -y to signal them",
+                        pattern
+                    )
+                };
+                error(command, message);
+                return None;
+            }
+            for p in &matches {
+                println!(
+                    "  {} {} {}",
+                    format!("{:>7}", p.pid).bright_yellow(),
+                    format!("{:<10}", p.user).dimmed(),
+                    p.command
+                );
+            }
+            let prompt = match (exact, n) {
+                (false, 1) => format!(
+                    "No process is named `{}`. Send {} to this one?",
+                    pattern,
+                    signal_name(signal)
+                ),
+                (false, _) => format!(
+                    "No process is named `{}`. Send {} to these {} processes?",
+                    pattern,
+                    signal_name(signal),
+                    n
+                ),
+                _ => format!("Send {} to these {} processes?", signal_name(signal), n),
+            };
+            match Confirm::new()
+                .with_prompt(prompt)
+                .default(false)
+                .interact_opt()
+            {
+                Ok(Some(true)) => {}
+                Ok(_) => return None,
+                Err(e) => {
+                    error(command, e.to_string());
+                    return None;
+                }
+            }
+        }
+        _ => {}
+    }
+    Some(matches)
+}
+
+/// `kill -l`: every signal, or the name for a number and the number for a name
+fn list_signals(which: Option<&str>) -> i32 {
+    let Some(which) = which else {
+        let names: Vec<&str> = SIGNALS.iter().map(|(name, _)| *name).collect();
+        println!("{}", names.join(" "));
+        return 0;
+    };
+    // `kill -l 143` names the signal that ended a command with that status
+    let number = which
+        .parse::<libc::c_int>()
+        .ok()
+        .map(|n| if n > 128 { n - 128 } else { n });
+    match number {
+        Some(n) => match SIGNALS.iter().find(|&&(_, s)| s == n) {
+            Some((name, _)) => println!("{}", name),
+            None => {
+                error("kill", format!("unknown signal `{}`", which));
+                return 1;
+            }
+        },
+        None => match signal_number(which) {
+            Some(n) => println!("{}", n),
+            None => {
+                error("kill", format!("unknown signal `{}`", which));
+                return 1;
+            }
+        },
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signals_are_named_with_or_without_sig_in_any_case() {
+        assert_eq!(signal_number("KILL"), Some(libc::SIGKILL));
+        assert_eq!(signal_number("sigterm"), Some(libc::SIGTERM));
+        assert_eq!(signal_number("SigHup"), Some(libc::SIGHUP));
+        assert_eq!(signal_number("9"), Some(9));
+        assert_eq!(signal_number("0"), Some(0));
+        for bad in ["SIG", "NOPE", "SIGSIGKILL", "99", "-1", ""] {
+            assert_eq!(signal_number(bad), None, "{}", bad);
+        }
+        assert_eq!(signal_name(libc::SIGUSR1), "SIGUSR1");
+        assert_eq!(signal_name(0), "0");
+    }
+
+    fn send(signal: libc::c_int, targets: Vec<&str>) -> SignalRequest<'_> {
+        SignalRequest::Send {
+            signal,
+            yes: false,
+            full: false,
+            targets,
+        }
+    }
+
+    #[test]
+    fn options_come_before_the_targets() {
+        let parse = |args: &[&'static str]| parse_signal_options("kill", args);
+        assert_eq!(parse(&["123"]), Ok(send(libc::SIGTERM, vec!["123"])));
+        assert_eq!(
+            parse(&["-9", "%1", "-HUP"]),
+            Ok(send(9, vec!["%1", "-HUP"]))
+        );
+        assert_eq!(
+            parse(&["-s", "int", "--", "-5"]),
+            Ok(send(libc::SIGINT, vec!["-5"]))
+        );
+        assert_eq!(
+            parse(&["-y", "-f", "-SIGSTOP", "vim"]),
+            Ok(SignalRequest::Send {
+                signal: libc::SIGSTOP,
+                yes: true,
+                full: true,
+                targets: vec!["vim"],
+            })
+        );
+        assert_eq!(parse(&["-l", "143"]), Ok(SignalRequest::List(Some("143"))));
+        assert!(parse(&["-s"]).is_err());
+        assert!(parse(&["-s", "FOO", "1"]).is_err());
+        assert!(parse(&["-BOGUS", "1"]).is_err());
+        assert!(parse(&["-9"]).is_err());
+    }
+
+    #[test]
+    fn pkill_has_no_list() {
+        assert!(parse_signal_options("pkill", &["-l"]).is_err());
+        assert_eq!(
+            parse_signal_options("pkill", &["-USR1", "foo"]),
+            Ok(send(libc::SIGUSR1, vec!["foo"]))
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use dialoguer::{Confirm, Select};
+
+use crate::state::{self, Value};
+
+/// Exit status when `read -t` runs out of time (128 + SIGALRM, like bash)
+const TIMEOUT_STATUS: i32 = 142;
+
+#[derive(Default)]
+struct Options {
+    prompt: Option<String>,
+    silent: bool,
+    raw: bool,
+    timeout: Option<Duration>,
+    nchars: Option<usize>,
+    delim: u8,
+    array: Option<String>,
+    names: Vec<String>,
+    select: Option<Vec<String>>,
+    confirm: bool,
+}
+
+fn usage() -> i32 {
+    eprintln!(
+        "Usage: read [-rs] [-p prompt] [-t secs] [-n chars] [-d delim] [-a array] [name...]\n       read [-p prompt] [name] --select item...\n       read [-p prompt] [name] --confirm"
+    );
+    2
+}
+
+fn error(message: String) {
+    eprintln!("{}", format!("xinux: read: {}", message).red());
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        delim: b'\n',
+        ..Default::default()
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        match arg.as_str() {
+            "--select" => {
+                opts.select = Some(args[i..].to_vec());
+                break;
+            }
+            "--confirm" => {
+                opts.confirm = true;
+                continue;
+            }
+            "--" => {
+                opts.names.extend(args[i..].iter().cloned());
+                break;
+            }
+            _ => {}
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+            opts.names.push(arg.clone());
+            continue;
+        };
+        let flags: Vec<char> = flags.chars().collect();
+        let mut j = 0;
+        while j < flags.len() {
+            let flag = flags[j];
+            j += 1;
+            if matches!(flag, 'r' | 's') {
+                if flag == 'r' {
+                    opts.raw = true;
+                } else {
+                    opts.silent = true;
+                }
+                continue;
+            }
+            if !matches!(flag, 'p' | 'a' | 'd' | 't' | 'n') {
+                return Err(format!("-{}: invalid option", flag));
+            }
+            // Options taking a value: the rest of this word, or the next argument
+            let value = if j < flags.len() {
+                let rest: String = flags[j..].iter().collect();
+                j = flags.len();
+                rest
+            } else if i < args.len() {
+                i += 1;
+                args[i - 1].clone()
+            } else {
+                return Err(format!("-{}: option requires an argument", flag));
+            };
+            match flag {
+                'p' => opts.prompt = Some(value),
+                'a' => opts.array = Some(value),
+                'd' => opts.delim = value.bytes().next().unwrap_or(0),
+                't' => {
+                    let secs: f64 = value
+                        .parse()
+                        .ok()
+                        .filter(|s: &f64| *s >= 0.0)
+                        .ok_or_else(|| format!("{}: invalid timeout specification", value))?;
+                    opts.timeout = Some(Duration::from_secs_f64(secs));
+                }
+                'n' => {
+                    let n = value
+                        .parse()
+                        .map_err(|_| format!("{}: invalid number", value))?;
+                    opts.nchars = Some(n);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+    for name in opts.names.iter().chain(opts.array.iter()) {
+        if !state::is_valid_name(name) {
+            return Err(format!("`{}`: not a valid identifier", name));
+        }
+    }
+    Ok(opts)
+}
+
+fn stdin_is_tty() -> bool {
+    // SAFETY: isatty only inspects the descriptor.
+    unsafe { libc::isatty(0) == 1 }
+}
+
+/// Puts the terminal into the mode `read` needs and restores it on drop
+struct TermGuard {
+    saved: Option<libc::termios>,
+}
+
+impl TermGuard {
+    fn new(silent: bool, char_mode: bool) -> Self {
+        if !(silent || char_mode) || !stdin_is_tty() {
+            return Self { saved: None };
+        }
+        let mut term = MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: tcgetattr fills the struct when it succeeds.
+        if unsafe { libc::tcgetattr(0, term.as_mut_ptr()) } != 0 {
+            return Self { saved: None };
+        }
+        // SAFETY: initialised by the successful tcgetattr above.
+        let saved = unsafe { term.assume_init() };
+        let mut raw = saved;
+        if silent {
+            raw.c_lflag &= !(libc::ECHO | libc::ECHONL);
+        }
+        if char_mode {
+            raw.c_lflag &= !libc::ICANON;
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+        }
+        // SAFETY: applying a termios derived from the current one.
+        unsafe { libc::tcsetattr(0, libc::TCSANOW, &raw) };
+        Self { saved: Some(saved) }
+    }
+}
+
+impl Drop for TermGuard {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            // SAFETY: restoring the attributes saved in `new`.
+            unsafe { libc::tcsetattr(0, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+enum ReadEnd {
+    Delimiter,
+    Count,
+    Eof,
+    Timeout,
+}
+
+/// Reads one byte from fd 0 without buffering, so nothing is consumed past the delimiter
+fn read_byte(deadline: Option<Instant>) -> Result<Option<u8>, ReadEnd> {
+    if let Some(deadline) = deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut pfd = libc::pollfd {
+            fd: 0,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ms = remaining.as_millis().min(i32::MAX as u128) as i32;
+        // SAFETY: polling a single valid pollfd.
+        if unsafe { libc::poll(&mut pfd, 1, ms) } == 0 {
+            return Err(ReadEnd::Timeout);
+        }
+    }
+    let mut byte = 0u8;
+    loop {
+        // SAFETY: reading into a one-byte buffer we own.
+        let n = unsafe { libc::read(0, (&mut byte as *mut u8).cast(), 1) };
+        match n {
+            1 => return Ok(Some(byte)),
+            0 => return Ok(None),
+            _ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Length of a UTF-8 sequence from its first byte
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0xF0..=0xFF => 4,
+        0xE0..=0xEF => 3,
+        0xC0..=0xDF => 2,
+        _ => 1,
+    }
+}
+
+fn read_input(opts: &Options) -> (String, ReadEnd) {
+    let deadline = opts.timeout.map(|t| Instant::now() + t);
+    let mut bytes = Vec::new();
+    let mut chars = 0;
+    let mut pending = 0;
+    let mut escaped = false;
+
+    let end = loop {
+        if opts.nchars.is_some_and(|n| chars >= n && pending == 0) {
+            break ReadEnd::Count;
+        }
+        let byte = match read_byte(deadline) {
+            Ok(Some(b)) => b,
+            Ok(None) => break ReadEnd::Eof,
+            Err(end) => break end,
+        };
+        if pending > 0 {
+            pending -= 1;
+            bytes.push(byte);
+            continue;
+        }
+        if escaped {
+            escaped = false;
+            if byte != b'\n' {
+                bytes.push(byte);
+                chars += 1;
+            }
+            continue;
+        }
+        if byte == opts.delim {
+            break ReadEnd::Delimiter;
+        }
+        if byte == b'\\' && !opts.raw {
+            escaped = true;
+            continue;
+        }
+        bytes.push(byte);
+        chars += 1;
+        pending = utf8_len(byte) - 1;
+    };
+    (String::from_utf8_lossy(&bytes).into_owned(), end)
+}
+
+/// Assigns the line to the variables, splitting on IFS like other shells
+fn assign(line: &str, opts: &Options) {
+    let ifs = state::get_var("IFS").unwrap_or_else(|| " \t\n".into());
+    if let Some(array) = &opts.array {
+        let fields = split_fields(line, &ifs, None);
+        state::set_value(array, Value::Array(fields));
+        return;
+    }
+    if opts.names.is_empty() {
+        state::set_var("REPLY", line);
+        return;
+    }
+    let fields = split_fields(line, &ifs, Some(opts.names.len()));
+    for (i, name) in opts.names.iter().enumerate() {
+        state::set_var(name, fields.get(i).map_or("", String::as_str));
+    }
+}
+
+/// Splits `line` into at most `limit` fields, the last taking the rest of the line.
+/// IFS whitespace around a separator merges into it, and is trimmed from both ends;
+/// every other IFS character ends exactly one field, so `x::z` splits on `:` into three.
+fn split_fields(line: &str, ifs: &str, limit: Option<usize>) -> Vec<String> {
+    let is_sep = |c: char| ifs.contains(c);
+    let is_space = |c: char| is_sep(c) && c.is_whitespace();
+    let mut rest = line.trim_matches(is_space);
+    let mut fields = Vec::new();
+    while !rest.is_empty() {
+        if limit.is_some_and(|n| fields.len() + 1 >= n) {
+            // The rest goes to the last name, less a single closing separator
+            let field = match rest.strip_suffix(|c: char| is_sep(c) && !is_space(c)) {
+                Some(field) if !field.trim_end_matches(is_space).contains(is_sep) => {
+                    field.trim_end_matches(is_space)
+                }
+                _ => rest,
+            };
+            fields.push(field.to_string());
+            break;
+        }
+        let end = rest.find(is_sep).unwrap_or(rest.len());
+        fields.push(rest[..end].to_string());
+        rest = rest[end..].trim_start_matches(is_space);
+        if let Some(after) = rest.strip_prefix(|c: char| is_sep(c) && !is_space(c)) {
+            rest = after.trim_start_matches(is_space);
+        }
+    }
+    fields
+}
+
+/// `read --select item...`: an arrow-key menu on a terminal, a numbered list otherwise
+fn read_select(opts: &Options, items: &[String]) -> i32 {
+    if items.is_empty() {
+        error("--select: no items given".into());
+        return 2;
+    }
+    let name = opts.names.first().map_or("REPLY", String::as_str);
+    let prompt = opts.prompt.clone().unwrap_or_default();
+
+    let choice = if stdin_is_tty() {
+        let mut select = Select::new().items(items).default(0);
+        if !prompt.is_empty() {
+            select = select.with_prompt(prompt.trim_end());
+        }
+        match select.interact_opt() {
+            Ok(choice) => choice,
+            Err(e) => {
+                error(e.to_string());
+                return 1;
+            }
+        }
+    } else {
+        for (i, item) in items.iter().enumerate() {
+            eprintln!("{}) {}", i + 1, item);
+        }
+        eprint!("{}", prompt);
+        let (line, _) = read_input(&Options {
+            delim: b'\n',
+            raw: true,
+            ..Default::default()
+        });
+        let line = line.trim();
+        line.parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .filter(|&i| i < items.len())
+            .or_else(|| items.iter().position(|item| item == line))
+    };
+
+    match choice {
+        Some(index) => {
+            state::set_var(name, &items[index]);
+            0
+        }
+        None => {
+            state::set_var(name, "");
+            1
+        }
+    }
+}
+
+/// `read --confirm`: yes/no question; the status is 0 for yes
+fn read_confirm(opts: &Options) -> i32 {
+    let name = opts.names.first().map_or("REPLY", String::as_str);
+    let prompt = opts.prompt.clone().unwrap_or_else(|| "Continue?".into());
+
+    let answer = if stdin_is_tty() {
+        match Confirm::new()
+            .with_prompt(prompt.trim_end())
+            .default(false)
+            .interact_opt()
+        {
+            Ok(answer) => answer.unwrap_or(false),
+            Err(e) => {
+                error(e.to_string());
+                return 1;
+            }
+        }
+    } else {
+        eprint!("{} [y/N] ", prompt.trim_end());
+        let (line, _) = read_input(&Options {
+            delim: b'\n',
+            raw: true,
+            ..Default::default()
+        });
+        matches!(line.trim().to_lowercase().as_str(), "y" | "yes")
+    };
+
+    state::set_var(name, if answer { "yes" } else { "no" });
+    i32::from(!answer)
+}
+
+pub fn builtin_read(args: &[String]) -> i32 {
+    let opts = match parse_options(args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            error(e);
+            return usage();
+        }
+    };
+    let _ = io::stdout().flush();
+
+    if let Some(items) = &opts.select {
+        return read_select(&opts, items);
+    }
+    if opts.confirm {
+        return read_confirm(&opts);
+    }
+
+    let tty = stdin_is_tty();
+    if let Some(prompt) = &opts.prompt
+        && tty
+    {
+        eprint!("{}", prompt);
+        let _ = io::stderr().flush();
+    }
+
+    let (line, end) = {
+        let _term = TermGuard::new(opts.silent, opts.nchars.is_some());
+        read_input(&opts)
+    };
+    if tty && opts.silent {
+        eprintln!();
+    }
+
+    match end {
+        ReadEnd::Timeout => {
+            assign(&line, &opts);
+            TIMEOUT_STATUS
+        }
+        ReadEnd::Eof => {
+            assign(&line, &opts);
+            1
+        }
+        ReadEnd::Delimiter | ReadEnd::Count => {
+            assign(&line, &opts);
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whitespace_separators_merge() {
+        assert_eq!(split_fields("  a  b\tc  ", " \t\n", None), ["a", "b", "c"]);
+        assert_eq!(split_fields("  a  b  c  ", " \t\n", Some(2)), ["a", "b  c"]);
+        assert!(split_fields("   ", " \t\n", None).is_empty());
+    }
+
+    #[test]
+    fn other_separators_each_end_one_field() {
+        assert_eq!(split_fields("x::z", ":", Some(3)), ["x", "", "z"]);
+        assert_eq!(split_fields("x::z", ":", None), ["x", "", "z"]);
+        assert_eq!(split_fields(":x", ":", Some(2)), ["", "x"]);
+        // A closing separator does not start another field
+        assert_eq!(split_fields("x::z:", ":", None), ["x", "", "z"]);
+    }
+
+    #[test]
+    fn whitespace_around_other_separators_belongs_to_them() {
+        assert_eq!(split_fields(" x :: z : ", ": ", None), ["x", "", "z"]);
+        assert_eq!(split_fields(" x : y : ", ": ", Some(3)), ["x", "y"]);
+        // Without whitespace in IFS, spaces are part of the fields
+        assert_eq!(split_fields(" x : y : ", ":", Some(2)), [" x ", " y : "]);
+    }
+
+    #[test]
+    fn the_last_name_takes_the_rest() {
+        assert_eq!(split_fields("a:b:c:d", ":", Some(2)), ["a", "b:c:d"]);
+        assert_eq!(split_fields("x:y:", ":", Some(2)), ["x", "y"]);
+        assert_eq!(split_fields("x:y::", ":", Some(2)), ["x", "y::"]);
+        assert_eq!(split_fields("x::z", ":", Some(2)), ["x", ":z"]);
+        assert_eq!(split_fields("x:y:", ":", Some(1)), ["x:y:"]);
+    }
+}
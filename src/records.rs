@@ -0,0 +1,578 @@
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use colored::Colorize;
+use comfy_table::{Attribute, Cell, Table, modifiers, presets};
+use regex::Regex;
+use serde_json::{Map, Number, Value};
+use unicode_width::UnicodeWidthStr;
+
+use crate::ls::{self, LsEntry, format_size};
+use crate::procs;
+
+/// Builtins that take records from the stage before them
+pub const FILTERS: [&str; 5] = ["where", "sort-by", "select", "first", "to"];
+
+/// A typed value in a record
+#[derive(Clone)]
+pub enum Field {
+    Text(String),
+    Number(f64),
+    Size(u64),
+    Time(DateTime<Local>),
+}
+
+/// How a builtin prints its results: `--json`, `--csv`, `--plain` or its usual display
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Pretty,
+    Json,
+    Csv,
+    Plain,
+}
+
+/// Takes the output format flags out of `args`; the last one given wins
+pub fn take_format<'a>(args: &[&'a str]) -> (Format, Vec<&'a str>) {
+    let mut format = Format::Pretty;
+    let mut rest = Vec::new();
+    for &arg in args {
+        match arg {
+            "--json" => format = Format::Json,
+            "--csv" => format = Format::Csv,
+            "--plain" => format = Format::Plain,
+            _ => rest.push(arg),
+        }
+    }
+    (format, rest)
+}
+
+/// One row of structured output, with its columns in display order
+#[derive(Clone)]
+pub struct Record(Vec<(String, Field)>);
+
+/// What a stage hands to the next: records, or text once `to` has rendered them
+enum Data {
+    Records(Vec<Record>),
+    Text(String),
+}
+
+impl Field {
+    /// How the field reads in a table
+    fn display(&self) -> String {
+        match self {
+            Field::Text(s) => s.clone(),
+            Field::Number(n) => n.to_string(),
+            Field::Size(bytes) => format_size(*bytes),
+            Field::Time(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+        }
+    }
+
+    /// How the field is written in JSON and CSV, where sizes stay exact
+    fn raw(&self) -> String {
+        match self {
+            Field::Text(s) => s.clone(),
+            Field::Number(n) => n.to_string(),
+            Field::Size(bytes) => bytes.to_string(),
+            Field::Time(time) => time.to_rfc3339(),
+        }
+    }
+
+    /// Whole numbers are written without a fraction; NaN and infinities have no JSON
+    /// form and become `null`
+    fn json(&self) -> Value {
+        match self {
+            Field::Number(n) if n.fract() == 0.0 && n.abs() < 9e15 => Value::from(*n as i64),
+            Field::Number(n) => Number::from_f64(*n).map_or(Value::Null, Value::Number),
+            Field::Size(bytes) => Value::from(*bytes),
+            _ => Value::String(self.raw()),
+        }
+    }
+
+    fn compare(&self, other: &Field) -> Ordering {
+        match (self, other) {
+            (Field::Number(a), Field::Number(b)) => a.total_cmp(b),
+            (Field::Size(a), Field::Size(b)) => a.cmp(b),
+            (Field::Time(a), Field::Time(b)) => a.cmp(b),
+            (a, b) => a.display().to_lowercase().cmp(&b.display().to_lowercase()),
+        }
+    }
+
+    /// Parses `text` as a value of the same kind as `self`, for `where`
+    fn parse_like(&self, text: &str) -> Result<Field, String> {
+        match self {
+            Field::Text(_) => Ok(Field::Text(text.to_string())),
+            Field::Number(_) => text
+                .parse()
+                .map(Field::Number)
+                .map_err(|_| format!("`{}` is not a number", text)),
+            Field::Size(_) => parse_size(text)
+                .map(Field::Size)
+                .ok_or_else(|| format!("`{}` is not a size (try 512, 10KB or 1.5MB)", text)),
+            Field::Time(_) => parse_time(text).map(Field::Time).ok_or_else(|| {
+                format!(
+                    "`{}` is not a date (try 2024-05-01, \"2024-05-01 12:00\" or 2days)",
+                    text
+                )
+            }),
+        }
+    }
+}
+
+impl Record {
+    pub fn new(columns: Vec<(String, Field)>) -> Self {
+        Record(columns)
+    }
+
+    /// An object with the columns in display order
+    fn json(&self) -> Value {
+        let fields: Map<String, Value> = self
+            .0
+            .iter()
+            .map(|(name, field)| (name.clone(), field.json()))
+            .collect();
+        Value::Object(fields)
+    }
+
+    fn get(&self, column: &str) -> Option<&Field> {
+        self.0
+            .iter()
+            .find(|(name, _)| name == column)
+            .map(|(_, field)| field)
+    }
+
+    fn columns(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// How many columns each field takes in a table
+    pub(crate) fn display_widths(&self) -> Vec<usize> {
+        self.0
+            .iter()
+            .map(|(_, field)| field.display().width())
+            .collect()
+    }
+
+    /// Rewrites the text in `column`
+    pub(crate) fn map_text(&mut self, column: &str, f: impl Fn(&str) -> String) {
+        for (name, field) in &mut self.0 {
+            if name == column
+                && let Field::Text(text) = field
+            {
+                *text = f(text);
+            }
+        }
+    }
+}
+
+/// Builtins whose output can be taken as records by a filter
+pub fn is_producer(name: &str) -> bool {
+    matches!(name, "ls" | "la" | "ps" | "procs")
+}
+
+pub fn is_filter(name: &str) -> bool {
+    FILTERS.contains(&name)
+}
+
+/// A filter run on its own, or after something that only writes text
+pub fn without_records(name: &str) -> i32 {
+    eprintln!(
+        "{}",
+        format!(
+            "xinux: {}: expects records from a builtin such as `ls` (e.g. `ls | where size > 1MB`)",
+            name
+        )
+        .red()
+    );
+    1
+}
+
+/// Runs a producer and the filters after it in-process, printing the result.
+/// Records only become text here, at the end of the chain.
+pub fn run_chain(stages: &[Vec<String>]) -> i32 {
+    match chain(stages) {
+        Ok(Data::Records(records)) => {
+            // A chain piped into another command sends it rows, not a table
+            // SAFETY: only asks whether stdout is a terminal.
+            let format = if unsafe { libc::isatty(1) } == 1 {
+                Format::Pretty
+            } else {
+                Format::Plain
+            };
+            print_records(&records, format);
+            0
+        }
+        Ok(Data::Text(text)) => {
+            print!("{}", text);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", format!("xinux: {}", e).red());
+            1
+        }
+    }
+}
+
+fn chain(stages: &[Vec<String>]) -> Result<Data, String> {
+    let Some((producer, filters)) = stages.split_first() else {
+        return Ok(Data::Records(Vec::new()));
+    };
+    let mut data = Data::Records(produce(producer)?);
+    for argv in filters {
+        let Data::Records(records) = data else {
+            return Err(format!(
+                "{}: `to` already turned the records into text",
+                argv[0]
+            ));
+        };
+        data = filter(&argv[0], &argv[1..], records).map_err(|e| format!("{}: {}", argv[0], e))?;
+    }
+    Ok(data)
+}
+
+fn produce(argv: &[String]) -> Result<Vec<Record>, String> {
+    let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+    if matches!(argv[0].as_str(), "ps" | "procs") {
+        let opts = procs::parse_ps_options(&args).map_err(|e| format!("{}: {}", argv[0], e))?;
+        return Ok(procs::ps_records(&opts, false));
+    }
+    let opts = ls::parse_options(&argv[0], &args).map_err(|e| format!("{}: {}", argv[0], e))?;
+    Ok(from_entries(ls::collect(&opts).0, opts.git))
+}
+
+/// Directory entries as records, with a `git` column for `ls --git`
+pub(crate) fn from_entries(entries: Vec<LsEntry>, git: bool) -> Vec<Record> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            let kind = entry.label.trim_matches(['[', ']']).to_lowercase();
+            let (size, modified) = (entry.size(), entry.modified());
+            let mut fields = vec![
+                ("name".to_string(), Field::Text(entry.name)),
+                ("type".to_string(), Field::Text(kind)),
+                ("size".to_string(), Field::Size(size)),
+                ("modified".to_string(), Field::Time(modified.into())),
+            ];
+            // Entries outside a repository get an empty status
+            if git {
+                let status = entry.git.map_or("", |s| s.name());
+                fields.push(("git".to_string(), Field::Text(status.to_string())));
+            }
+            Record(fields)
+        })
+        .collect()
+}
+
+fn filter(name: &str, args: &[String], mut records: Vec<Record>) -> Result<Data, String> {
+    match name {
+        "where" => {
+            let usage = || "usage: where COLUMN OP VALUE (e.g. `where size > 1MB`)".to_string();
+            let (column, rest) = args.split_first().ok_or_else(usage)?;
+            let rest = rest.join(" ");
+            let op = ["==", "!=", "<=", ">=", "=~", "!~", "=", "<", ">"]
+                .into_iter()
+                .find(|op| rest.starts_with(op))
+                .ok_or_else(usage)?;
+            let value = rest[op.len()..].trim();
+            if value.is_empty() {
+                return Err(usage());
+            }
+            check_columns(&records, std::slice::from_ref(column))?;
+            let Some(sample) = records.first().and_then(|r| r.get(column)) else {
+                return Ok(Data::Records(records));
+            };
+            let keep: Box<dyn Fn(&Field) -> bool> = if op == "=~" || op == "!~" {
+                let re = Regex::new(value).map_err(|e| e.to_string())?;
+                Box::new(move |field| re.is_match(&field.display()) == (op == "=~"))
+            } else {
+                let target = sample.parse_like(value)?;
+                Box::new(move |field| {
+                    let ordering = field.compare(&target);
+                    match op {
+                        "!=" => ordering != Ordering::Equal,
+                        "<" => ordering == Ordering::Less,
+                        "<=" => ordering != Ordering::Greater,
+                        ">" => ordering == Ordering::Greater,
+                        ">=" => ordering != Ordering::Less,
+                        _ => ordering == Ordering::Equal,
+                    }
+                })
+            };
+            records.retain(|r| r.get(column).is_some_and(&keep));
+            Ok(Data::Records(records))
+        }
+        "sort-by" => {
+            let reverse = args.iter().any(|a| a == "-r" || a == "--reverse");
+            let columns: Vec<String> = args
+                .iter()
+                .filter(|a| !a.starts_with('-'))
+                .cloned()
+                .collect();
+            if columns.is_empty() {
+                return Err("usage: sort-by COLUMN... [-r]".into());
+            }
+            check_columns(&records, &columns)?;
+            records.sort_by(|a, b| {
+                let ordering = columns
+                    .iter()
+                    .map(|c| match (a.get(c), b.get(c)) {
+                        (Some(x), Some(y)) => x.compare(y),
+                        _ => Ordering::Equal,
+                    })
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal);
+                if reverse {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+            Ok(Data::Records(records))
+        }
+        "select" => {
+            if args.is_empty() {
+                return Err("usage: select COLUMN...".into());
+            }
+            check_columns(&records, args)?;
+            let records = records
+                .into_iter()
+                .map(|r| {
+                    Record(
+                        args.iter()
+                            .filter_map(|c| r.get(c).map(|f| (c.clone(), f.clone())))
+                            .collect(),
+                    )
+                })
+                .collect();
+            Ok(Data::Records(records))
+        }
+        "first" => {
+            let count = match args {
+                [] => 1,
+                [n] => n.parse().map_err(|_| format!("`{}` is not a count", n))?,
+                _ => return Err("usage: first [N]".into()),
+            };
+            records.truncate(count);
+            Ok(Data::Records(records))
+        }
+        "to" => match args {
+            [format] if format == "json" => Ok(Data::Text(to_json(&records))),
+            [format] if format == "csv" => Ok(Data::Text(to_csv(&records))),
+            [format] if format == "table" => Ok(Data::Text(to_table(&records))),
+            _ => Err("usage: to json|csv|table".into()),
+        },
+        _ => Err("not a filter".into()),
+    }
+}
+
+fn check_columns(records: &[Record], wanted: &[String]) -> Result<(), String> {
+    let Some(first) = records.first() else {
+        return Ok(());
+    };
+    match wanted.iter().find(|c| first.get(c).is_none()) {
+        Some(missing) => Err(format!(
+            "no column `{}` (columns: {})",
+            missing,
+            first.columns().collect::<Vec<_>>().join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// `512`, `10k`, `1.5MB`, `2GiB`; units are powers of 1024 like in `ls`
+fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let power = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 1,
+        "m" | "mb" | "mib" => 2,
+        "g" | "gb" | "gib" => 3,
+        "t" | "tb" | "tib" => 4,
+        _ => return None,
+    };
+    Some((number * 1024f64.powi(power)) as u64)
+}
+
+/// `now`, `@SECONDS`, RFC 3339, a date or a date and time, or a duration meaning
+/// that long ago, such as `2h` or `3 days ago`. `where` and `touch -d` both use it.
+pub(crate) fn parse_time(text: &str) -> Option<DateTime<Local>> {
+    let text = text.trim();
+    if text == "now" {
+        return Some(Local::now());
+    }
+    if let Some(seconds) = text.strip_prefix('@') {
+        let time = DateTime::from_timestamp(seconds.parse().ok()?, 0)?;
+        return Some(time.with_timezone(&Local));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Local));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .single();
+    }
+    for format in ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(text, format) {
+            return Local.from_local_datetime(&time).single();
+        }
+    }
+    let ago: Duration =
+        humantime::parse_duration(text.strip_suffix(" ago").unwrap_or(text)).ok()?;
+    Some(Local::now() - chrono::Duration::from_std(ago).ok()?)
+}
+
+fn header(records: &[Record]) -> Vec<String> {
+    records
+        .first()
+        .map(|r| r.columns().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn to_table(records: &[Record]) -> String {
+    if records.is_empty() {
+        return String::new();
+    }
+    let mut table = Table::new();
+    // SAFETY: only asks whether stdout is a terminal.
+    if unsafe { libc::isatty(1) } == 1 {
+        table
+            .load_preset(presets::UTF8_FULL_CONDENSED)
+            .apply_modifier(modifiers::UTF8_ROUND_CORNERS);
+    } else {
+        table.load_preset(presets::NOTHING);
+    }
+    let bold = colored::control::SHOULD_COLORIZE.should_colorize();
+    table.set_header(header(records).into_iter().map(|name| {
+        let cell = Cell::new(name);
+        if bold {
+            cell.add_attribute(Attribute::Bold)
+        } else {
+            cell
+        }
+    }));
+    for record in records {
+        table.add_row(record.0.iter().map(|(_, field)| field.display()));
+    }
+    format!("{}\n", table)
+}
+
+fn to_json(records: &[Record]) -> String {
+    let rows: Vec<Value> = records.iter().map(Record::json).collect();
+    serde_json::to_string_pretty(&rows).unwrap_or_default() + "\n"
+}
+
+fn to_csv(records: &[Record]) -> String {
+    let quote = |value: &str| {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    if records.is_empty() {
+        return String::new();
+    }
+    let mut out = header(records).join(",");
+    out.push('\n');
+    for record in records {
+        let fields: Vec<String> = record
+            .0
+            .iter()
+            .map(|(_, field)| quote(&field.raw()))
+            .collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Prints a list of records as JSON, CSV, one first column per line, or a table
+pub fn print_records(records: &[Record], format: Format) {
+    match format {
+        Format::Json => print!("{}", to_json(records)),
+        Format::Csv => print!("{}", to_csv(records)),
+        Format::Plain => {
+            for record in records {
+                if let Some((_, field)) = record.0.first() {
+                    println!("{}", field.raw());
+                }
+            }
+        }
+        Format::Pretty => print!("{}", to_table(records)),
+    }
+}
+
+/// Prints a single record, such as a summary, with one `key value` line per field in plain mode
+pub fn print_record(record: &Record, format: Format) {
+    match format {
+        Format::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&record.json()).unwrap_or_default()
+            );
+        }
+        Format::Plain => {
+            for (name, field) in &record.0 {
+                println!("{} {}", name, field.raw());
+            }
+        }
+        _ => print_records(std::slice::from_ref(record), format),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_without_a_json_form_become_null() {
+        let record = Record::new(vec![
+            ("nan".into(), Field::Number(f64::NAN)),
+            ("inf".into(), Field::Number(f64::INFINITY)),
+            ("whole".into(), Field::Number(3.0)),
+            ("half".into(), Field::Number(0.5)),
+            ("size".into(), Field::Size(1024)),
+            ("name".into(), Field::Text("a \"b\"".into())),
+        ]);
+        let json = to_json(&[record]);
+        let parsed: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!([{
+                "nan": null,
+                "inf": null,
+                "whole": 3,
+                "half": 0.5,
+                "size": 1024,
+                "name": "a \"b\"",
+            }])
+        );
+    }
+
+    #[test]
+    fn columns_keep_their_order() {
+        let record = Record::new(vec![
+            ("zeta".into(), Field::Number(1.0)),
+            ("alpha".into(), Field::Number(2.0)),
+        ]);
+        let json = to_json(&[record]);
+        assert!(json.find("zeta") < json.find("alpha"));
+    }
+
+    #[test]
+    fn times_can_be_given_as_ago() {
+        let day = Local::now() - chrono::Duration::days(1);
+        for text in ["1 day ago", "1day", " 24h ago "] {
+            let time = parse_time(text).unwrap();
+            assert!((time - day).num_seconds().abs() < 5, "{}", text);
+        }
+        assert_eq!(parse_time("@0").unwrap().timestamp(), 0);
+        assert!(parse_time("someday").is_none());
+    }
+}
@@ -0,0 +1,318 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{File, Metadata};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What the first bytes of a file say it is, as a key of the file-type registry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Content {
+    /// A format that settles the type, such as an ELF binary or a PNG image
+    Specific(&'static str),
+    /// Text, binary data or a container (zip, gzip, ...) that many types are made of,
+    /// so a type known from the name is more precise
+    Generic(&'static str),
+}
+
+/// How much of a file is read to decide
+const SNIFF_LEN: usize = 1024;
+
+struct Cached {
+    modified: SystemTime,
+    size: u64,
+    content: Option<Content>,
+}
+
+thread_local! {
+    /// Results by directory and file name, kept while the file's size and time stay the same
+    static CACHE: RefCell<HashMap<PathBuf, HashMap<OsString, Cached>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Sniffs a regular file, reusing an earlier answer when the file has not changed
+pub fn content(path: &Path, metadata: &Metadata) -> Option<Content> {
+    if !metadata.is_file() || metadata.len() == 0 {
+        return None;
+    }
+    let dir = path.parent()?.to_path_buf();
+    let name = path.file_name()?.to_os_string();
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let size = metadata.len();
+    let known = CACHE.with(|c| {
+        c.borrow()
+            .get(&dir)
+            .and_then(|files| files.get(&name))
+            .filter(|hit| hit.modified == modified && hit.size == size)
+            .map(|hit| hit.content)
+    });
+    if let Some(content) = known {
+        return content;
+    }
+    let content = sniff_file(path);
+    CACHE.with(|c| {
+        c.borrow_mut().entry(dir).or_default().insert(
+            name,
+            Cached {
+                modified,
+                size,
+                content,
+            },
+        )
+    });
+    content
+}
+
+fn sniff_file(path: &Path) -> Option<Content> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    sniff(&head, head.len() < SNIFF_LEN)
+}
+
+/// Decides from the first bytes of a file; `whole` says they are all there is
+fn sniff(head: &[u8], whole: bool) -> Option<Content> {
+    use Content::{Generic, Specific};
+    if head.is_empty() {
+        return None;
+    }
+    let starts = |magic: &[u8]| head.starts_with(magic);
+
+    if starts(b"\x7fELF") {
+        return Some(Specific(elf_kind(head)));
+    }
+    if starts(b"MZ")
+        && let Some(kind) = pe_kind(head)
+    {
+        return Some(Specific(kind));
+    }
+    if let Some(kind) = mach_o_kind(head) {
+        return Some(Specific(kind));
+    }
+    if starts(b"#!") {
+        return Some(Specific(interpreter_kind(head)));
+    }
+    let specific = [
+        (&b"\x89PNG\r\n\x1a\n"[..], "image"),
+        (b"\xff\xd8\xff", "image"),
+        (b"GIF87a", "image"),
+        (b"GIF89a", "image"),
+        (b"%PDF-", "pdf"),
+        (b"\0asm", "wasm"),
+        (b"SQLite format 3\0", "database"),
+    ];
+    if let Some((_, kind)) = specific.iter().find(|(magic, _)| starts(magic)) {
+        return Some(Specific(kind));
+    }
+    if starts(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        return Some(Specific("image"));
+    }
+    let containers: [&[u8]; 8] = [
+        b"PK\x03\x04",
+        b"PK\x05\x06",
+        b"\x1f\x8b",
+        b"BZh",
+        b"\xfd7zXZ\0",
+        b"7z\xbc\xaf\x27\x1c",
+        b"\x28\xb5\x2f\xfd",
+        b"Rar!\x1a\x07",
+    ];
+    if containers.iter().any(|magic| starts(magic)) {
+        return Some(Generic("archive"));
+    }
+    Some(Generic(if is_text(head, whole) {
+        "text"
+    } else {
+        "binary"
+    }))
+}
+
+/// UTF-8 without NUL bytes; a character cut off at the end of the sample still counts
+fn is_text(head: &[u8], whole: bool) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => !whole && e.error_len().is_none(),
+    }
+}
+
+/// Executables, shared libraries, object files and core dumps by `e_type`
+fn elf_kind(head: &[u8]) -> &'static str {
+    let little_endian = head.get(5) != Some(&2);
+    let e_type = match head.get(16..18) {
+        Some(&[a, b]) if little_endian => u16::from_le_bytes([a, b]),
+        Some(&[a, b]) => u16::from_be_bytes([a, b]),
+        _ => return "exe",
+    };
+    match e_type {
+        1 => "object",
+        // Position-independent executables are ET_DYN too; only libraries lack an interpreter
+        3 if !contains(head, b"/lib") && !contains(head, b"/bin/linker") => "library",
+        4 => "binary",
+        _ => "exe",
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// A Windows executable or DLL, if the `MZ` header leads to a PE header
+fn pe_kind(head: &[u8]) -> Option<&'static str> {
+    let offset = u32::from_le_bytes(head.get(0x3c..0x40)?.try_into().ok()?) as usize;
+    if head.get(offset..offset + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let characteristics = u16::from_le_bytes(head.get(offset + 22..offset + 24)?.try_into().ok()?);
+    Some(if characteristics & 0x2000 != 0 {
+        "library"
+    } else {
+        "exe"
+    })
+}
+
+fn mach_o_kind(head: &[u8]) -> Option<&'static str> {
+    let magic = head.get(..4)?;
+    let big_endian = match magic {
+        [0xfe, 0xed, 0xfa, 0xce | 0xcf] => true,
+        [0xce | 0xcf, 0xfa, 0xed, 0xfe] => false,
+        // Universal binaries share their magic with Java classes, which have a
+        // version number where the universal header has a small architecture count
+        [0xca, 0xfe, 0xba, 0xbe] => {
+            let count = u32::from_be_bytes(head.get(4..8)?.try_into().ok()?);
+            return (count < 20).then_some("exe");
+        }
+        _ => return None,
+    };
+    let bytes: [u8; 4] = head.get(12..16)?.try_into().ok()?;
+    let file_type = if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    };
+    Some(match file_type {
+        1 => "object",
+        6 | 8 => "library",
+        _ => "exe",
+    })
+}
+
+/// The script type for a `#!` line, looking through `env` and its options
+fn interpreter_kind(head: &[u8]) -> &'static str {
+    let line = head[2..].split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(line);
+    let mut words = line.split_whitespace();
+    let mut program = words.next().unwrap_or_default();
+    if program.rsplit('/').next() == Some("env") {
+        program = words
+            .find(|w| !w.starts_with('-') && !w.contains('='))
+            .unwrap_or_default();
+    }
+    let name = program.rsplit('/').next().unwrap_or_default();
+    let base = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match base {
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "mksh" | "fish" | "xinux" => "shell",
+        "python" | "pypy" => "python",
+        "node" | "nodejs" | "deno" | "bun" => "javascript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" | "luajit" => "lua",
+        "Rscript" => "r",
+        "pwsh" => "powershell",
+        _ => "script",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Content::{Generic, Specific};
+
+    /// A 64-bit little-endian ELF header of type `e_type`, followed by `rest`
+    fn elf(e_type: u16, rest: &[u8]) -> Vec<u8> {
+        let mut head = b"\x7fELF\x02\x01\x01".to_vec();
+        head.resize(16, 0);
+        head.extend(e_type.to_le_bytes());
+        head.resize(64, 0);
+        head.extend(rest);
+        head
+    }
+
+    #[test]
+    fn elf_files_are_told_apart_by_type() {
+        assert_eq!(sniff(&elf(1, b""), true), Some(Specific("object")));
+        assert_eq!(sniff(&elf(2, b""), true), Some(Specific("exe")));
+        assert_eq!(sniff(&elf(3, b""), true), Some(Specific("library")));
+        // A position-independent executable names its interpreter
+        let pie = elf(3, b"/lib64/ld-linux-x86-64.so.2\0");
+        assert_eq!(sniff(&pie, true), Some(Specific("exe")));
+        assert_eq!(sniff(&elf(4, b""), true), Some(Specific("binary")));
+    }
+
+    #[test]
+    fn shebangs_name_the_interpreter() {
+        let kind = |line: &str| sniff(line.as_bytes(), true);
+        assert_eq!(kind("#!/bin/bash\necho"), Some(Specific("shell")));
+        assert_eq!(kind("#!/usr/bin/python3.12"), Some(Specific("python")));
+        assert_eq!(
+            kind("#!/usr/bin/env -S node --flag"),
+            Some(Specific("javascript"))
+        );
+        assert_eq!(kind("#!/usr/bin/env LANG=C ruby"), Some(Specific("ruby")));
+        assert_eq!(kind("#! /usr/local/bin/xinux"), Some(Specific("shell")));
+        assert_eq!(kind("#!/opt/tool/run"), Some(Specific("script")));
+    }
+
+    #[test]
+    fn magic_bytes_and_text() {
+        assert_eq!(
+            sniff(b"\x89PNG\r\n\x1a\n....", true),
+            Some(Specific("image"))
+        );
+        assert_eq!(
+            sniff(b"RIFF\0\0\0\0WEBPVP8 ", true),
+            Some(Specific("image"))
+        );
+        assert_eq!(sniff(b"%PDF-1.7", true), Some(Specific("pdf")));
+        assert_eq!(sniff(b"PK\x03\x04rest", true), Some(Generic("archive")));
+        assert_eq!(sniff(b"\x1f\x8b\x08", true), Some(Generic("archive")));
+        assert_eq!(sniff(b"MZ but no PE header", true), Some(Generic("text")));
+        assert_eq!(
+            sniff("plain text, ünïcode".as_bytes(), true),
+            Some(Generic("text"))
+        );
+        assert_eq!(sniff(b"text\0with a NUL", true), Some(Generic("binary")));
+        // A class file starts like a universal binary but has a large version number
+        assert_eq!(
+            sniff(b"\xca\xfe\xba\xbe\0\0\0\x41", true),
+            Some(Generic("binary"))
+        );
+        assert_eq!(sniff(b"", true), None);
+    }
+
+    #[test]
+    fn a_character_cut_off_by_the_sample_is_still_text() {
+        let cut = &"é".as_bytes()[..1];
+        assert_eq!(sniff(cut, false), Some(Generic("text")));
+        assert_eq!(sniff(cut, true), Some(Generic("binary")));
+    }
+
+    #[test]
+    fn windows_dlls_have_the_dll_flag() {
+        let mut head = b"MZ".to_vec();
+        head.resize(0x40, 0);
+        head[0x3c] = 0x40;
+        head.extend(b"PE\0\0");
+        head.resize(0x40 + 24, 0);
+        assert_eq!(sniff(&head, true), Some(Specific("exe")));
+        head[0x40 + 23] = 0x20;
+        assert_eq!(sniff(&head, true), Some(Specific("library")));
+    }
+}
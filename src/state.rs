@@ -0,0 +1,255 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::rc::Rc;
+
+use crate::parse_input::Node;
+
+/// Default value for `FUNCNEST` when the variable is unset
+pub const DEFAULT_FUNCNEST: usize = 200;
+
+/// A shell variable: either a plain string or an indexed array
+#[derive(Clone, Debug)]
+pub enum Value {
+    Str(String),
+    Array(Vec<String>),
+}
+
+impl Value {
+    /// Scalar view of the value (first element for arrays, like bash)
+    pub fn as_scalar(&self) -> String {
+        match self {
+            Value::Str(s) => s.clone(),
+            Value::Array(items) => items.first().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// A user-defined function
+#[derive(Clone)]
+pub struct Function {
+    pub body: Rc<Node>,
+    pub source: String,
+}
+
+/// One active function call
+pub struct Frame {
+    pub name: String,
+    pub args: Vec<String>,
+    /// `LINENO` of the command that called the function
+    pub call_line: usize,
+    /// Values shadowed by `local` (and whether they were exported),
+    /// restored when the function returns
+    pub saved: Vec<(String, Option<Value>, bool)>,
+}
+
+/// A command started with `&`
+pub struct Job {
+    /// The `N` of `%N`
+    pub id: usize,
+    /// The forked shell running the command, which leads the job's process group
+    pub pid: libc::pid_t,
+    pub command: String,
+    /// Exit code, once it has finished
+    pub done: Option<i32>,
+}
+
+#[derive(Default)]
+pub struct Shell {
+    pub vars: HashMap<String, Value>,
+    pub functions: HashMap<String, Function>,
+    pub frames: Vec<Frame>,
+    pub positional: Vec<String>,
+    pub script_name: String,
+    pub status: i32,
+    pub exit_requested: Option<i32>,
+    /// Nesting depth of contexts where failures are expected (conditions, functions)
+    pub quiet_depth: usize,
+    pub loop_depth: usize,
+    pub source_depth: usize,
+    pub interactive: bool,
+    /// Where `getopts` is inside a group like `-abc`: the `OPTIND` it belongs to and the offset
+    pub getopts_pos: (usize, usize),
+    /// `trap` commands by condition (`DEBUG`, `EXIT`)
+    pub traps: HashMap<String, String>,
+    /// Set while a trap runs so it does not trigger itself
+    pub in_trap: bool,
+    /// Line of the simple command being run, for `LINENO`
+    pub lineno: usize,
+    /// Background jobs, oldest first
+    pub jobs: Vec<Job>,
+    /// PID of the newest background job, for `$!`
+    pub last_background: Option<libc::pid_t>,
+}
+
+thread_local! {
+    static SHELL: RefCell<Shell> = RefCell::new(Shell {
+        script_name: "xinux".into(),
+        ..Default::default()
+    });
+}
+
+/// Runs `f` with mutable access to the shell state.
+/// Never call back into the evaluator from inside `f`.
+pub fn with<R>(f: impl FnOnce(&mut Shell) -> R) -> R {
+    SHELL.with(|s| f(&mut s.borrow_mut()))
+}
+
+pub fn status() -> i32 {
+    with(|s| s.status)
+}
+
+pub fn set_status(code: i32) {
+    with(|s| s.status = code);
+}
+
+/// Current positional parameters (`$1`, `$2`, ...)
+pub fn positionals() -> Vec<String> {
+    with(|s| match s.frames.last() {
+        Some(frame) => frame.args.clone(),
+        None => s.positional.clone(),
+    })
+}
+
+pub fn set_positionals(args: Vec<String>) {
+    with(|s| match s.frames.last_mut() {
+        Some(frame) => frame.args = args,
+        None => s.positional = args,
+    });
+}
+
+/// Looks up a variable, falling back to the environment
+pub fn get_value(name: &str) -> Option<Value> {
+    if name == "LINENO" {
+        return Some(Value::Str(with(|s| s.lineno).to_string()));
+    }
+    if name == "FUNCNAME" {
+        let names: Vec<String> = with(|s| s.frames.iter().rev().map(|f| f.name.clone()).collect());
+        return if names.is_empty() {
+            None
+        } else {
+            Some(Value::Array(names))
+        };
+    }
+    if let Some(v) = with(|s| s.vars.get(name).cloned()) {
+        return Some(v);
+    }
+    env::var(name).ok().map(Value::Str)
+}
+
+pub fn get_var(name: &str) -> Option<String> {
+    get_value(name).map(|v| v.as_scalar())
+}
+
+/// Sets a variable. Exported (environment) variables stay exported.
+pub fn set_value(name: &str, value: Value) {
+    if env::var_os(name).is_some()
+        && let Value::Str(s) = &value
+    {
+        export(name, s);
+        with(|sh| sh.vars.remove(name));
+        return;
+    }
+    with(|s| s.vars.insert(name.to_string(), value));
+}
+
+pub fn set_var(name: &str, value: &str) {
+    set_value(name, Value::Str(value.to_string()));
+}
+
+pub fn unset_var(name: &str) {
+    with(|s| s.vars.remove(name));
+    if env::var_os(name).is_some() {
+        // SAFETY: the shell never mutates the environment from more than one thread.
+        unsafe { env::remove_var(name) };
+    }
+}
+
+pub fn export(name: &str, value: &str) {
+    // SAFETY: the shell never mutates the environment from more than one thread.
+    unsafe { env::set_var(name, value) };
+}
+
+/// Declares `name` local to the innermost function call.
+/// Returns false when called outside a function.
+pub fn make_local(name: &str) -> bool {
+    let previous = get_value(name);
+    let exported = env::var_os(name).is_some();
+    with(|s| {
+        let Some(frame) = s.frames.last_mut() else {
+            return false;
+        };
+        if !frame.saved.iter().any(|(n, _, _)| n == name) {
+            frame.saved.push((name.to_string(), previous, exported));
+        }
+        if exported {
+            // SAFETY: the shell never mutates the environment from more than one thread.
+            unsafe { env::remove_var(name) };
+        }
+        s.vars.remove(name);
+        true
+    })
+}
+
+pub fn push_frame(name: &str, args: Vec<String>) {
+    with(|s| {
+        s.frames.push(Frame {
+            name: name.to_string(),
+            args,
+            call_line: s.lineno,
+            saved: Vec::new(),
+        })
+    });
+}
+
+/// Pops the innermost call frame and restores every variable it shadowed
+pub fn pop_frame() {
+    let Some(frame) = with(|s| s.frames.pop()) else {
+        return;
+    };
+    for (name, previous, exported) in frame.saved.into_iter().rev() {
+        unset_var(&name);
+        match previous {
+            Some(value) if exported => export(&name, &value.as_scalar()),
+            Some(value) => set_value(&name, value),
+            None => {}
+        }
+    }
+}
+
+pub fn call_depth() -> usize {
+    with(|s| s.frames.len())
+}
+
+/// Maximum function nesting level, taken from `FUNCNEST`
+pub fn max_call_depth() -> usize {
+    get_var("FUNCNEST")
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_FUNCNEST)
+}
+
+pub fn function(name: &str) -> Option<Function> {
+    with(|s| s.functions.get(name).cloned())
+}
+
+pub fn define_function(name: &str, function: Function) {
+    with(|s| s.functions.insert(name.to_string(), function));
+}
+
+pub fn function_names() -> Vec<String> {
+    let mut names: Vec<String> = with(|s| s.functions.keys().cloned().collect());
+    names.sort();
+    names
+}
+
+/// Whether failing external commands should be reported on stderr
+pub fn report_failures() -> bool {
+    with(|s| s.interactive && s.quiet_depth == 0 && s.source_depth == 0 && s.frames.is_empty())
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,496 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+
+use crate::bench;
+use crate::eval::{self, Flow};
+use crate::expand::expand_words;
+use crate::ls::format_size;
+use crate::parse_input::{Node, Word, parse};
+use crate::records::{self, Field, Format, Record};
+use crate::state;
+
+/// How coarsely CPU time may be counted; shorter commands get no CPU percentage
+const CPU_RESOLUTION: Duration = Duration::from_millis(10);
+
+thread_local! {
+    /// The largest resident set of the children reaped under each running `time`,
+    /// innermost last
+    static PEAKS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// How `time` runs and reports
+pub(crate) struct Options {
+    /// `-p`: the POSIX `real`/`user`/`sys` lines
+    posix: bool,
+    pub(crate) format: Format,
+    /// `--runs N`
+    pub(crate) runs: Option<usize>,
+    /// `--warmup N`: runs before the measured ones, not counted
+    pub(crate) warmup: usize,
+    /// `--prepare CMD`: run before every run, not timed
+    pub(crate) prepare: Option<String>,
+    pub(crate) export_json: Option<String>,
+    pub(crate) export_markdown: Option<String>,
+}
+
+impl Options {
+    /// Whether any option asks for repeated runs and statistics
+    fn benchmark(&self) -> bool {
+        self.runs.is_some()
+            || self.warmup > 0
+            || self.prepare.is_some()
+            || self.export_json.is_some()
+            || self.export_markdown.is_some()
+    }
+}
+
+/// What a timed command took
+pub(crate) struct Usage {
+    pub(crate) real: Duration,
+    pub(crate) user: Duration,
+    pub(crate) sys: Duration,
+    /// Bytes
+    max_rss: u64,
+    minor_faults: u64,
+    major_faults: u64,
+    voluntary_switches: u64,
+    involuntary_switches: u64,
+}
+
+fn error(message: String) {
+    eprintln!("{}", format!("time: {}", message).red());
+}
+
+/// How many values follow `word` when it is an option of `time`, `None` when it is not
+pub fn option_arity(word: &str) -> Option<usize> {
+    match word {
+        "-p" | "--json" | "--csv" | "--plain" | "--" => Some(0),
+        "--runs" | "--warmup" | "--prepare" | "--export-json" | "--export-markdown" => Some(1),
+        _ => None,
+    }
+}
+
+/// Reads the options at the start of `args`, returning them and how many words they took
+fn parse_options(args: &[String]) -> Result<(Options, usize), String> {
+    let mut options = Options {
+        posix: false,
+        format: Format::Pretty,
+        runs: None,
+        warmup: 0,
+        prepare: None,
+        export_json: None,
+        export_markdown: None,
+    };
+    let mut taken = 0;
+    while let Some(arg) = args.get(taken) {
+        let arg = arg.as_str();
+        if option_arity(arg) == Some(1) {
+            let value = args
+                .get(taken + 1)
+                .ok_or_else(|| format!("`{}` needs a value", arg))?;
+            let count = |least: usize| {
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n >= least)
+                    .ok_or_else(|| {
+                        format!(
+                            "`{}` needs a number of at least {}, not `{}`",
+                            arg, least, value
+                        )
+                    })
+            };
+            match arg {
+                "--runs" => options.runs = Some(count(1)?),
+                "--warmup" => options.warmup = count(0)?,
+                "--prepare" => options.prepare = Some(value.clone()),
+                "--export-json" => options.export_json = Some(value.clone()),
+                _ => options.export_markdown = Some(value.clone()),
+            }
+            taken += 2;
+            continue;
+        }
+        match arg {
+            "-p" => options.posix = true,
+            "--json" => options.format = Format::Json,
+            "--csv" => options.format = Format::Csv,
+            "--plain" => options.format = Format::Plain,
+            "--" => {
+                taken += 1;
+                break;
+            }
+            _ => break,
+        }
+        taken += 1;
+    }
+    Ok((options, taken))
+}
+
+/// The `time` keyword: runs a pipeline, builtins and functions included, and reports
+/// what it took
+pub fn run_time(options: &[Word], pipeline: &Node, source: &str) -> Flow {
+    let (options, _) = match parse_options(&expand_words(options)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            error(e);
+            return Flow::Next(2);
+        }
+    };
+    if options.benchmark() {
+        // Commands to compare are quoted words: `time --runs 5 'ls' 'ls -R'`
+        let commands = match quoted_commands(pipeline) {
+            Some(sources) => {
+                let mut commands = Vec::new();
+                for source in sources {
+                    match parse(&source) {
+                        Ok(node) => commands.push((source, node)),
+                        Err(e) => {
+                            error(format!("syntax error in `{}`: {}", source, e));
+                            return Flow::Next(2);
+                        }
+                    }
+                }
+                commands
+            }
+            None => vec![(source.to_string(), pipeline.clone())],
+        };
+        let runs: Vec<bench::Command> = commands
+            .iter()
+            .map(|(source, node)| {
+                let run: Box<dyn Fn() -> Flow> = Box::new(|| eval::run(node));
+                (source.clone(), run)
+            })
+            .collect();
+        return bench::benchmark(&options, &runs);
+    }
+    timed(&options, source, || eval::run(pipeline))
+}
+
+/// The words of a simple command that are each quoted as a whole, unquoted
+fn quoted_commands(pipeline: &Node) -> Option<Vec<String>> {
+    let Node::Simple(cmd) = pipeline else {
+        return None;
+    };
+    let quoted = |text: &str| {
+        text.len() >= 2
+            && (text.starts_with('\'') && text.ends_with('\'')
+                || text.starts_with('"') && text.ends_with('"'))
+    };
+    if !cmd.assigns.is_empty()
+        || !cmd.redirects.is_empty()
+        || !cmd.words.iter().all(|w| quoted(&w.text))
+    {
+        return None;
+    }
+    Some(expand_words(&cmd.words))
+}
+
+/// `time` run as a command, e.g. through an alias: times the rest of its arguments
+pub fn builtin_time(args: &[&str]) -> i32 {
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    let (options, taken) = match parse_options(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            error(e);
+            return 2;
+        }
+    };
+    let command = &args[taken..];
+    if command.is_empty() {
+        println!(
+            "{} {}",
+            "Usage:".bold().bright_red(),
+            "time [-p] [--runs N] [--warmup N] [--prepare CMD] [--json|--csv|--plain] <command> [args...]"
+                .bright_white()
+        );
+        return 2;
+    }
+    if options.benchmark() {
+        let run: Box<dyn Fn() -> Flow> = Box::new(|| eval::run_argv(command));
+        return bench::benchmark(&options, &[(command.join(" "), run)]).code();
+    }
+    timed(&options, &command.join(" "), || eval::run_argv(command)).code()
+}
+
+/// Counts a reaped child's peak memory towards every running `time`
+pub fn reaped(usage: &libc::rusage) {
+    let rss = usage.ru_maxrss.max(0) as u64 * 1024;
+    PEAKS.with(|peaks| {
+        for peak in peaks.borrow_mut().iter_mut() {
+            *peak = (*peak).max(rss);
+        }
+    });
+}
+
+fn rusage(who: libc::c_int) -> libc::rusage {
+    // SAFETY: `rusage` is plain data, for which all zeroes is valid.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    // SAFETY: `usage` is a valid place for the kernel to write to.
+    unsafe { libc::getrusage(who, &mut usage) };
+    usage
+}
+
+fn duration(tv: libc::timeval) -> Duration {
+    Duration::from_secs(tv.tv_sec.max(0) as u64) + Duration::from_micros(tv.tv_usec.max(0) as u64)
+}
+
+/// Runs `run` and reports what it took
+fn timed(options: &Options, source: &str, run: impl FnOnce() -> Flow) -> Flow {
+    let (flow, usage) = measure(run);
+    report(options, source, flow.code(), &usage);
+    flow
+}
+
+/// Runs `run`, returning how it went and what it took
+pub(crate) fn measure(run: impl FnOnce() -> Flow) -> (Flow, Usage) {
+    let _ = io::stdout().flush();
+    PEAKS.with(|peaks| peaks.borrow_mut().push(0));
+    let shell_before = rusage(libc::RUSAGE_SELF);
+    let children_before = rusage(libc::RUSAGE_CHILDREN);
+    let start = Instant::now();
+
+    let flow = run();
+
+    let real = start.elapsed();
+    // The shell's own usage counts for builtins, that of reaped children for the rest
+    let shell = rusage(libc::RUSAGE_SELF);
+    let children = rusage(libc::RUSAGE_CHILDREN);
+    let peak = PEAKS.with(|peaks| peaks.borrow_mut().pop()).unwrap_or(0);
+
+    let cpu = |f: fn(&libc::rusage) -> libc::timeval| {
+        (duration(f(&shell)) + duration(f(&children)))
+            .saturating_sub(duration(f(&shell_before)) + duration(f(&children_before)))
+    };
+    let count = |f: fn(&libc::rusage) -> libc::c_long| {
+        (f(&shell) + f(&children) - f(&shell_before) - f(&children_before)).max(0) as u64
+    };
+    let usage = Usage {
+        real,
+        user: cpu(|u| u.ru_utime),
+        sys: cpu(|u| u.ru_stime),
+        // Nothing was started: the shell's own peak is all there is
+        max_rss: if peak > 0 {
+            peak
+        } else {
+            shell.ru_maxrss.max(0) as u64 * 1024
+        },
+        minor_faults: count(|u| u.ru_minflt),
+        major_faults: count(|u| u.ru_majflt),
+        voluntary_switches: count(|u| u.ru_nvcsw),
+        involuntary_switches: count(|u| u.ru_nivcsw),
+    };
+    (flow, usage)
+}
+
+fn report(options: &Options, source: &str, code: i32, usage: &Usage) {
+    if options.format != Format::Pretty {
+        records::print_record(&record(source, code, usage), options.format);
+        return;
+    }
+    let _ = io::stdout().flush();
+    if options.posix {
+        eprintln!(
+            "real {:.2}\nuser {:.2}\nsys {:.2}",
+            usage.real.as_secs_f64(),
+            usage.user.as_secs_f64(),
+            usage.sys.as_secs_f64()
+        );
+        return;
+    }
+    if let Some(format) = state::get_var("TIMEFORMAT") {
+        if !format.is_empty() {
+            eprintln!("{}", timeformat(&format, code, usage));
+        }
+        return;
+    }
+
+    let label = |text: &str| format!("{:<18}", text).bold().bright_white();
+    eprintln!(
+        "~~~~+++~~~~\n{} {}",
+        "Execution time:".bold().bright_white(),
+        format_duration(usage.real).bold().cyan()
+    );
+    eprintln!(
+        "{}{}",
+        label("User CPU:"),
+        format_duration(usage.user).yellow()
+    );
+    eprintln!(
+        "{}{}",
+        label("System CPU:"),
+        format_duration(usage.sys).yellow()
+    );
+    eprintln!(
+        "{}{}",
+        label("CPU usage:"),
+        cpu_percent(usage).map_or("too short to measure".dimmed(), |percent| {
+            format!("{:.0}%", percent).green()
+        })
+    );
+    eprintln!(
+        "{}{}",
+        label("Max memory:"),
+        format_size(usage.max_rss).magenta()
+    );
+    eprintln!(
+        "{}{} minor, {} major",
+        label("Page faults:"),
+        usage.minor_faults.to_string().blue(),
+        usage.major_faults.to_string().blue()
+    );
+    eprintln!(
+        "{}{} voluntary, {} involuntary",
+        label("Context switches:"),
+        usage.voluntary_switches.to_string().blue(),
+        usage.involuntary_switches.to_string().blue()
+    );
+}
+
+fn record(source: &str, code: i32, usage: &Usage) -> Record {
+    let seconds = |d: Duration| (d.as_secs_f64() * 1000.0).round() / 1000.0;
+    Record::new(vec![
+        ("command".to_string(), Field::Text(source.to_string())),
+        ("status".to_string(), Field::Number(code.into())),
+        ("real".to_string(), Field::Number(seconds(usage.real))),
+        ("user".to_string(), Field::Number(seconds(usage.user))),
+        ("sys".to_string(), Field::Number(seconds(usage.sys))),
+        (
+            "cpu".to_string(),
+            Field::Number((cpu_percent(usage).unwrap_or(0.0) * 10.0).round() / 10.0),
+        ),
+        ("max_rss".to_string(), Field::Size(usage.max_rss)),
+        (
+            "minor_faults".to_string(),
+            Field::Number(usage.minor_faults as f64),
+        ),
+        (
+            "major_faults".to_string(),
+            Field::Number(usage.major_faults as f64),
+        ),
+        (
+            "voluntary_switches".to_string(),
+            Field::Number(usage.voluntary_switches as f64),
+        ),
+        (
+            "involuntary_switches".to_string(),
+            Field::Number(usage.involuntary_switches as f64),
+        ),
+    ])
+}
+
+/// CPU time as a share of the wall-clock time; above 100 when several cores worked.
+/// `None` when the command finished within one step of the CPU clock, which then
+/// cannot tell how much of that time was spent working.
+fn cpu_percent(usage: &Usage) -> Option<f64> {
+    if usage.real < CPU_RESOLUTION {
+        return None;
+    }
+    Some((usage.user + usage.sys).as_secs_f64() / usage.real.as_secs_f64() * 100.0)
+}
+
+/// `1h 2m 3.456s`, `3.456s`, `12.345ms` or `87µs`
+pub(crate) fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let fraction = (secs % 60) as f64 + f64::from(d.subsec_nanos()) / 1e9;
+    if secs >= 3600 {
+        format!("{}h {}m {:.3}s", secs / 3600, secs % 3600 / 60, fraction)
+    } else if secs >= 60 {
+        format!("{}m {:.3}s", secs / 60, fraction)
+    } else if secs >= 1 {
+        format!("{:.3}s", fraction)
+    } else if d.as_micros() >= 1000 {
+        format!("{:.3}ms", d.as_secs_f64() * 1000.0)
+    } else {
+        format!("{}µs", d.as_micros())
+    }
+}
+
+/// Expands a bash-style `TIMEFORMAT`: `%[p][l]R`, `%[p][l]U` and `%[p][l]S` for the
+/// real, user and system seconds with `p` decimals (3 by default, `l` for `MmS.FFFs`),
+/// `%[p]P` for the CPU percentage and `%%`; also `%M` for the peak memory in KB,
+/// `%f`/`%F` for minor/major page faults, `%w`/`%c` for voluntary/involuntary context
+/// switches and `%x` for the exit status
+fn timeformat(format: &str, code: i32, usage: &Usage) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let precision = chars
+            .next_if(char::is_ascii_digit)
+            .and_then(|d| d.to_digit(10))
+            .map(|d| d.min(3) as usize);
+        let long = chars.next_if_eq(&'l').is_some();
+        let seconds = |d: Duration| {
+            let precision = precision.unwrap_or(3);
+            if long {
+                let secs = d.as_secs_f64();
+                let minutes = (secs / 60.0).floor();
+                format!("{}m{:.*}s", minutes, precision, secs - minutes * 60.0)
+            } else {
+                format!("{:.*}", precision, d.as_secs_f64())
+            }
+        };
+        match chars.next() {
+            Some('R') => out.push_str(&seconds(usage.real)),
+            Some('U') => out.push_str(&seconds(usage.user)),
+            Some('S') => out.push_str(&seconds(usage.sys)),
+            Some('P') => out.push_str(&format!(
+                "{:.*}",
+                precision.unwrap_or(2),
+                cpu_percent(usage).unwrap_or(0.0)
+            )),
+            Some('M') => out.push_str(&(usage.max_rss / 1024).to_string()),
+            Some('f') => out.push_str(&usage.minor_faults.to_string()),
+            Some('F') => out.push_str(&usage.major_faults.to_string()),
+            Some('w') => out.push_str(&usage.voluntary_switches.to_string()),
+            Some('c') => out.push_str(&usage.involuntary_switches.to_string()),
+            Some('x') => out.push_str(&code.to_string()),
+            Some('%') => out.push('%'),
+            // Anything else is written as it stands
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(real_ms: u64, cpu_ms: u64) -> Usage {
+        Usage {
+            real: Duration::from_millis(real_ms),
+            user: Duration::from_millis(cpu_ms),
+            sys: Duration::ZERO,
+            max_rss: 0,
+            minor_faults: 0,
+            major_faults: 0,
+            voluntary_switches: 0,
+            involuntary_switches: 0,
+        }
+    }
+
+    #[test]
+    fn cpu_percent_is_a_share_of_real_time() {
+        assert_eq!(cpu_percent(&usage(1000, 500)), Some(50.0));
+        assert_eq!(cpu_percent(&usage(1000, 3000)), Some(300.0));
+    }
+
+    #[test]
+    fn commands_shorter_than_a_tick_have_no_cpu_percent() {
+        assert_eq!(cpu_percent(&usage(0, 0)), None);
+        // 4ms of CPU counted against 1ms of wall clock, as ticks can round it
+        assert_eq!(cpu_percent(&usage(1, 4)), None);
+        assert_eq!(timeformat("%P", 0, &usage(1, 4)), "0.00");
+    }
+}
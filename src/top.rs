@@ -0,0 +1,672 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use colored::{ColoredString, Colorize};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
+    enable_raw_mode,
+};
+use crossterm::{execute, queue};
+use sysinfo::{
+    Disks, MINIMUM_CPU_UPDATE_INTERVAL, Networks, Pid, ProcessRefreshKind, ProcessesToUpdate,
+    System, UpdateKind, Users,
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::ls::format_size;
+
+const USAGE: &str = "usage: top [-d SECONDS] [-s cpu|mem|pid|name] [-f TEXT]";
+
+/// Lines other than the core grid above the process table: the title, memory, swap,
+/// I/O and the table header
+const FIXED_LINES: usize = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Sort {
+    Cpu,
+    Memory,
+    Pid,
+    Name,
+}
+
+impl Sort {
+    fn parse(name: &str) -> Option<Sort> {
+        match name {
+            "cpu" => Some(Sort::Cpu),
+            "mem" | "memory" => Some(Sort::Memory),
+            "pid" => Some(Sort::Pid),
+            "name" | "command" => Some(Sort::Name),
+            _ => None,
+        }
+    }
+}
+
+/// What keys do at the moment
+enum Mode {
+    Normal,
+    /// Typing into the filter
+    Filter,
+    /// Waiting for `y` before sending the signal
+    Confirm(Pid, libc::c_int),
+}
+
+/// One line of the process table
+struct Row {
+    pid: Pid,
+    user: String,
+    cpu: f32,
+    memory: u64,
+    name: String,
+    command: String,
+}
+
+struct Dashboard {
+    sys: System,
+    disks: Disks,
+    networks: Networks,
+    users: Users,
+    interval: Duration,
+    sort: Sort,
+    reverse: bool,
+    filter: String,
+    mode: Mode,
+    rows: Vec<Row>,
+    /// Followed across refreshes, so the selection stays on the same process
+    selected: Option<Pid>,
+    scroll: usize,
+    message: Option<ColoredString>,
+    last_refresh: Instant,
+    /// Bytes per second since the previous refresh
+    disk_read: u64,
+    disk_written: u64,
+    received: u64,
+    transmitted: u64,
+}
+
+/// Puts the terminal back however the dashboard ends
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        enable_raw_mode()?;
+        let screen = Screen;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+fn error(message: String) {
+    eprintln!("{}", format!("top: {}", message).red());
+}
+
+/// The `top` builtin (and `info --watch`): a live view of the CPUs, memory, I/O and
+/// processes until `q` or Ctrl-C
+pub fn builtin_top(args: &[&str]) -> i32 {
+    let mut interval = Duration::from_secs(1);
+    let mut sort = Sort::Cpu;
+    let mut filter = String::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .copied()
+                .ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE))
+        };
+        let parsed = match arg {
+            "-d" | "--delay" => value().and_then(|v| {
+                v.parse::<f64>()
+                    .ok()
+                    .filter(|s| s.is_finite() && *s >= 0.1)
+                    .map(|s| interval = Duration::from_secs_f64(s))
+                    .ok_or_else(|| format!("invalid delay `{}`", v))
+            }),
+            "-s" | "--sort" => value().and_then(|v| {
+                Sort::parse(v)
+                    .map(|s| sort = s)
+                    .ok_or_else(|| format!("cannot sort by `{}`\n{}", v, USAGE))
+            }),
+            "-f" | "--filter" => value().map(|v| filter = v.to_string()),
+            _ => Err(format!("unknown option `{}`\n{}", arg, USAGE)),
+        };
+        if let Err(e) = parsed {
+            error(e);
+            return 2;
+        }
+    }
+    // SAFETY: isatty only inspects the descriptor.
+    if unsafe { libc::isatty(0) != 1 || libc::isatty(1) != 1 } {
+        error("needs a terminal".into());
+        return 1;
+    }
+
+    let mut dashboard = Dashboard {
+        sys: System::new(),
+        disks: Disks::new_with_refreshed_list(),
+        networks: Networks::new_with_refreshed_list(),
+        users: Users::new_with_refreshed_list(),
+        interval,
+        sort,
+        reverse: false,
+        filter,
+        mode: Mode::Normal,
+        rows: Vec::new(),
+        selected: None,
+        scroll: 0,
+        message: None,
+        last_refresh: Instant::now(),
+        disk_read: 0,
+        disk_written: 0,
+        received: 0,
+        transmitted: 0,
+    };
+    // CPU usage is the difference between two readings
+    dashboard.refresh();
+    thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+    dashboard.refresh();
+
+    let screen = match Screen::enter() {
+        Ok(screen) => screen,
+        Err(e) => {
+            error(e.to_string());
+            return 1;
+        }
+    };
+    let result = dashboard.run();
+    drop(screen);
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            error(e.to_string());
+            1
+        }
+    }
+}
+
+impl Dashboard {
+    fn run(&mut self) -> io::Result<()> {
+        let mut next = Instant::now() + self.interval;
+        loop {
+            self.draw()?;
+            let now = Instant::now();
+            if now >= next {
+                self.refresh();
+                next = now + self.interval;
+                continue;
+            }
+            // Any other event, such as a resize, only redraws
+            if event::poll(next - now)?
+                && let Event::Key(key) = event::read()?
+                && key.kind != KeyEventKind::Release
+                && !self.key(key)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    fn refresh(&mut self) {
+        let elapsed = self.last_refresh.elapsed().as_secs_f64().max(0.001);
+        self.last_refresh = Instant::now();
+        self.sys.refresh_cpu_usage();
+        self.sys.refresh_memory();
+        self.sys.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing()
+                .with_cpu()
+                .with_memory()
+                .with_user(UpdateKind::OnlyIfNotSet)
+                .with_cmd(UpdateKind::OnlyIfNotSet),
+        );
+        self.disks.refresh(true);
+        self.networks.refresh(true);
+
+        // A device mounted in several places is counted once
+        let mut devices = HashSet::new();
+        let (mut read, mut written) = (0, 0);
+        for disk in self.disks.list() {
+            if devices.insert(disk.name().to_os_string()) {
+                read += disk.usage().read_bytes;
+                written += disk.usage().written_bytes;
+            }
+        }
+        let (received, transmitted) = self
+            .networks
+            .iter()
+            .filter(|(name, _)| *name != "lo")
+            .fold((0, 0), |(rx, tx), (_, data)| {
+                (rx + data.received(), tx + data.transmitted())
+            });
+        let rate = |bytes: u64| (bytes as f64 / elapsed) as u64;
+        self.disk_read = rate(read);
+        self.disk_written = rate(written);
+        self.received = rate(received);
+        self.transmitted = rate(transmitted);
+        self.update_rows();
+    }
+
+    /// The process table, filtered and sorted; also after the sort or filter changes
+    fn update_rows(&mut self) {
+        let filter = self.filter.to_lowercase();
+        let mut rows: Vec<Row> = self
+            .sys
+            .processes()
+            .values()
+            // Threads are shown through their process
+            .filter(|p| p.thread_kind().is_none())
+            .map(|p| {
+                let name = p.name().to_string_lossy().into_owned();
+                let command = p
+                    .cmd()
+                    .iter()
+                    .map(|a| a.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                Row {
+                    pid: p.pid(),
+                    user: p
+                        .user_id()
+                        .and_then(|uid| self.users.get_user_by_id(uid))
+                        .map_or_else(|| "?".into(), |u| u.name().to_string()),
+                    cpu: p.cpu_usage(),
+                    memory: p.memory(),
+                    command: if command.is_empty() {
+                        format!("[{}]", name)
+                    } else {
+                        command
+                    },
+                    name,
+                }
+            })
+            .filter(|row| {
+                filter.is_empty()
+                    || row.name.to_lowercase().contains(&filter)
+                    || row.command.to_lowercase().contains(&filter)
+                    || row.user.to_lowercase() == filter
+            })
+            .collect();
+        match self.sort {
+            Sort::Cpu => rows.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(a.pid.cmp(&b.pid))),
+            Sort::Memory => rows.sort_by(|a, b| b.memory.cmp(&a.memory).then(a.pid.cmp(&b.pid))),
+            Sort::Pid => rows.sort_by_key(|r| r.pid),
+            Sort::Name => rows.sort_by(|a, b| {
+                a.name
+                    .to_lowercase()
+                    .cmp(&b.name.to_lowercase())
+                    .then(a.pid.cmp(&b.pid))
+            }),
+        }
+        if self.reverse {
+            rows.reverse();
+        }
+        self.rows = rows;
+        if !self
+            .selected
+            .is_some_and(|pid| self.rows.iter().any(|r| r.pid == pid))
+        {
+            self.selected = self.rows.first().map(|r| r.pid);
+        }
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected
+            .and_then(|pid| self.rows.iter().position(|r| r.pid == pid))
+            .unwrap_or(0)
+    }
+
+    fn select(&mut self, index: usize) {
+        let index = index.min(self.rows.len().saturating_sub(1));
+        self.selected = self.rows.get(index).map(|r| r.pid);
+    }
+
+    /// Handles a key; `false` means quit
+    fn key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        match self.mode {
+            Mode::Filter => {
+                match key.code {
+                    KeyCode::Enter => self.mode = Mode::Normal,
+                    KeyCode::Esc => {
+                        self.filter.clear();
+                        self.mode = Mode::Normal;
+                    }
+                    KeyCode::Backspace => {
+                        self.filter.pop();
+                    }
+                    KeyCode::Char(c) => self.filter.push(c),
+                    _ => return true,
+                }
+                self.update_rows();
+            }
+            Mode::Confirm(pid, signal) => {
+                if key.code == KeyCode::Char('y') {
+                    self.message = Some(send_signal(pid, signal));
+                } else {
+                    self.message = None;
+                }
+                self.mode = Mode::Normal;
+            }
+            Mode::Normal => {
+                self.message = None;
+                let page = self.table_height().max(1);
+                let index = self.selected_index();
+                match key.code {
+                    KeyCode::Char('q') => return false,
+                    KeyCode::Esc if self.filter.is_empty() => return false,
+                    KeyCode::Esc => {
+                        self.filter.clear();
+                        self.update_rows();
+                    }
+                    KeyCode::Up => self.select(index.saturating_sub(1)),
+                    KeyCode::Down => self.select(index + 1),
+                    KeyCode::PageUp => self.select(index.saturating_sub(page)),
+                    KeyCode::PageDown => self.select(index + page),
+                    KeyCode::Home => self.select(0),
+                    KeyCode::End => self.select(usize::MAX),
+                    KeyCode::Char('/') => self.mode = Mode::Filter,
+                    KeyCode::Char('r') => {
+                        self.reverse = !self.reverse;
+                        self.update_rows();
+                    }
+                    KeyCode::Char(c @ ('c' | 'm' | 'p' | 'n')) => {
+                        let sort = match c {
+                            'c' => Sort::Cpu,
+                            'm' => Sort::Memory,
+                            'p' => Sort::Pid,
+                            _ => Sort::Name,
+                        };
+                        // Choosing the current order again turns it around
+                        self.reverse = sort == self.sort && !self.reverse;
+                        self.sort = sort;
+                        self.update_rows();
+                    }
+                    KeyCode::Char(c @ ('k' | 'K')) => {
+                        if let Some(pid) = self.selected {
+                            let signal = if c == 'k' {
+                                libc::SIGTERM
+                            } else {
+                                libc::SIGKILL
+                            };
+                            self.mode = Mode::Confirm(pid, signal);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        true
+    }
+
+    fn table_height(&self) -> usize {
+        let (_, height) = terminal::size().unwrap_or((80, 24));
+        let cores = self.sys.cpus().len();
+        let columns = self.core_columns();
+        // The footer is the one line below the table
+        (height as usize).saturating_sub(cores.div_ceil(columns) + FIXED_LINES + 1)
+    }
+
+    fn core_columns(&self) -> usize {
+        let (width, _) = terminal::size().unwrap_or((80, 24));
+        (width as usize / 30).clamp(1, self.sys.cpus().len().max(1))
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let mut lines: Vec<String> = Vec::new();
+
+        let load = System::load_average();
+        let title = format!(
+            "xinux top  {}  up {}  load {:.2} {:.2} {:.2}  CPU {:.0}%  {} processes",
+            System::host_name().unwrap_or_default(),
+            humantime::format_duration(Duration::from_secs(System::uptime())),
+            load.one,
+            load.five,
+            load.fifteen,
+            self.sys.global_cpu_usage(),
+            self.rows.len(),
+        );
+        lines.push(truncate(&title, width).bold().to_string());
+
+        let columns = self.core_columns();
+        let cell = width / columns;
+        let cpus = self.sys.cpus();
+        let label_width = cpus.len().saturating_sub(1).to_string().len() + 4;
+        for (row, cpus) in cpus.chunks(columns).enumerate() {
+            let line: String = cpus
+                .iter()
+                .enumerate()
+                .map(|(i, cpu)| {
+                    let label = format!("{:<label_width$}", format!("CPU{}", row * columns + i));
+                    let text = format!("{:.1}%", cpu.cpu_usage());
+                    let meter = bar(
+                        cpu.cpu_usage() as f64 / 100.0,
+                        cell.saturating_sub(label_width + 3),
+                        &text,
+                    );
+                    format!("{}[{}] ", label.cyan(), meter)
+                })
+                .collect();
+            lines.push(line);
+        }
+
+        let memory = format!(
+            "{}/{}",
+            format_size(self.sys.used_memory()),
+            format_size(self.sys.total_memory())
+        );
+        lines.push(format!(
+            "{}[{}]",
+            "Mem  ".cyan(),
+            bar(
+                fraction(self.sys.used_memory(), self.sys.total_memory()),
+                width.saturating_sub(7),
+                &memory
+            )
+        ));
+        let swap = if self.sys.total_swap() == 0 {
+            "Disabled".to_string()
+        } else {
+            format!(
+                "{}/{}",
+                format_size(self.sys.used_swap()),
+                format_size(self.sys.total_swap())
+            )
+        };
+        lines.push(format!(
+            "{}[{}]",
+            "Swap ".cyan(),
+            bar(
+                fraction(self.sys.used_swap(), self.sys.total_swap()),
+                width.saturating_sub(7),
+                &swap
+            )
+        ));
+        let io_line = format!(
+            "Disk  read {}/s  write {}/s    Net  down {}/s  up {}/s",
+            format_size(self.disk_read),
+            format_size(self.disk_written),
+            format_size(self.received),
+            format_size(self.transmitted)
+        );
+        lines.push(truncate(&io_line, width));
+
+        let arrow = if self.reverse { "▲" } else { "▼" };
+        let mark = |sort: Sort| if self.sort == sort { arrow } else { " " };
+        let header = format!(
+            "{:>7}{} {:<10} {:>6}{} {:>5} {:>10}{}  COMMAND{}",
+            "PID",
+            mark(Sort::Pid),
+            "USER",
+            "CPU%",
+            mark(Sort::Cpu),
+            "MEM%",
+            "MEM",
+            mark(Sort::Memory),
+            mark(Sort::Name),
+        );
+        lines.push(pad(&truncate(&header, width), width).reversed().to_string());
+
+        let table_height = height.saturating_sub(lines.len() + 1);
+        let selected = self.selected_index();
+        if selected < self.scroll {
+            self.scroll = selected;
+        } else if selected >= self.scroll + table_height {
+            self.scroll = selected + 1 - table_height.max(1);
+        }
+        self.scroll = self
+            .scroll
+            .min(self.rows.len().saturating_sub(table_height));
+        let total_memory = self.sys.total_memory().max(1);
+        for (i, row) in self
+            .rows
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(table_height)
+        {
+            let text = format!(
+                "{:>7}  {:<10} {:>6.1}  {:>5.1} {:>10}   {}",
+                row.pid.as_u32(),
+                truncate(&row.user, 10),
+                row.cpu,
+                row.memory as f64 * 100.0 / total_memory as f64,
+                format_size(row.memory),
+                row.command
+            );
+            let text = truncate(&text, width);
+            lines.push(if i == selected {
+                pad(&text, width).black().on_bright_cyan().to_string()
+            } else if row.cpu >= 50.0 {
+                text.bright_red().to_string()
+            } else {
+                text
+            });
+        }
+        while lines.len() < height.saturating_sub(1) {
+            lines.push(String::new());
+        }
+        lines.truncate(height.saturating_sub(1));
+        lines.push(self.footer(width));
+
+        let mut out = io::stdout().lock();
+        for (y, line) in lines.iter().enumerate() {
+            queue!(
+                out,
+                MoveTo(0, y as u16),
+                crossterm::style::Print(line),
+                Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        out.flush()
+    }
+
+    fn footer(&self, width: usize) -> String {
+        match self.mode {
+            Mode::Filter => format!("{} {}█", "filter:".bold(), self.filter),
+            Mode::Confirm(pid, signal) => {
+                let name = self
+                    .rows
+                    .iter()
+                    .find(|r| r.pid == pid)
+                    .map_or("", |r| r.name.as_str());
+                let signal = if signal == libc::SIGKILL {
+                    "SIGKILL"
+                } else {
+                    "SIGTERM"
+                };
+                format!("Send {} to {} ({})? [y/N]", signal, pid, name)
+                    .bright_yellow()
+                    .bold()
+                    .to_string()
+            }
+            Mode::Normal => {
+                if let Some(message) = &self.message {
+                    return message.to_string();
+                }
+                let filter = if self.filter.is_empty() {
+                    String::new()
+                } else {
+                    format!("  filter: {}", self.filter)
+                };
+                let keys = format!(
+                    "q quit  ↑↓ select  c/m/p/n sort  r reverse  / filter  k kill  K kill -9{}",
+                    filter
+                );
+                truncate(&keys, width).dimmed().to_string()
+            }
+        }
+    }
+}
+
+fn send_signal(pid: Pid, signal: libc::c_int) -> ColoredString {
+    // SAFETY: kill only sends a signal; a stale pid makes it fail with ESRCH.
+    if unsafe { libc::kill(pid.as_u32() as libc::pid_t, signal) } == 0 {
+        let name = if signal == libc::SIGKILL {
+            "SIGKILL"
+        } else {
+            "SIGTERM"
+        };
+        format!("Sent {} to {}", name, pid).bright_green()
+    } else {
+        format!("kill {}: {}", pid, io::Error::last_os_error()).red()
+    }
+}
+
+fn fraction(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        used as f64 / total as f64
+    }
+}
+
+/// A meter `width` columns wide, filled to `fraction`, with `text` at its right end
+fn bar(fraction: f64, width: usize, text: &str) -> String {
+    let text_width = text.width().min(width);
+    let room = width - text_width;
+    let filled = ((fraction.clamp(0.0, 1.0) * width as f64).round() as usize).min(room);
+    let meter = "|".repeat(filled);
+    let meter = if fraction >= 0.9 {
+        meter.bright_red()
+    } else if fraction >= 0.6 {
+        meter.bright_yellow()
+    } else {
+        meter.bright_green()
+    };
+    format!(
+        "{}{}{}",
+        meter,
+        " ".repeat(room - filled),
+        truncate(text, text_width).dimmed()
+    )
+}
+
+/// `text` cut to `width` columns
+fn truncate(text: &str, width: usize) -> String {
+    let mut used = 0;
+    text.chars()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= width
+        })
+        .collect()
+}
+
+/// `text` filled out to `width` columns, so a highlight spans the line
+fn pad(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+}
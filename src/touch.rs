@@ -0,0 +1,390 @@
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use colored::Colorize;
+
+use crate::filetypes;
+use crate::records;
+
+const USAGE: &str = "usage: touch [-acmhp] [-d DATE] [-r FILE] [--template] [--] file...";
+
+/// Which timestamps to set, and to what
+struct Options {
+    no_create: bool,
+    access: bool,
+    modify: bool,
+    parents: bool,
+    template: bool,
+    /// `-h`: change a symbolic link itself, not the file it points to
+    no_dereference: bool,
+    /// From `-d` or `-r`; the current time when neither is given
+    time: Option<libc::timespec>,
+    files: Vec<String>,
+}
+
+fn error(message: String) {
+    eprintln!("{}", format!("touch: {}", message).red());
+}
+
+/// The `touch` builtin: creates files and updates their times like coreutils `touch`
+pub fn builtin_touch(args: &[&str]) -> i32 {
+    let opts = match parse_options(args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            error(e);
+            return 2;
+        }
+    };
+    let mut status = 0;
+    for file in &opts.files {
+        if let Err(e) = touch(file, &opts) {
+            error(format!("cannot touch '{}': {}", file, e));
+            status = 1;
+        }
+    }
+    status
+}
+
+fn parse_options(args: &[&str]) -> Result<Options, String> {
+    let mut opts = Options {
+        no_create: false,
+        access: false,
+        modify: false,
+        parents: false,
+        template: false,
+        no_dereference: false,
+        time: None,
+        files: Vec::new(),
+    };
+    // Read once all flags are known, since `-h` decides whether it follows a link
+    let mut reference = None;
+    let mut args = args.iter();
+    let value = |flag: &str, args: &mut std::slice::Iter<&str>| {
+        args.next()
+            .map(|v| v.to_string())
+            .ok_or_else(|| format!("{} needs a value\n{}", flag, USAGE))
+    };
+    while let Some(&arg) = args.next() {
+        if arg == "--" {
+            opts.files.extend(args.by_ref().map(|a| a.to_string()));
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, v)) => (name, Some(v.to_string())),
+                None => (long, None),
+            };
+            match name {
+                "no-create" => opts.no_create = true,
+                "parents" => opts.parents = true,
+                "template" => opts.template = true,
+                "no-dereference" => opts.no_dereference = true,
+                "date" => {
+                    let date = inline.map_or_else(|| value(arg, &mut args), Ok)?;
+                    opts.time = Some(parse_date(&date)?);
+                    reference = None;
+                }
+                "reference" => {
+                    reference = Some(inline.map_or_else(|| value(arg, &mut args), Ok)?);
+                }
+                _ => return Err(format!("unknown option `{}`\n{}", arg, USAGE)),
+            }
+        } else if let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) {
+            for (i, flag) in flags.char_indices() {
+                match flag {
+                    'c' => opts.no_create = true,
+                    'a' => opts.access = true,
+                    'm' => opts.modify = true,
+                    'p' => opts.parents = true,
+                    'h' => opts.no_dereference = true,
+                    'd' | 'r' => {
+                        // The value is the rest of this word or the next argument
+                        let rest = &flags[i + 1..];
+                        let text = if rest.is_empty() {
+                            value(&format!("-{}", flag), &mut args)?
+                        } else {
+                            rest.to_string()
+                        };
+                        if flag == 'd' {
+                            opts.time = Some(parse_date(&text)?);
+                            reference = None;
+                        } else {
+                            reference = Some(text);
+                        }
+                        break;
+                    }
+                    _ => return Err(format!("unknown option `-{}`\n{}", flag, USAGE)),
+                }
+            }
+        } else {
+            opts.files.push(arg.to_string());
+        }
+    }
+    if let Some(file) = reference {
+        opts.time = Some(reference_time(&file, opts.no_dereference)?);
+    }
+    if opts.files.is_empty() {
+        return Err(format!("missing file operand\n{}", USAGE));
+    }
+    // Neither `-a` nor `-m` means both
+    if !opts.access && !opts.modify {
+        opts.access = true;
+        opts.modify = true;
+    }
+    Ok(opts)
+}
+
+/// A date for `-d`, in any form `records::parse_time` reads
+fn parse_date(text: &str) -> Result<libc::timespec, String> {
+    let time =
+        records::parse_time(text).ok_or_else(|| format!("invalid date `{}`", text.trim()))?;
+    Ok(libc::timespec {
+        tv_sec: time.timestamp(),
+        tv_nsec: time.timestamp_subsec_nanos().into(),
+    })
+}
+
+/// The modification time of `file`, for `-r`; of the link itself under `-h`
+fn reference_time(file: &str, no_dereference: bool) -> Result<libc::timespec, String> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = if no_dereference {
+        fs::symlink_metadata(file)
+    } else {
+        fs::metadata(file)
+    };
+    let metadata =
+        metadata.map_err(|e| format!("failed to get attributes of '{}': {}", file, e))?;
+    Ok(libc::timespec {
+        tv_sec: metadata.mtime(),
+        tv_nsec: metadata.mtime_nsec(),
+    })
+}
+
+fn touch(file: &str, opts: &Options) -> io::Result<()> {
+    let path = Path::new(file);
+    // A dangling link counts as missing, and touching it creates its target;
+    // `-h` never creates anything and lets utimensat report a missing file
+    let exists = if opts.no_dereference {
+        true
+    } else {
+        fs::metadata(path).is_ok()
+    };
+    if !exists {
+        if opts.no_create {
+            return Ok(());
+        }
+        if opts.parents
+            && let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let template = if opts.template {
+            templates_dir().and_then(|dir| find_template(&dir, path))
+        } else {
+            None
+        };
+        match &template {
+            Some(template) => create_from_template(path, template)?,
+            None => {
+                OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(path)?;
+            }
+        }
+        // A new file already has the current time
+        if opts.time.is_some() || !(opts.access && opts.modify) {
+            set_times(path, opts)?;
+        }
+        report_created(path, file, template.as_deref());
+        return Ok(());
+    }
+    set_times(path, opts)
+}
+
+fn set_times(path: &Path, opts: &Options) -> io::Result<()> {
+    let omit = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: libc::UTIME_OMIT,
+    };
+    let now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: libc::UTIME_NOW,
+    };
+    let time = opts.time.unwrap_or(now);
+    let times = [
+        if opts.access { time } else { omit },
+        if opts.modify { time } else { omit },
+    ];
+    let flags = if opts.no_dereference {
+        libc::AT_SYMLINK_NOFOLLOW
+    } else {
+        0
+    };
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path is NUL-terminated and `times` holds the two entries utimensat reads.
+    if unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), flags) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn templates_dir() -> Option<PathBuf> {
+    Some(dirs::home_dir()?.join(".config/xinux/templates"))
+}
+
+/// `templates/NAME` for the exact file name (`main.rs`, `Makefile`), else
+/// `templates/default.EXT` for its extension
+fn find_template(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let exact = dir.join(path.file_name()?);
+    if exact.is_file() {
+        return Some(exact);
+    }
+    let ext = path.extension()?.to_str()?;
+    Some(dir.join(format!("default.{}", ext))).filter(|p| p.is_file())
+}
+
+/// Copies the template, filling in `{{name}}`, `{{stem}}`, `{{date}}`, `{{year}}` and
+/// `{{user}}`; an executable template makes an executable file
+fn create_from_template(path: &Path, template: &Path) -> io::Result<()> {
+    let text = fs::read_to_string(template)?;
+    let now = Local::now();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let text = text
+        .replace("{{name}}", &name)
+        .replace("{{stem}}", &stem)
+        .replace("{{date}}", &now.format("%Y-%m-%d").to_string())
+        .replace("{{year}}", &now.format("%Y").to_string())
+        .replace("{{user}}", &whoami::username());
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)?;
+    io::Write::write_all(&mut file, text.as_bytes())?;
+    let mode = fs::metadata(template)?.permissions().mode();
+    if mode & 0o111 != 0 {
+        let current = file.metadata()?.permissions().mode();
+        file.set_permissions(fs::Permissions::from_mode(current | (mode & 0o111)))?;
+    }
+    Ok(())
+}
+
+fn report_created(path: &Path, file: &str, template: Option<&Path>) {
+    let name = path
+        .file_name()
+        .map_or(file.into(), |n| n.to_string_lossy());
+    // Files made from a template are recognized by their content
+    let kind = match fs::metadata(path) {
+        Ok(metadata) => filetypes::classify_content(path, &name, &metadata),
+        Err(_) => filetypes::classify(&name, None),
+    };
+    let from = template
+        .and_then(|t| t.file_name())
+        .map(|t| format!(" from {}", t.to_string_lossy()))
+        .unwrap_or_default();
+    println!(
+        "{} {} {} {} ({}){}",
+        "✔️ ".green().bold(),
+        "Created".bright_green(),
+        file.bright_white().underline(),
+        "➜".dimmed(),
+        format!("{} {}", kind.description, kind.icon).blue(),
+        from.dimmed()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_come_from_d_in_any_spelling() {
+        for args in [
+            &["-d", "@86400", "f"][..],
+            &["-d@86400", "f"],
+            &["--date", "@86400", "f"],
+            &["--date=@86400", "f"],
+            &["-cd", "1970-01-02 00:00:00+00:00", "f"],
+        ] {
+            let opts = parse_options(args).unwrap();
+            assert_eq!(opts.time.map(|t| t.tv_sec), Some(86400), "{:?}", args);
+            assert_eq!(opts.files, ["f"]);
+        }
+        assert!(parse_options(&["-d", "yesterday-ish", "f"]).is_err());
+        assert!(parse_options(&["f", "-d"]).is_err());
+    }
+
+    #[test]
+    fn flags_combine_and_default_to_both_times() {
+        let opts = parse_options(&["-cm", "--", "-a"]).unwrap();
+        assert!(opts.no_create && opts.modify && !opts.access);
+        assert_eq!(opts.files, ["-a"]);
+        let opts = parse_options(&["-hp", "x", "y"]).unwrap();
+        assert!(opts.no_dereference && opts.parents && opts.access && opts.modify);
+        assert!(parse_options(&["-x", "f"]).is_err());
+        assert!(parse_options(&["-c"]).is_err());
+    }
+
+    #[test]
+    fn references_are_read_once_h_is_known() {
+        let dir = std::env::temp_dir().join(format!("xinux-touch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file");
+        fs::write(&file, "").unwrap();
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        let link = link.to_str().unwrap();
+
+        let followed = parse_options(&["-r", link, "x"]).unwrap();
+        assert_eq!(followed.time.map(|t| t.tv_sec), Some(1_000_000));
+        let own = parse_options(&["-r", link, "-h", "x"]).unwrap();
+        assert_ne!(own.time.map(|t| t.tv_sec), Some(1_000_000));
+        // The last of `-d` and `-r` wins
+        let date = parse_options(&["--reference", link, "-d", "@5", "x"]).unwrap();
+        assert_eq!(date.time.map(|t| t.tv_sec), Some(5));
+        assert!(parse_options(&["-r", dir.join("missing").to_str().unwrap(), "x"]).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn templates_fill_in_the_file_name() {
+        let dir = std::env::temp_dir().join(format!("xinux-templates-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("default.py"), "# {{name}} ({{stem}}), {{year}}\n").unwrap();
+        fs::write(dir.join("Makefile"), "all:\n").unwrap();
+        fs::set_permissions(dir.join("default.py"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(
+            find_template(&dir, Path::new("src/Makefile")),
+            Some(dir.join("Makefile"))
+        );
+        assert_eq!(find_template(&dir, Path::new("x.rs")), None);
+        let template = find_template(&dir, Path::new("tool.py")).unwrap();
+        assert_eq!(template, dir.join("default.py"));
+
+        let new = dir.join("tool.py");
+        create_from_template(&new, &template).unwrap();
+        let year = Local::now().format("%Y").to_string();
+        assert_eq!(
+            fs::read_to_string(&new).unwrap(),
+            format!("# tool.py (tool), {}\n", year)
+        );
+        assert_ne!(fs::metadata(&new).unwrap().permissions().mode() & 0o111, 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}